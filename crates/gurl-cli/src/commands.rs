@@ -1,9 +1,19 @@
+use crate::status::{StatusPattern, UnexpectedStatus};
 use anyhow::{Context, Result};
-use clap::Args;
-use gurl_core::GurlClient;
-use gurl_core::client::{Body, GurlRequest};
+use clap::{Args, Subcommand};
+use gurl_core::cache::{CacheMode, HttpCache};
+use gurl_core::client::{
+    Auth, Backoff, Body, BodyStream, Cassette, FormPart, GurlRequest, HostTls, Matcher, Netrc,
+    OAuth2, PartValue, ProxyConfig, ResponseMeta, RetryCondition, RetryPolicy, SigV4,
+};
+use gurl_core::curl::{self, CurlCommand};
+use gurl_core::har::HarRecorder;
+use gurl_core::output::envelope::ErrorEnvelope;
+use gurl_core::session::{self, SessionStore};
+use gurl_core::{Config, GurlClient, GurlResponse};
 use reqwest::Method;
 use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use url::Url;
 
 #[derive(Args)]
@@ -20,11 +30,17 @@ pub struct HttpArgs {
     #[arg(long)]
     pub json: Option<String>,
 
-    /// Request body as raw string
+    /// Request body (repeatable, joined with '&'); "@file" or "@-" reads a file or
+    /// stdin and drops its newlines, as curl does
     #[arg(short = 'd', long = "data")]
-    pub data: Option<String>,
+    pub data: Vec<String>,
 
-    /// Form data (repeatable), format: "key=value"
+    /// Request body sent exactly as given; "@file" or "@-" streams a file or stdin
+    #[arg(long, value_name = "DATA", conflicts_with = "data")]
+    pub data_binary: Option<String>,
+
+    /// Form field (repeatable): "key=value", or "key=@path" to upload a file, with
+    /// optional ";type=..." and ";filename=..." (file fields make it multipart/form-data)
     #[arg(short = 'F', long = "form")]
     pub form: Vec<String>,
 
@@ -40,7 +56,26 @@ pub struct HttpArgs {
     #[arg(long)]
     pub no_redirect: bool,
 
-    /// Verbose output (include timing and TLS info)
+    /// Give up after following this many redirects (default 10)
+    #[arg(long, value_name = "N", conflicts_with = "no_redirect")]
+    pub max_redirects: Option<usize>,
+
+    /// Fail on HTTP errors (status 400 and up, or outside --expect-status): print an
+    /// error envelope instead of the response and exit 22 (4xx), 29 (5xx) or 24 (other)
+    #[arg(short = 'f', long, conflicts_with = "fail_with_body")]
+    pub fail: bool,
+
+    /// Like --fail, but print the response as usual
+    #[arg(long)]
+    pub fail_with_body: bool,
+
+    /// Statuses that count as success, e.g. "200,201" or "2xx,304"; the response is
+    /// printed and any other status exits as with --fail
+    #[arg(long, value_name = "STATUSES", value_delimiter = ',')]
+    pub expect_status: Vec<StatusPattern>,
+
+    /// Print where the time went (DNS, connect, TLS, first byte) and the TLS
+    /// session to stderr
     #[arg(short = 'v', long)]
     pub verbose: bool,
 
@@ -48,6 +83,184 @@ pub struct HttpArgs {
     #[arg(long)]
     pub timeout: Option<u64>,
 
+    /// Refuse response bodies larger than this, e.g. 500k, 20M or 1G (exit 63)
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    pub max_size: Option<u64>,
+
+    /// Retry failed requests up to N times
+    #[arg(long, value_name = "N")]
+    pub retry: Option<u32>,
+
+    /// Backoff strategy between retries: fixed, linear, exponential
+    #[arg(long, value_name = "STRATEGY", default_value = "exponential")]
+    pub retry_backoff: Backoff,
+
+    /// Base delay between retries in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 1.0)]
+    pub retry_delay: f64,
+
+    /// Conditions that trigger a retry (comma-separated): 5xx, 429, timeout, connection
+    #[arg(
+        long,
+        value_name = "CONDITIONS",
+        value_delimiter = ',',
+        default_value = "5xx,timeout,connection"
+    )]
+    pub retry_on: Vec<RetryCondition>,
+
+    /// Retry POST and PATCH after timeouts, connection errors and 5xx too (may repeat side effects)
+    #[arg(long)]
+    pub retry_all_methods: bool,
+
+    /// Use the persistent HTTP cache (honors Cache-Control, revalidates stale entries)
+    #[arg(long, conflicts_with = "no_cache")]
+    pub cache: bool,
+
+    /// Bypass the HTTP cache entirely
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Serve from the HTTP cache only, never touching the network
+    #[arg(long, conflicts_with = "no_cache")]
+    pub offline: bool,
+
+    /// Refuse URLs disallowed by robots.txt and honor its Crawl-delay
+    #[arg(long, conflicts_with = "ignore_robots")]
+    pub robots: bool,
+
+    /// Skip robots.txt checks even if enabled in the config file
+    #[arg(long)]
+    pub ignore_robots: bool,
+
+    /// Basic auth credentials, format: "user:password"
+    #[arg(
+        short = 'u',
+        long,
+        value_name = "USER:PASSWORD",
+        conflicts_with = "bearer"
+    )]
+    pub user: Option<String>,
+
+    /// Use HTTP Digest instead of Basic for --user (answers the server's 401 challenge)
+    #[arg(long, requires = "user")]
+    pub digest: bool,
+
+    /// Bearer token: the token itself, env:NAME to read an environment variable, or @FILE
+    #[arg(long, value_name = "TOKEN|env:NAME|@FILE")]
+    pub bearer: Option<String>,
+
+    /// Authenticate with an OAuth2 profile from the config file (tokens are cached until expiry)
+    #[arg(long, value_name = "PROFILE", conflicts_with_all = ["user", "bearer"])]
+    pub oauth: Option<String>,
+
+    /// Sign with AWS SigV4, format: "aws:amz[:REGION[:SERVICE]]" (inferred from *.amazonaws.com hosts)
+    #[arg(long, value_name = "PROVIDER", conflicts_with_all = ["user", "bearer", "oauth"])]
+    pub aws_sigv4: Option<String>,
+
+    /// AWS profile for SigV4 and s3:// URLs (default: $AWS_PROFILE, then the environment)
+    #[arg(long, value_name = "NAME")]
+    pub aws_profile: Option<String>,
+
+    /// Read credentials from this netrc file instead of $NETRC or ~/.netrc
+    #[arg(long, value_name = "FILE", conflicts_with = "no_netrc")]
+    pub netrc_file: Option<String>,
+
+    /// Don't look up credentials in a netrc file
+    #[arg(long)]
+    pub no_netrc: bool,
+
+    /// Trust the CA certificates in this PEM file instead of the built-in roots
+    #[arg(long, value_name = "FILE")]
+    pub cacert: Option<String>,
+
+    /// Client certificate for mutual TLS: PEM, or PKCS#12 (.p12, .pfx)
+    #[arg(short = 'E', long, value_name = "FILE")]
+    pub cert: Option<String>,
+
+    /// Private key for a PEM --cert, if it is not in the certificate file
+    #[arg(long, value_name = "FILE", requires = "cert")]
+    pub key: Option<String>,
+
+    /// Password for a PKCS#12 --cert: the password itself, env:NAME or @FILE
+    #[arg(long, value_name = "PASSWORD|env:NAME|@FILE", requires = "cert")]
+    pub cert_password: Option<String>,
+
+    /// Skip server certificate verification (the response carries a warning)
+    #[arg(short = 'k', long)]
+    pub insecure: bool,
+
+    /// Require one of these public key pins, format: "sha256//BASE64[;sha256//BASE64...]"
+    #[arg(long, value_name = "HASHES")]
+    pub pinnedpubkey: Option<String>,
+
+    /// Proxy URL: http://, https://, socks5:// or socks5h:// (proxy resolves DNS)
+    #[arg(short = 'x', long)]
+    pub proxy: Option<String>,
+
+    /// Proxy credentials, format: "user:password"
+    #[arg(long, value_name = "USER:PASSWORD")]
+    pub proxy_user: Option<String>,
+
+    /// Comma-separated hosts to reach without a proxy (overrides NO_PROXY)
+    #[arg(long, value_name = "HOSTS")]
+    pub noproxy: Option<String>,
+
+    /// Load cookies and default headers from a named session and save them back
+    #[arg(long, value_name = "NAME")]
+    pub session: Option<String>,
+
+    /// Record every request and response, redirects and retries included, into a
+    /// HAR archive (appended to if it exists; with --session, kept for the session)
+    #[arg(long, value_name = "FILE", conflicts_with = "no_har")]
+    pub har: Option<PathBuf>,
+
+    /// Stop recording: skip the session's HAR archive and forget it
+    #[arg(long)]
+    pub no_har: bool,
+
+    /// Write HAR response bodies to a <name>-bodies directory next to the archive
+    /// instead of inline
+    #[arg(long, requires = "har")]
+    pub har_sidecar: bool,
+
+    /// Headers whose values are redacted in the HAR archive, replacing the default
+    /// authorization, proxy-authorization, cookie and set-cookie ('' for none)
+    #[arg(long, value_name = "HEADERS", value_delimiter = ',', requires = "har")]
+    pub har_redact: Option<Vec<String>>,
+
+    /// Record every request and its response into a cassette directory (added to
+    /// if it exists), for replaying later with --replay
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer requests from a cassette recorded with --record, without touching
+    /// the network; a request that was not recorded is an error
+    #[arg(long, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+
+    /// What a request must share with a recorded one (comma-separated): method,
+    /// url, body
+    #[arg(
+        long = "match",
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        value_parser = ["method", "url", "body"],
+        default_value = "method,url"
+    )]
+    pub match_on: Vec<String>,
+
+    /// Also match recorded requests on this header's value (repeatable)
+    #[arg(long, value_name = "NAME")]
+    pub match_header: Vec<String>,
+
+    /// Send cookies from a Netscape cookies.txt file (e.g. exported from a browser)
+    #[arg(long, value_name = "FILE")]
+    pub cookies: Option<String>,
+
+    /// Write cookies back to the --cookies file after the request, readable only by you
+    #[arg(long, requires = "cookies")]
+    pub save_cookies: bool,
+
     /// Save response body to file
     #[arg(short = 'o', long = "output")]
     pub output: Option<String>,
@@ -55,6 +268,59 @@ pub struct HttpArgs {
     /// Extract a field from the JSON envelope using dot notation (e.g. "content.body", "response.status")
     #[arg(long = "select")]
     pub select: Option<String>,
+
+    /// Print the equivalent curl command instead of sending the request
+    #[arg(long)]
+    pub to_curl: bool,
+}
+
+#[derive(Args)]
+pub struct FromCurlArgs {
+    /// The curl command as one argument, or "-" to read it from stdin
+    pub command: String,
+
+    /// gurl options, applied on top of the curl command's
+    #[command(flatten)]
+    pub http: HttpArgs,
+}
+
+/// Resolve a secret given as `env:NAME`, `@FILE` or the literal value, so
+/// tokens need not appear in shell history.
+fn read_secret(value: &str) -> Result<String> {
+    if let Some(name) = value.strip_prefix("env:") {
+        std::env::var(name).with_context(|| format!("environment variable {name} is not set"))
+    } else if let Some(path) = value.strip_prefix('@') {
+        let secret =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+        Ok(secret.trim().to_string())
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// Region and service from curl's `--aws-sigv4 "aws:amz:REGION:SERVICE"`,
+/// filling in what is left out from an `<service>.<region>.amazonaws.com` host.
+fn sigv4_scope(spec: &str, url: &Url) -> Result<(Option<String>, String)> {
+    let mut parts = spec.split(':').skip(2).filter(|part| !part.is_empty());
+    let (region, service) = (parts.next(), parts.next());
+    let labels: Vec<&str> = url
+        .host_str()
+        .and_then(|host| host.strip_suffix(".amazonaws.com"))
+        .map(|host| host.split('.').collect())
+        .unwrap_or_default();
+    let (host_service, host_region) = match labels[..] {
+        [] => (None, None),
+        // Regions look like us-east-1; legacy global hosts have none
+        [.., service, region] if region.contains('-') => (Some(service), Some(region)),
+        [.., service] => (Some(service), None),
+    };
+    let service = service.or(host_service).with_context(|| {
+        format!("--aws-sigv4 {spec}: add the service, e.g. aws:amz:us-east-1:execute-api")
+    })?;
+    Ok((
+        region.or(host_region).map(str::to_string),
+        service.to_string(),
+    ))
 }
 
 pub async fn execute(method: &str, args: HttpArgs) -> Result<()> {
@@ -64,15 +330,150 @@ pub async fn execute(method: &str, args: HttpArgs) -> Result<()> {
         .parse()
         .with_context(|| format!("invalid HTTP method: {method}"))?;
 
-    let mut req = GurlRequest::get(url).with_method(method);
+    send(GurlRequest::get(url).with_method(method), args).await
+}
+
+/// Run a curl command line. gurl options given with it apply on top, and
+/// curl's -k and -b FILE become --insecure and --cookies.
+pub async fn from_curl(args: FromCurlArgs) -> Result<()> {
+    let FromCurlArgs {
+        command,
+        http: mut args,
+    } = args;
+    if !args.url.is_empty() {
+        anyhow::bail!(
+            "unexpected argument '{}'; quote the curl command as one argument",
+            args.url
+        );
+    }
+    let command = match command.as_str() {
+        "-" => io::read_to_string(io::stdin()).context("failed to read stdin")?,
+        _ => command,
+    };
+    let curl = CurlCommand::parse(&command)?;
+    args.insecure |= curl.insecure;
+    if args.cookies.is_none() {
+        args.cookies = curl.cookie_file.map(|path| path.display().to_string());
+    }
+    send(curl.request, args).await
+}
 
+#[derive(Args)]
+pub struct ExecArgs {
+    /// File with the request as JSON, or NDJSON of several; - for stdin
+    #[arg(value_name = "FILE")]
+    pub input: String,
+}
+
+/// `gurl exec` requests failed; their error envelopes are already printed.
+#[derive(Debug)]
+struct ExecFailed {
+    failed: usize,
+    exit_status: i32,
+}
+
+impl std::fmt::Display for ExecFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} request(s) failed", self.failed)
+    }
+}
+
+impl std::error::Error for ExecFailed {}
+
+/// Send requests given as JSON, one after the other on a client configured
+/// from the config file, printing one envelope per line. A request that
+/// fails gets an error envelope and the others still run; the exit status
+/// is that of the first failure. Input that is not a valid request ends
+/// the run.
+pub async fn exec(args: ExecArgs) -> Result<()> {
+    let input: Box<dyn io::Read + Send> = match args.input.as_str() {
+        "-" => Box::new(io::stdin()),
+        path => {
+            Box::new(std::fs::File::open(path).with_context(|| format!("failed to read {path}"))?)
+        }
+    };
+    let client = GurlClient::from_config(&Config::load()?)?;
+    let mut requests =
+        serde_json::Deserializer::from_reader(io::BufReader::new(input)).into_iter::<GurlRequest>();
+    let mut failed = 0;
+    let mut exit_status = 0;
+    let mut fail = |code: &str, message: String| -> Result<()> {
+        eprintln!("gurl: {message}");
+        println!(
+            "{}",
+            serde_json::to_string(&ErrorEnvelope::new(code, message))?
+        );
+        failed += 1;
+        if exit_status == 0 {
+            exit_status = self::exit_status(code);
+        }
+        Ok(())
+    };
+    // Read each request once the previous one is answered, so that an
+    // agent can write them one at a time
+    while let Some(req) = tokio::task::block_in_place(|| requests.next()) {
+        let req = match req {
+            Ok(req) => req,
+            Err(e) => {
+                fail("invalid_request", format!("invalid request: {e}"))?;
+                break;
+            }
+        };
+        match client.execute(req).await {
+            Ok(response) => {
+                for warning in &response.response.warnings {
+                    eprintln!("warning: {warning}");
+                }
+                // The envelope names the file; whoever reads it deletes it
+                if let Some(truncated) = &response.content.truncated {
+                    eprintln!(
+                        "warning: whole body left in {} (delete it when done)",
+                        truncated.body_file.display()
+                    );
+                }
+                println!("{}", serde_json::to_string(&response)?);
+            }
+            Err(e) => fail(e.code(), e.to_string())?,
+        }
+    }
+    if failed > 0 {
+        return Err(ExecFailed {
+            failed,
+            exit_status,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Apply the request options in `args` to `req`, then send it, or print it
+/// as a curl command with --to-curl.
+async fn send(mut req: GurlRequest, args: HttpArgs) -> Result<()> {
     if args.no_redirect {
         req = req.no_redirects();
     }
+    if let Some(max) = args.max_redirects {
+        req = req.with_max_redirects(max);
+    }
 
     if let Some(secs) = args.timeout {
         req = req.with_timeout(std::time::Duration::from_secs(secs));
     }
+    if let Some(bytes) = args.max_size {
+        req = req.with_max_size(bytes);
+    }
+
+    if let Some(retries) = args.retry {
+        let delay = std::time::Duration::try_from_secs_f64(args.retry_delay)
+            .with_context(|| format!("invalid retry delay: {}", args.retry_delay))?;
+        req = req.with_retry(
+            RetryPolicy::new(retries)
+                .with_backoff(args.retry_backoff)
+                .with_base_delay(delay)
+                .with_retry_on(args.retry_on.clone())
+                .with_retry_non_idempotent(args.retry_all_methods),
+        );
+    }
 
     // Parse headers
     let mut header_map = reqwest::header::HeaderMap::new();
@@ -85,43 +486,394 @@ pub async fn execute(method: &str, args: HttpArgs) -> Result<()> {
             reqwest::header::HeaderValue::from_str(value.trim())?,
         );
     }
-    req = req.with_headers(header_map);
+    req.headers.extend(header_map);
 
-    // Parse body: --json > --data > --form
+    if let Some(user) = &args.user {
+        let (username, password) = match user.split_once(':') {
+            Some((username, password)) => (username, Some(password.to_string())),
+            None => (user.as_str(), None),
+        };
+        req = req.with_auth(if args.digest {
+            Auth::digest(username, password.unwrap_or_default())
+        } else {
+            Auth::Basic {
+                username: username.to_string(),
+                password,
+            }
+        });
+    } else if let Some(bearer) = &args.bearer {
+        req = req.with_auth(Auth::bearer(read_secret(bearer)?));
+    } else if let Some(spec) = &args.aws_sigv4 {
+        let (region, service) = sigv4_scope(spec, &req.url)?;
+        let profile = args.aws_profile.as_deref();
+        let mut signer = SigV4::from_env(profile, service)?;
+        if let Some(region) = region {
+            signer.region = region;
+        }
+        req = req.with_auth(Auth::SigV4(signer));
+    } else if req.url.scheme() == "s3" && args.aws_profile.is_some() {
+        let signer = SigV4::from_env(args.aws_profile.as_deref(), "s3")?;
+        req = req.with_auth(Auth::SigV4(signer));
+    }
+
+    // Parse body: --json > --data > --data-binary > --form
     if let Some(json_str) = &args.json {
         let value: serde_json::Value =
             serde_json::from_str(json_str).with_context(|| "invalid JSON body")?;
         req = req.with_body(Body::Json(value));
-    } else if let Some(data) = &args.data {
-        req = req.with_body(Body::Raw(data.as_bytes().to_vec()));
+    } else if !args.data.is_empty() {
+        let data = args
+            .data
+            .iter()
+            .map(|data| curl::read_data(data).with_context(|| format!("failed to read {data}")))
+            .collect::<Result<Vec<_>>>()?;
+        req = req.with_body(Body::Raw(data.join(&b'&')));
+    } else if let Some(data) = &args.data_binary {
+        req = req.with_body(match data.strip_prefix('@') {
+            Some("-") => Body::Stream(BodyStream::stdin()),
+            Some(path) => {
+                std::fs::metadata(path).with_context(|| format!("failed to read {path}"))?;
+                Body::File(PathBuf::from(path))
+            }
+            None => Body::Raw(data.as_bytes().to_vec()),
+        });
     } else if !args.form.is_empty() {
-        let mut pairs = Vec::new();
-        for f in &args.form {
-            let (key, value) = f
-                .split_once('=')
-                .with_context(|| format!("invalid form field (expected 'key=value'): {f}"))?;
-            pairs.push((key.to_string(), value.to_string()));
+        let parts = args
+            .form
+            .iter()
+            .map(|f| f.parse::<FormPart>().map_err(anyhow::Error::msg))
+            .collect::<Result<Vec<_>>>()?;
+        for part in &parts {
+            if let PartValue::File(path) = &part.value {
+                std::fs::metadata(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+            }
         }
-        req = req.with_body(Body::Form(pairs));
+        let multipart = parts
+            .iter()
+            .any(|part| matches!(part.value, PartValue::File(_)) || part.content_type.is_some());
+        req = req.with_body(if multipart {
+            Body::Multipart(parts)
+        } else {
+            Body::Form(
+                parts
+                    .into_iter()
+                    .filter_map(|part| match part.value {
+                        PartValue::Text(value) => Some((part.name, value)),
+                        PartValue::File(_) => None,
+                    })
+                    .collect(),
+            )
+        });
     }
 
-    let client = GurlClient::new()?;
+    if args.to_curl {
+        return print_curl(req, &args);
+    }
+
+    let config = Config::load()?;
+    if let Some(name) = &args.oauth {
+        let profile = config.oauth.get(name).with_context(|| {
+            let mut names: Vec<_> = config.oauth.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            format!(
+                "no OAuth profile '{name}' in the config file (have: {})",
+                names.join(", ")
+            )
+        })?;
+        let oauth = OAuth2::new(name, profile.clone()).on_device_prompt(|prompt| {
+            match &prompt.verification_uri_complete {
+                Some(uri) => eprintln!("To sign in, open {uri} (code {})", prompt.user_code),
+                None => eprintln!(
+                    "To sign in, open {} and enter the code {}",
+                    prompt.verification_uri, prompt.user_code
+                ),
+            }
+        });
+        req = req.with_auth(Auth::OAuth2(oauth));
+    }
+    let mut client = GurlClient::from_config(&config)?
+        .with_robots((config.robots || args.robots) && !args.ignore_robots);
+    if args.proxy.is_some() || args.proxy_user.is_some() || args.noproxy.is_some() {
+        let mut proxy = config.proxy.clone().or(ProxyConfig::from_env());
+        if let Some(url) = &args.proxy {
+            proxy = proxy.with_proxy(url);
+        }
+        if let Some(credentials) = &args.proxy_user {
+            proxy = proxy.with_credentials(credentials);
+        }
+        if let Some(hosts) = &args.noproxy {
+            proxy = proxy.with_no_proxy(hosts);
+        }
+        client = client.with_proxy(proxy)?;
+    }
+    let tls = HostTls {
+        cacert: args.cacert.as_ref().map(PathBuf::from),
+        cert: args.cert.as_ref().map(PathBuf::from),
+        key: args.key.as_ref().map(PathBuf::from),
+        cert_password: args.cert_password.as_deref().map(read_secret).transpose()?,
+        insecure: args.insecure.then_some(true),
+        pins: args
+            .pinnedpubkey
+            .as_ref()
+            .map(|pins| pins.split(';').map(str::to_string).collect()),
+    };
+    if tls != HostTls::default() {
+        client = client.with_tls(config.tls.clone().with_override(&tls))?;
+    }
+    if args.no_netrc {
+        client = client.with_netrc(Netrc::default());
+    } else if let Some(path) = &args.netrc_file {
+        let netrc = Netrc::load(std::path::Path::new(path))
+            .with_context(|| format!("failed to read netrc file {path}"))?;
+        client = client.with_netrc(netrc);
+    }
+    if (args.cache || args.offline) && !args.no_cache {
+        client = client.with_cache(HttpCache::open_default().context("failed to open HTTP cache")?);
+    }
+    if args.offline {
+        req = req.with_cache_mode(CacheMode::Offline);
+    } else if args.no_cache {
+        req = req.with_cache_mode(CacheMode::NoStore);
+    }
+    let session = match &args.session {
+        Some(name) => {
+            let store = SessionStore::open_default().context("failed to open session store")?;
+            let mut session = store.load_or_new(name)?;
+            for (name, value) in &req.headers {
+                if let Ok(value) = value.to_str()
+                    && !session.remember_header(name.as_str(), value)
+                    && session::is_credential_header(name.as_str())
+                {
+                    eprintln!(
+                        "warning: {name} is sent with this request only, not saved in session {}",
+                        session.name
+                    );
+                }
+            }
+            if args.har.is_some() || args.no_har {
+                // Later runs may start from another directory
+                session.har = args.har.as_deref().map(std::path::absolute).transpose()?;
+            }
+            client = client.with_session(&session)?;
+            Some((store, session))
+        }
+        None => None,
+    };
+    // After the session, whose own archive gets these settings
+    if let Some(path) = &args.har {
+        let mut har = HarRecorder::open(path)
+            .with_context(|| format!("failed to open HAR file {}", path.display()))?
+            .sidecar_bodies(args.har_sidecar);
+        if let Some(names) = &args.har_redact {
+            har = har.redact_headers(names.iter().filter(|name| !name.is_empty()));
+        }
+        client = client.with_har(har);
+    }
+    let matcher = Matcher {
+        method: args.match_on.iter().any(|field| field == "method"),
+        url: args.match_on.iter().any(|field| field == "url"),
+        body: args.match_on.iter().any(|field| field == "body"),
+        headers: args.match_header.clone(),
+    };
+    if let Some(dir) = &args.record {
+        let cassette = Cassette::record(dir)
+            .with_context(|| format!("failed to open cassette {}", dir.display()))?;
+        client = client.with_cassette(cassette.matching(matcher));
+    } else if let Some(dir) = &args.replay {
+        let cassette = Cassette::replay(dir)
+            .with_context(|| format!("failed to open cassette {}", dir.display()))?;
+        client = client.with_cassette(cassette.matching(matcher));
+    }
+    if let Some(path) = &args.cookies {
+        // With --save-cookies the file may not exist yet
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                client.cookies().import_netscape(&text);
+            }
+            Err(e) if args.save_cookies && e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("failed to read cookies from {path}")),
+        }
+    }
     let response = client.execute(req).await?;
+    for warning in &response.response.warnings {
+        eprintln!("warning: {warning}");
+    }
+    if args.verbose {
+        print_verbose(&response.response);
+    }
+    if let Some(path) = args.cookies.as_ref().filter(|_| args.save_cookies) {
+        save_cookies(Path::new(path), &client.cookies().to_netscape())
+            .with_context(|| format!("failed to write cookies to {path}"))?;
+    }
+    if let Some((store, session)) = &session {
+        store
+            .save(session)
+            .with_context(|| format!("failed to save session '{}'", session.name))?;
+    }
+
+    let status = response.response.status;
+    let enforced = args.fail || args.fail_with_body || !args.expect_status.is_empty();
+    if enforced && !UnexpectedStatus::check(&args.expect_status, status) {
+        let mut unexpected = UnexpectedStatus {
+            status,
+            status_text: response.response.status_text.clone(),
+            url: response.response.final_url.clone(),
+            printed: false,
+        };
+        if !args.fail {
+            print_response(&args, &response)?;
+            unexpected.printed = true;
+        } else if let Some(truncated) = &response.content.truncated {
+            let _ = std::fs::remove_file(&truncated.body_file);
+        }
+        return Err(unexpected.into());
+    }
+    print_response(&args, &response)
+}
+
+/// --save-cookies: the jar holds session cookies, so like a session file it
+/// is readable only by the user, and replaced whole so a reader never sees
+/// half of it.
+fn save_cookies(path: &Path, jar: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp{}", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    gurl_core::write_private(&tmp, jar.as_bytes())?;
+    std::fs::rename(&tmp, path)
+}
+
+/// --to-curl: print the curl command for `req` and the client options in
+/// `args`, warning about the options curl has no counterpart for.
+fn print_curl(req: GurlRequest, args: &HttpArgs) -> Result<()> {
+    let mut command = CurlCommand::new(req);
+    command.insecure = args.insecure;
+    command.cookie_file = args.cookies.as_ref().map(PathBuf::from);
+    let options = [
+        ("--cacert", &args.cacert),
+        ("--cert", &args.cert),
+        ("--key", &args.key),
+        ("--pinnedpubkey", &args.pinnedpubkey),
+        ("--proxy", &args.proxy),
+        ("--proxy-user", &args.proxy_user),
+        ("--noproxy", &args.noproxy),
+        ("--netrc-file", &args.netrc_file),
+        ("--output", &args.output),
+    ];
+    for (option, value) in options {
+        if let Some(value) = value {
+            command.options.extend([option.to_string(), value.clone()]);
+        }
+    }
+    if let Some(path) = args.cookies.as_ref().filter(|_| args.save_cookies) {
+        command
+            .options
+            .extend(["--cookie-jar".to_string(), path.clone()]);
+    }
+    // gurl reads ~/.netrc unless told not to; curl only when asked
+    if args.netrc_file.is_none() && !args.no_netrc {
+        command.options.push("--netrc-optional".to_string());
+    }
+    if args.fail {
+        command.options.push("--fail".to_string());
+    } else if args.fail_with_body {
+        command.options.push("--fail-with-body".to_string());
+    }
+
+    let left_out = [
+        ("--oauth", args.oauth.is_some()),
+        ("--cert-password", args.cert_password.is_some()),
+        ("--session", args.session.is_some()),
+        ("--cache", args.cache),
+        ("--offline", args.offline),
+        ("--robots", args.robots),
+        ("--har", args.har.is_some()),
+        ("--record", args.record.is_some()),
+        ("--replay", args.replay.is_some()),
+        ("--expect-status", !args.expect_status.is_empty()),
+    ];
+    for (option, _) in left_out.iter().filter(|(_, used)| *used) {
+        eprintln!("warning: {option} has no curl equivalent and is left out");
+    }
+    println!("{command}");
+    Ok(())
+}
+
+/// -v: timing breakdown and TLS summary, on stderr so stdout stays the response.
+fn print_verbose(meta: &ResponseMeta) {
+    let timing = &meta.timing;
+    let phases = [
+        ("dns", timing.dns_ms),
+        ("connect", timing.connect_ms),
+        ("tls", timing.tls_ms),
+        ("first byte", timing.first_byte_ms),
+        ("total", Some(timing.total_ms)),
+    ];
+    let phases: Vec<String> = phases
+        .iter()
+        .filter_map(|(name, ms)| ms.map(|ms| format!("{name} {ms} ms")))
+        .collect();
+    eprintln!("* timing: {}", phases.join(", "));
+    if let Some(tls) = &meta.tls {
+        let mut line = format!("* tls: {} {}", tls.version, tls.cipher);
+        if let Some(alpn) = &tls.alpn {
+            line.push_str(&format!(", alpn {alpn}"));
+        }
+        if let Some(issuer) = &tls.cert_issuer {
+            line.push_str(&format!(", issuer {issuer}"));
+        }
+        if let Some(leaf) = tls.certificates.first() {
+            line.push_str(&format!(", expires in {} days", leaf.expires_in_days));
+        }
+        eprintln!("{line}");
+    }
+}
+
+fn print_response(args: &HttpArgs, response: &GurlResponse) -> Result<()> {
+    // A body over the memory limit is only whole in its temp file
+    let truncated = response.content.truncated.as_ref();
 
     // --output: save raw body to file
     if let Some(path) = &args.output {
-        std::fs::write(path, &response.content.raw_body)
-            .with_context(|| format!("failed to write output to {path}"))?;
-        eprintln!("Saved {} bytes to {path}", response.content.raw_body.len());
+        let size = match truncated {
+            Some(truncated) => {
+                move_file(&truncated.body_file, Path::new(path))
+                    .with_context(|| format!("failed to write output to {path}"))?;
+                truncated.size
+            }
+            None => {
+                std::fs::write(path, &response.content.raw_body)
+                    .with_context(|| format!("failed to write output to {path}"))?;
+                response.content.raw_body.len() as u64
+            }
+        };
+        eprintln!("Saved {size} bytes to {path}");
         return Ok(());
     }
 
     // --raw: write raw response bytes
     if args.raw {
-        io::stdout().write_all(&response.content.raw_body)?;
+        match truncated {
+            Some(truncated) => {
+                let mut file = std::fs::File::open(&truncated.body_file)?;
+                let copied = io::copy(&mut file, &mut io::stdout().lock());
+                let _ = std::fs::remove_file(&truncated.body_file);
+                copied?;
+            }
+            None => io::stdout().write_all(&response.content.raw_body)?,
+        }
         return Ok(());
     }
 
+    if let Some(truncated) = truncated {
+        eprintln!(
+            "warning: body is {} bytes; the envelope holds the first {} and the whole body is in {} (delete it when done)",
+            truncated.size,
+            truncated.kept,
+            truncated.body_file.display()
+        );
+    }
+
     // --quiet: body content only
     if args.quiet {
         let body_str = match &response.content.body {
@@ -134,7 +886,7 @@ pub async fn execute(method: &str, args: HttpArgs) -> Result<()> {
 
     // --select: extract a field using dot notation
     if let Some(path) = &args.select {
-        let envelope = serde_json::to_value(&response)?;
+        let envelope = serde_json::to_value(response)?;
         let selected = select_path(&envelope, path);
         let out = match selected {
             serde_json::Value::String(s) => s,
@@ -150,15 +902,138 @@ pub async fn execute(method: &str, args: HttpArgs) -> Result<()> {
     // Default: full JSON envelope
     let is_tty = io::stdout().is_terminal();
     let output = if is_tty {
-        serde_json::to_string_pretty(&response)?
+        serde_json::to_string_pretty(response)?
     } else {
-        serde_json::to_string(&response)?
+        serde_json::to_string(response)?
     };
     println!("{output}");
 
     Ok(())
 }
 
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    /// List saved sessions
+    List,
+    /// Show a session's headers and cookies
+    Show { name: String },
+    /// Delete a session, or every session with --all
+    Clear {
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
+        #[arg(long, conflicts_with = "name")]
+        all: bool,
+    },
+}
+
+pub fn session(command: SessionCommand) -> Result<()> {
+    let store = SessionStore::open_default().context("failed to open session store")?;
+    match command {
+        SessionCommand::List => print_json(&store.list()?),
+        SessionCommand::Show { name } => {
+            let session = store
+                .load(&name)?
+                .with_context(|| format!("no session named '{name}'"))?;
+            print_json(&session.data())
+        }
+        SessionCommand::Clear { name, all } => {
+            let names = match name {
+                Some(name) if !all => vec![name],
+                _ => store.list()?.into_iter().map(|s| s.name).collect(),
+            };
+            for name in names {
+                if !store.remove(&name)? {
+                    anyhow::bail!("no session named '{name}'");
+                }
+                eprintln!("Removed session {name}");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Report a failed run: a one-line summary on stderr and an error envelope
+/// on stdout. Returns the exit status for it.
+pub fn report_error(e: &anyhow::Error) -> i32 {
+    if let Some(failed) = e.downcast_ref::<ExecFailed>() {
+        return failed.exit_status;
+    }
+    if let Some(unexpected) = e.downcast_ref::<UnexpectedStatus>() {
+        eprintln!("gurl: {unexpected}");
+        if !unexpected.printed {
+            let _ = print_json(&ErrorEnvelope::new("http_status", unexpected.to_string()));
+        }
+        return unexpected.exit_status();
+    }
+    let code = error_code(e);
+    eprintln!("gurl: {e:#}");
+    let _ = print_json(&ErrorEnvelope::new(code, format!("{e:#}")));
+    exit_status(code)
+}
+
+fn error_code(e: &anyhow::Error) -> &'static str {
+    if let Some(error) = e.chain().find_map(|c| c.downcast_ref::<gurl_core::Error>()) {
+        error.code()
+    } else if e.chain().any(|c| c.is::<url::ParseError>()) {
+        "invalid_url"
+    } else {
+        "error"
+    }
+}
+
+/// Exit status for an error code, the same as curl's where it has one.
+fn exit_status(code: &str) -> i32 {
+    match code {
+        "config" | "invalid_curl" | "invalid_request" => 2,
+        "invalid_url" => 3,
+        "not_cached" | "not_recorded" => 4,
+        "dns" => 6,
+        "connect" => 7,
+        "policy_denied" => 9,
+        "timeout" => 28,
+        "tls" => 35,
+        "body_too_large" => 63,
+        "too_many_redirects" => 47,
+        "auth" => 67,
+        _ => 1,
+    }
+}
+
+/// Move a file, copying it when a rename can't (e.g. across filesystems).
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)
+}
+
+/// Parse a byte count with an optional k, M or G suffix (powers of 1024).
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, unit) = match s.as_bytes().last() {
+        Some(b'k' | b'K') => (&s[..s.len() - 1], 1 << 10),
+        Some(b'm' | b'M') => (&s[..s.len() - 1], 1 << 20),
+        Some(b'g' | b'G') => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| format!("invalid size '{s}', expected e.g. 1048576, 500k or 20M"))
+}
+
+fn print_json(value: &impl serde::Serialize) -> Result<()> {
+    let output = if io::stdout().is_terminal() {
+        serde_json::to_string_pretty(value)?
+    } else {
+        serde_json::to_string(value)?
+    };
+    println!("{output}");
+    Ok(())
+}
+
 fn select_path(value: &serde_json::Value, path: &str) -> serde_json::Value {
     let mut current = value;
     for key in path.split('.') {
@@ -1,4 +1,5 @@
 mod commands;
+mod status;
 
 use clap::{Parser, Subcommand};
 
@@ -28,6 +29,16 @@ enum Commands {
     Head(commands::HttpArgs),
     /// HTTP OPTIONS request
     Options(commands::HttpArgs),
+    /// Run a curl command line, e.g. gurl from-curl 'curl -H "Accept: text/html" example.com'
+    #[command(mut_arg("url", |arg| arg.hide(true)))]
+    FromCurl(commands::FromCurlArgs),
+    /// Send requests given as JSON, e.g. echo '{"url": "https://example.com"}' | gurl exec -
+    Exec(commands::ExecArgs),
+    /// Manage named sessions (cookie jars and default headers)
+    Session {
+        #[command(subcommand)]
+        command: commands::SessionCommand,
+    },
 }
 
 #[tokio::main]
@@ -42,6 +53,9 @@ async fn main() {
         Some(Commands::Delete(args)) => commands::execute("DELETE", args).await,
         Some(Commands::Head(args)) => commands::execute("HEAD", args).await,
         Some(Commands::Options(args)) => commands::execute("OPTIONS", args).await,
+        Some(Commands::FromCurl(args)) => commands::from_curl(args).await,
+        Some(Commands::Exec(args)) => commands::exec(args).await,
+        Some(Commands::Session { command }) => commands::session(command),
         None => {
             if cli.args.url.is_empty() {
                 use clap::CommandFactory;
@@ -54,7 +68,6 @@ async fn main() {
     };
 
     if let Err(e) = result {
-        eprintln!("gurl: {e:#}");
-        std::process::exit(1);
+        std::process::exit(commands::report_error(&e));
     }
 }
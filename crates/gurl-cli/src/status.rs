@@ -0,0 +1,142 @@
+// Status checks for scripted callers: --fail, --fail-with-body and
+// --expect-status turn an unwanted HTTP status into a non-zero exit.
+//
+// Exit statuses by class: 22 for 4xx, 29 for 5xx and 24 for anything else
+// outside --expect-status. curl's --fail exits 22 for 5xx as well; gurl tells
+// the classes apart with 24 and 29, which curl has retired and no longer
+// returns, so none of them means something else to a curl-minded script.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// One entry of `--expect-status`: an exact status or a class like `2xx`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusPattern {
+    Exact(u16),
+    Class(u16),
+}
+
+impl StatusPattern {
+    fn matches(self, status: u16) -> bool {
+        match self {
+            StatusPattern::Exact(expected) => status == expected,
+            StatusPattern::Class(class) => status / 100 == class,
+        }
+    }
+}
+
+impl FromStr for StatusPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("invalid status '{s}', expected e.g. 200 or 2xx");
+        match s.to_ascii_lowercase().strip_suffix("xx") {
+            Some(class) => match class.parse() {
+                Ok(class @ 1..=5) => Ok(StatusPattern::Class(class)),
+                _ => Err(invalid()),
+            },
+            None => match s.parse() {
+                Ok(status @ 100..=599) => Ok(StatusPattern::Exact(status)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+/// A response whose status the caller did not accept.
+#[derive(Debug)]
+pub struct UnexpectedStatus {
+    pub status: u16,
+    pub status_text: String,
+    pub url: String,
+    /// The response was printed anyway (--fail-with-body, --expect-status)
+    pub printed: bool,
+}
+
+impl UnexpectedStatus {
+    /// Check `status` against `expected`, or against `< 400` when empty.
+    pub fn check(expected: &[StatusPattern], status: u16) -> bool {
+        if expected.is_empty() {
+            status < 400
+        } else {
+            expected.iter().any(|pattern| pattern.matches(status))
+        }
+    }
+
+    pub fn exit_status(&self) -> i32 {
+        match self.status {
+            400..=499 => 22,
+            500..=599 => 29,
+            _ => 24,
+        }
+    }
+}
+
+impl fmt::Display for UnexpectedStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {}", self.status)?;
+        if !self.status_text.is_empty() {
+            write!(f, " {}", self.status_text)?;
+        }
+        write!(f, " from {}", self.url)
+    }
+}
+
+impl std::error::Error for UnexpectedStatus {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unexpected(status: u16) -> UnexpectedStatus {
+        UnexpectedStatus {
+            status,
+            status_text: String::new(),
+            url: "https://example.com/".to_string(),
+            printed: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_patterns() {
+        assert_eq!("200".parse(), Ok(StatusPattern::Exact(200)));
+        assert_eq!(" 2XX ".parse(), Ok(StatusPattern::Class(2)));
+        assert_eq!("100".parse(), Ok(StatusPattern::Exact(100)));
+        assert_eq!("599".parse(), Ok(StatusPattern::Exact(599)));
+        for invalid in ["6xx", "0xx", "xx", "099", "600", "2x", "abc", ""] {
+            assert!(invalid.parse::<StatusPattern>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        // Without --expect-status anything below 400 is fine
+        assert!(UnexpectedStatus::check(&[], 200));
+        assert!(UnexpectedStatus::check(&[], 399));
+        assert!(!UnexpectedStatus::check(&[], 400));
+        assert!(!UnexpectedStatus::check(&[], 503));
+
+        let expected = [StatusPattern::Exact(201), StatusPattern::Class(3)];
+        assert!(UnexpectedStatus::check(&expected, 201));
+        assert!(UnexpectedStatus::check(&expected, 304));
+        assert!(!UnexpectedStatus::check(&expected, 200));
+        assert!(UnexpectedStatus::check(&[StatusPattern::Class(4)], 404));
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(unexpected(404).exit_status(), 22);
+        assert_eq!(unexpected(499).exit_status(), 22);
+        assert_eq!(unexpected(500).exit_status(), 29);
+        assert_eq!(unexpected(200).exit_status(), 24);
+        assert_eq!(unexpected(302).exit_status(), 24);
+        // Servers may send codes outside the registered range
+        assert_eq!(unexpected(600).exit_status(), 24);
+        assert_eq!(unexpected(99).exit_status(), 24);
+        assert_eq!(
+            unexpected(404).to_string(),
+            "HTTP 404 from https://example.com/"
+        );
+    }
+}
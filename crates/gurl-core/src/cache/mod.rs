@@ -0,0 +1,369 @@
+// Persistent HTTP response cache (RFC 9111).
+//
+// Every method+URL gets a directory under the cache root, named by its
+// SHA-256. Inside it, each response variant is a pair of files:
+// `<variant>.json` (status, headers and the converted content) and
+// `<variant>.body` (raw response bytes). Variants exist because of Vary:
+// the `vary` file records which request headers the latest response varied
+// on, and the variant key hashes those request header values.
+//
+// Storing the converted `Content` means a fresh hit or a 304 revalidation
+// skips markitdown entirely.
+
+pub mod policy;
+
+use crate::client::response::HeaderField;
+use crate::hex;
+use crate::output::envelope::Content;
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use reqwest::header::{
+    AGE, ETAG, HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE, VARY,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// How a request interacts with the client's cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheMode {
+    /// Serve fresh entries, revalidate stale ones and store cacheable responses
+    #[default]
+    Default,
+    /// Bypass the cache: neither read nor write
+    NoStore,
+    /// Never touch the network; serve any stored entry, fresh or stale
+    Offline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    /// Served from the cache without contacting the origin
+    Hit,
+    /// Stored entry confirmed by the origin with a 304
+    Revalidated,
+    /// Fetched from the origin
+    Miss,
+    /// Stale entry served without revalidation (offline mode)
+    Stale,
+}
+
+/// Cache outcome reported in the envelope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheInfo {
+    pub status: CacheStatus,
+    /// Age of the served entry in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_s: Option<u64>,
+    /// Seconds the entry remains fresh
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_s: Option<u64>,
+    /// Whether the response was written to the cache
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stored: bool,
+}
+
+/// A response as kept on disk.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<HeaderField>,
+    pub request_time: DateTime<Utc>,
+    pub response_time: DateTime<Utc>,
+    pub content: Content,
+}
+
+impl CachedResponse {
+    pub fn header_map(&self) -> HeaderMap {
+        HeaderField::map(&self.headers)
+    }
+
+    pub fn age(&self, now: DateTime<Utc>) -> std::time::Duration {
+        policy::current_age(
+            &self.header_map(),
+            self.request_time,
+            self.response_time,
+            now,
+        )
+    }
+
+    pub fn lifetime(&self) -> std::time::Duration {
+        policy::freshness_lifetime(self.status, &self.header_map())
+    }
+
+    /// Cache outcome for serving this entry now.
+    pub fn info(&self, status: CacheStatus) -> CacheInfo {
+        let age = self.age(Utc::now());
+        CacheInfo {
+            status,
+            age_s: Some(age.as_secs()),
+            ttl_s: Some(self.lifetime().saturating_sub(age).as_secs()),
+            stored: false,
+        }
+    }
+
+    pub fn is_fresh(&self, request: &HeaderMap) -> bool {
+        let headers = self.header_map();
+        let age = policy::current_age(&headers, self.request_time, self.response_time, Utc::now());
+        policy::is_fresh(&headers, self.lifetime(), age, request)
+    }
+
+    /// Conditional request headers that let the origin answer with a 304.
+    pub fn validators(&self) -> HeaderMap {
+        let headers = self.header_map();
+        let mut validators = HeaderMap::new();
+        if let Some(etag) = headers.get(ETAG) {
+            validators.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(modified) = headers.get(LAST_MODIFIED) {
+            validators.insert(IF_MODIFIED_SINCE, modified.clone());
+        }
+        validators
+    }
+
+    /// Merge the headers of a 304 into the stored response (RFC 9111 section 4.3.4).
+    pub fn freshen(&mut self, not_modified: &HeaderMap, request_time: DateTime<Utc>) {
+        for name in not_modified.keys() {
+            if matches!(
+                name.as_str(),
+                "content-length" | "content-encoding" | "transfer-encoding"
+            ) {
+                continue;
+            }
+            self.headers
+                .retain(|field| !field.name.eq_ignore_ascii_case(name.as_str()));
+            for value in not_modified.get_all(name) {
+                self.headers.push(HeaderField::new(name, value));
+            }
+        }
+        self.request_time = request_time;
+        self.response_time = Utc::now();
+    }
+
+    /// Headers as reported in the envelope, with `age` reflecting the current age.
+    pub fn envelope_headers(&self) -> Vec<HeaderField> {
+        let age = HeaderValue::from(self.age(Utc::now()).as_secs());
+        let mut headers: Vec<HeaderField> = self
+            .headers
+            .iter()
+            .filter(|field| !field.name.eq_ignore_ascii_case(AGE.as_str()))
+            .cloned()
+            .collect();
+        headers.push(HeaderField::new(&AGE, &age));
+        headers
+    }
+}
+
+/// On-disk HTTP cache shared by all requests of a `GurlClient`.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    /// Open (creating if needed) a cache rooted at `dir`.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Open the cache in the user's cache directory (e.g. `~/.cache/gurl/http`).
+    pub fn open_default() -> io::Result<Self> {
+        let dir = Self::default_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+        Self::open(dir)
+    }
+
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|d| d.join("gurl").join("http"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Remove every stored response.
+    pub fn clear(&self) -> io::Result<()> {
+        std::fs::remove_dir_all(&self.dir)?;
+        std::fs::create_dir_all(&self.dir)
+    }
+
+    /// The stored response for a request, if any. Entries hold whole bodies,
+    /// so a Range request is never answered from them.
+    pub(crate) fn lookup(
+        &self,
+        method: &Method,
+        url: &Url,
+        request: &HeaderMap,
+    ) -> Option<CachedResponse> {
+        if request.contains_key(RANGE) {
+            return None;
+        }
+        let entry_dir = self.entry_dir(method, url);
+        let vary = std::fs::read_to_string(entry_dir.join("vary")).unwrap_or_default();
+        let variant = variant_key(&vary, request);
+
+        let meta = std::fs::read(entry_dir.join(format!("{variant}.json"))).ok()?;
+        let mut cached: CachedResponse = serde_json::from_slice(&meta).ok()?;
+        cached.content.raw_body = std::fs::read(entry_dir.join(format!("{variant}.body"))).ok()?;
+        Some(cached)
+    }
+
+    pub(crate) fn store(
+        &self,
+        method: &Method,
+        url: &Url,
+        request: &HeaderMap,
+        response: &CachedResponse,
+    ) -> io::Result<()> {
+        let entry_dir = self.entry_dir(method, url);
+        std::fs::create_dir_all(&entry_dir)?;
+
+        let vary = response
+            .headers
+            .iter()
+            .filter(|field| field.name.eq_ignore_ascii_case(VARY.as_str()))
+            .flat_map(|field| field.value.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let variant = variant_key(&vary, request);
+
+        write_atomic(
+            &entry_dir.join(format!("{variant}.body")),
+            &response.content.raw_body,
+        )?;
+        write_atomic(
+            &entry_dir.join(format!("{variant}.json")),
+            &serde_json::to_vec(response)?,
+        )?;
+        write_atomic(&entry_dir.join("vary"), vary.as_bytes())
+    }
+
+    /// Drop every stored variant for a URL, e.g. after a successful POST to it.
+    pub(crate) fn invalidate(&self, url: &Url) -> io::Result<()> {
+        match std::fs::remove_dir_all(self.entry_dir(&Method::GET, url)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_dir(&self, method: &Method, url: &Url) -> PathBuf {
+        let key = Sha256::digest(format!("{method} {url}"));
+        self.dir.join(hex(&key))
+    }
+}
+
+/// Key of the variant selected by the request's values for the varied headers.
+fn variant_key(vary: &str, request: &HeaderMap) -> String {
+    let mut hasher = Sha256::new();
+    for name in vary.lines() {
+        hasher.update(name.as_bytes());
+        hasher.update(b":");
+        for value in request.get_all(name) {
+            hasher.update(value.as_bytes());
+            hasher.update(b",");
+        }
+        hasher.update(b"\n");
+    }
+    hex(&hasher.finalize())
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&str, &str)]) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            status_text: "OK".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| HeaderField {
+                    name: k.to_string(),
+                    value: v.to_string(),
+                    value_base64: None,
+                })
+                .collect(),
+            request_time: Utc::now(),
+            response_time: Utc::now(),
+            content: Content {
+                content_type: "markdown".to_string(),
+                original_type: "text/html".to_string(),
+                title: Some("Doc".to_string()),
+                body: serde_json::Value::String("# Doc".to_string()),
+                metadata: None,
+                links: None,
+                images: None,
+                truncated: None,
+                raw_body: b"<h1>Doc</h1>".to_vec(),
+            },
+        }
+    }
+
+    fn temp_cache(name: &str) -> HttpCache {
+        let dir = std::env::temp_dir().join(format!("gurl-cache-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        HttpCache::open(dir).unwrap()
+    }
+
+    #[test]
+    fn test_store_and_lookup_with_vary() {
+        let cache = temp_cache("vary");
+        let url = Url::parse("https://example.com/docs").unwrap();
+        let mut en = HeaderMap::new();
+        en.insert("accept-language", HeaderValue::from_static("en"));
+        let mut de = HeaderMap::new();
+        de.insert("accept-language", HeaderValue::from_static("de"));
+
+        let stored = response(&[("cache-control", "max-age=60"), ("vary", "Accept-Language")]);
+        cache.store(&Method::GET, &url, &en, &stored).unwrap();
+
+        let hit = cache.lookup(&Method::GET, &url, &en).unwrap();
+        assert_eq!(hit.content.body, "# Doc");
+        assert_eq!(hit.content.raw_body, b"<h1>Doc</h1>");
+        assert!(hit.is_fresh(&HeaderMap::new()));
+        assert!(cache.lookup(&Method::GET, &url, &de).is_none());
+
+        cache.invalidate(&url).unwrap();
+        assert!(cache.lookup(&Method::GET, &url, &en).is_none());
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_validators_and_freshen() {
+        let mut cached = response(&[
+            ("etag", "\"v1\""),
+            ("last-modified", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("cache-control", "no-cache"),
+        ]);
+        assert!(!cached.is_fresh(&HeaderMap::new()));
+
+        let validators = cached.validators();
+        assert_eq!(validators[IF_NONE_MATCH], "\"v1\"");
+        assert_eq!(
+            validators[IF_MODIFIED_SINCE],
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+
+        let mut not_modified = HeaderMap::new();
+        not_modified.insert("cache-control", HeaderValue::from_static("max-age=300"));
+        not_modified.insert("content-length", HeaderValue::from_static("0"));
+        cached.freshen(&not_modified, Utc::now());
+        assert!(cached.is_fresh(&HeaderMap::new()));
+        assert!(!cached.headers.iter().any(|f| f.name == "content-length"));
+    }
+}
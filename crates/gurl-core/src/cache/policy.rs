@@ -0,0 +1,334 @@
+// RFC 9111 caching rules: Cache-Control parsing, storability and freshness.
+//
+// gurl is a private (single-user) cache, so `private` responses may be
+// stored and `s-maxage` is ignored.
+
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use reqwest::header::{AGE, CACHE_CONTROL, DATE, EXPIRES, HeaderMap, LAST_MODIFIED, PRAGMA, RANGE};
+use std::time::Duration;
+
+/// Heuristic freshness never exceeds this, no matter how old the resource is.
+const MAX_HEURISTIC_FRESHNESS: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Status codes that may be cached without explicit freshness information
+/// (RFC 9110 section 15.1), less 206: gurl does not store partial content.
+const HEURISTICALLY_CACHEABLE: &[u16] = &[200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Parsed Cache-Control directives, from either a request or a response.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub must_revalidate: bool,
+    pub public: bool,
+    pub private: bool,
+    pub immutable: bool,
+    /// `max-stale` with an optional limit; `Some(None)` accepts any staleness
+    pub max_stale: Option<Option<u64>>,
+    pub min_fresh: Option<u64>,
+    pub only_if_cached: bool,
+}
+
+impl CacheControl {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut cc = Self::default();
+        for value in headers.get_all(CACHE_CONTROL) {
+            let Ok(value) = value.to_str() else { continue };
+            for directive in split_directives(value) {
+                let (name, arg) = match directive.split_once('=') {
+                    Some((name, arg)) => (name.trim(), Some(arg.trim().trim_matches('"'))),
+                    None => (directive.trim(), None),
+                };
+                let seconds = arg.and_then(|a| a.parse::<u64>().ok());
+                match name.to_ascii_lowercase().as_str() {
+                    "max-age" => cc.max_age = seconds.or(Some(0)),
+                    "no-cache" => cc.no_cache = true,
+                    "no-store" => cc.no_store = true,
+                    "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                    "public" => cc.public = true,
+                    "private" => cc.private = true,
+                    "immutable" => cc.immutable = true,
+                    "max-stale" => cc.max_stale = Some(seconds),
+                    "min-fresh" => cc.min_fresh = seconds,
+                    "only-if-cached" => cc.only_if_cached = true,
+                    _ => {}
+                }
+            }
+        }
+        // HTTP/1.0 caches: "Pragma: no-cache" without Cache-Control
+        if headers.get(CACHE_CONTROL).is_none()
+            && headers
+                .get_all(PRAGMA)
+                .iter()
+                .any(|v| v.to_str().is_ok_and(|v| v.contains("no-cache")))
+        {
+            cc.no_cache = true;
+        }
+        cc
+    }
+}
+
+/// Split on commas that are not inside a quoted string.
+fn split_directives(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts.into_iter().filter(|p| !p.trim().is_empty()).collect()
+}
+
+pub fn http_date(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<DateTime<Utc>> {
+    let value = headers.get(name)?.to_str().ok()?;
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Whether a response may be stored at all (RFC 9111 section 3).
+pub fn is_storable(
+    method: &Method,
+    status: u16,
+    request: &HeaderMap,
+    response: &HeaderMap,
+) -> bool {
+    // A Range request gets part of the body, which must not stand in for all of it
+    if method != Method::GET || status == 206 || request.contains_key(RANGE) {
+        return false;
+    }
+    let req_cc = CacheControl::from_headers(request);
+    let res_cc = CacheControl::from_headers(response);
+    if req_cc.no_store || res_cc.no_store {
+        return false;
+    }
+    if response
+        .get_all(reqwest::header::VARY)
+        .iter()
+        .any(|v| v.to_str().is_ok_and(|v| v.trim() == "*"))
+    {
+        return false;
+    }
+    res_cc.max_age.is_some()
+        || response.contains_key(EXPIRES)
+        || res_cc.public
+        || res_cc.private
+        || HEURISTICALLY_CACHEABLE.contains(&status)
+}
+
+/// How long a response stays fresh after it was generated (RFC 9111 section 4.2.1).
+pub fn freshness_lifetime(status: u16, headers: &HeaderMap) -> Duration {
+    let cc = CacheControl::from_headers(headers);
+    if let Some(max_age) = cc.max_age {
+        return Duration::from_secs(max_age);
+    }
+    let date = http_date(headers, DATE);
+    if headers.contains_key(EXPIRES) {
+        // An invalid Expires (e.g. "0") means already expired
+        return match (http_date(headers, EXPIRES), date) {
+            (Some(expires), Some(date)) => (expires - date).to_std().unwrap_or_default(),
+            _ => Duration::ZERO,
+        };
+    }
+    // Heuristic freshness: 10% of the time since last modification
+    if HEURISTICALLY_CACHEABLE.contains(&status)
+        && let (Some(date), Some(modified)) = (date, http_date(headers, LAST_MODIFIED))
+    {
+        let since_modified = (date - modified).to_std().unwrap_or_default();
+        return (since_modified / 10).min(MAX_HEURISTIC_FRESHNESS);
+    }
+    Duration::ZERO
+}
+
+/// Current age of a stored response (RFC 9111 section 4.2.3).
+pub fn current_age(
+    headers: &HeaderMap,
+    request_time: DateTime<Utc>,
+    response_time: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Duration {
+    let age_value = headers
+        .get(AGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default();
+    let apparent_age = http_date(headers, DATE)
+        .and_then(|date| (response_time - date).to_std().ok())
+        .unwrap_or_default();
+    let response_delay = (response_time - request_time).to_std().unwrap_or_default();
+    let corrected_initial_age = apparent_age.max(age_value + response_delay);
+    let resident_time = (now - response_time).to_std().unwrap_or_default();
+    corrected_initial_age + resident_time
+}
+
+/// Whether a stored response can be served without revalidation, given the
+/// request's own Cache-Control.
+pub fn is_fresh(
+    response: &HeaderMap,
+    lifetime: Duration,
+    age: Duration,
+    request: &HeaderMap,
+) -> bool {
+    let res_cc = CacheControl::from_headers(response);
+    let req_cc = CacheControl::from_headers(request);
+    if res_cc.no_cache || req_cc.no_cache {
+        return false;
+    }
+    let lifetime = match req_cc.max_age {
+        Some(max_age) => lifetime.min(Duration::from_secs(max_age)),
+        None => lifetime,
+    };
+    let needed = age + Duration::from_secs(req_cc.min_fresh.unwrap_or(0));
+    if lifetime > needed {
+        return true;
+    }
+    // max-stale lets the client accept stale responses, unless the origin forbids it
+    match req_cc.max_stale {
+        Some(limit) if !res_cc.must_revalidate => {
+            limit.is_none_or(|limit| needed - lifetime <= Duration::from_secs(limit))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (k, v) in pairs {
+            map.append(*k, HeaderValue::from_static(v));
+        }
+        map
+    }
+
+    #[test]
+    fn test_parse_cache_control() {
+        let cc = CacheControl::from_headers(&headers(&[
+            ("cache-control", "public, max-age=600"),
+            ("cache-control", "must-revalidate, ext=\"a,b\""),
+        ]));
+        assert_eq!(cc.max_age, Some(600));
+        assert!(cc.public && cc.must_revalidate);
+        assert!(!cc.no_store);
+
+        let req = CacheControl::from_headers(&headers(&[("cache-control", "max-stale")]));
+        assert_eq!(req.max_stale, Some(None));
+        let pragma = CacheControl::from_headers(&headers(&[("pragma", "no-cache")]));
+        assert!(pragma.no_cache);
+    }
+
+    #[test]
+    fn test_storable() {
+        let none = HeaderMap::new();
+        assert!(is_storable(&Method::GET, 200, &none, &none));
+        assert!(!is_storable(&Method::POST, 200, &none, &none));
+        assert!(!is_storable(&Method::GET, 500, &none, &none));
+        assert!(is_storable(
+            &Method::GET,
+            500,
+            &none,
+            &headers(&[("cache-control", "max-age=5")])
+        ));
+        assert!(!is_storable(
+            &Method::GET,
+            200,
+            &none,
+            &headers(&[("cache-control", "no-store")])
+        ));
+        assert!(!is_storable(
+            &Method::GET,
+            200,
+            &none,
+            &headers(&[("vary", "*")])
+        ));
+        let ranged = headers(&[("cache-control", "max-age=60")]);
+        assert!(!is_storable(&Method::GET, 206, &none, &ranged));
+        assert!(!is_storable(
+            &Method::GET,
+            200,
+            &headers(&[("range", "bytes=0-99")]),
+            &ranged
+        ));
+    }
+
+    #[test]
+    fn test_freshness_lifetime() {
+        let max_age = headers(&[
+            ("cache-control", "max-age=60"),
+            ("expires", "Thu, 01 Jan 1970 00:00:00 GMT"),
+        ]);
+        assert_eq!(freshness_lifetime(200, &max_age), Duration::from_secs(60));
+
+        let expires = headers(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("expires", "Sun, 06 Nov 1994 09:49:37 GMT"),
+        ]);
+        assert_eq!(freshness_lifetime(200, &expires), Duration::from_secs(3600));
+        assert_eq!(
+            freshness_lifetime(200, &headers(&[("expires", "0")])),
+            Duration::ZERO
+        );
+
+        let heuristic = headers(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("last-modified", "Sun, 06 Nov 1994 06:49:37 GMT"),
+        ]);
+        assert_eq!(
+            freshness_lifetime(200, &heuristic),
+            Duration::from_secs(720)
+        );
+    }
+
+    #[test]
+    fn test_current_age_and_freshness() {
+        let response = headers(&[("age", "30"), ("cache-control", "max-age=100")]);
+        let t0 = Utc::now();
+        let age = current_age(&response, t0, t0, t0 + chrono::Duration::seconds(50));
+        assert_eq!(age, Duration::from_secs(80));
+
+        let lifetime = freshness_lifetime(200, &response);
+        let none = HeaderMap::new();
+        assert!(is_fresh(&response, lifetime, age, &none));
+        assert!(!is_fresh(
+            &response,
+            lifetime,
+            age,
+            &headers(&[("cache-control", "max-age=60")])
+        ));
+        assert!(!is_fresh(
+            &response,
+            lifetime,
+            age,
+            &headers(&[("cache-control", "no-cache")])
+        ));
+
+        let stale = Duration::from_secs(130);
+        assert!(!is_fresh(&response, lifetime, stale, &none));
+        assert!(is_fresh(
+            &response,
+            lifetime,
+            stale,
+            &headers(&[("cache-control", "max-stale=60")])
+        ));
+        assert!(!is_fresh(
+            &response,
+            lifetime,
+            stale,
+            &headers(&[("cache-control", "max-stale=10")])
+        ));
+    }
+}
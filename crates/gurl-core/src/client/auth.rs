@@ -0,0 +1,498 @@
+// Authentication: Basic, Bearer and Digest (RFC 7617, 6750, 7616), plus
+// credentials looked up in a netrc file.
+//
+// Basic and Bearer are sent up front. Digest needs a challenge, so the first
+// request goes out without credentials and a 401 carrying a Digest
+// `WWW-Authenticate` is answered once with the computed response.
+
+use super::oauth::OAuth2;
+use super::sigv4::SigV4;
+use base64::prelude::*;
+use md5::Md5;
+use reqwest::Method;
+use reqwest::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+#[derive(Clone, PartialEq)]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+    Digest {
+        username: String,
+        password: String,
+    },
+    /// Bearer token from an OAuth2 token endpoint, fetched and cached by the client
+    OAuth2(OAuth2),
+    /// AWS Signature Version 4, computed for each attempt as it is sent
+    SigV4(SigV4),
+}
+
+// Credentials stay out of logs and panics
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::Basic { username, .. } => write!(f, "Basic({username}, ***)"),
+            Auth::Bearer(_) => write!(f, "Bearer(***)"),
+            Auth::Digest { username, .. } => write!(f, "Digest({username}, ***)"),
+            Auth::OAuth2(oauth) => write!(f, "OAuth2({})", oauth.name),
+            Auth::SigV4(signer) => write!(f, "SigV4({}, {})", signer.region, signer.service),
+        }
+    }
+}
+
+/// The serialized form of `Auth`: the credentials a request carries itself.
+/// OAuth2 and SigV4 come from the config file and the environment instead.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AuthData {
+    Basic {
+        username: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+    Bearer(String),
+    Digest {
+        username: String,
+        password: String,
+    },
+}
+
+impl Serialize for Auth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = match self.clone() {
+            Auth::Basic { username, password } => AuthData::Basic { username, password },
+            Auth::Bearer(token) => AuthData::Bearer(token),
+            Auth::Digest { username, password } => AuthData::Digest { username, password },
+            Auth::OAuth2(_) | Auth::SigV4(_) => {
+                return Err(ser::Error::custom(
+                    "OAuth2 and SigV4 credentials cannot be serialized",
+                ));
+            }
+        };
+        data.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Auth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match AuthData::deserialize(deserializer)? {
+            AuthData::Basic { username, password } => Auth::Basic { username, password },
+            AuthData::Bearer(token) => Auth::Bearer(token),
+            AuthData::Digest { username, password } => Auth::Digest { username, password },
+        })
+    }
+}
+
+impl Auth {
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Auth::Basic {
+            username: username.into(),
+            password: Some(password.into()),
+        }
+    }
+
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Auth::Bearer(token.into())
+    }
+
+    pub fn digest(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Auth::Digest {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// The Authorization header to send before any challenge. Digest has
+    /// none: it answers the server's challenge instead. Nor have OAuth2,
+    /// whose token the client fetches, and SigV4, which signs the finished
+    /// request.
+    pub fn header(&self) -> Option<HeaderValue> {
+        let value = match self {
+            Auth::Basic { username, password } => {
+                let credentials = format!("{username}:{}", password.as_deref().unwrap_or(""));
+                format!("Basic {}", BASE64_STANDARD.encode(credentials))
+            }
+            Auth::Bearer(token) => format!("Bearer {token}"),
+            Auth::Digest { .. } | Auth::OAuth2(_) | Auth::SigV4(_) => return None,
+        };
+        let mut value = HeaderValue::from_str(&value).ok()?;
+        value.set_sensitive(true);
+        Some(value)
+    }
+}
+
+pub(crate) fn bearer_header(token: &str) -> Result<HeaderValue, crate::Error> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+        .map_err(|_| crate::Error::Auth("access token is not a valid header value".to_string()))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Answer a Digest challenge from a 401 response, if it carries one.
+pub(crate) fn digest_authorization(
+    challenge_headers: &HeaderMap,
+    username: &str,
+    password: &str,
+    method: &Method,
+    url: &Url,
+) -> Option<HeaderValue> {
+    let cnonce = crate::hex(&fastrand::u64(..).to_be_bytes());
+    digest_with_cnonce(challenge_headers, username, password, method, url, &cnonce)
+}
+
+fn digest_with_cnonce(
+    challenge_headers: &HeaderMap,
+    username: &str,
+    password: &str,
+    method: &Method,
+    url: &Url,
+    cnonce: &str,
+) -> Option<HeaderValue> {
+    let challenge = challenge_headers
+        .get_all(WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find_map(DigestChallenge::parse)?;
+
+    let hash: fn(&str) -> String = match challenge.algorithm.to_ascii_uppercase().as_str() {
+        "MD5" | "MD5-SESS" => |s| crate::hex(&Md5::digest(s)),
+        "SHA-256" | "SHA-256-SESS" => |s| crate::hex(&Sha256::digest(s)),
+        _ => return None,
+    };
+    let mut uri = url.path().to_string();
+    if let Some(query) = url.query() {
+        uri.push('?');
+        uri.push_str(query);
+    }
+    let nc = "00000001";
+
+    let mut ha1 = hash(&format!("{username}:{}:{password}", challenge.realm));
+    if challenge.algorithm.to_ascii_lowercase().ends_with("-sess") {
+        ha1 = hash(&format!("{ha1}:{}:{cnonce}", challenge.nonce));
+    }
+    let ha2 = hash(&format!("{method}:{uri}"));
+    // Only qop=auth is supported; auth-int would need the request body hashed
+    let qop = challenge
+        .qop
+        .as_deref()
+        .map(|qop| qop.split(',').map(str::trim).any(|q| q == "auth"));
+    let response = match qop {
+        Some(true) => hash(&format!(
+            "{ha1}:{}:{nc}:{cnonce}:auth:{ha2}",
+            challenge.nonce
+        )),
+        Some(false) => return None,
+        None => hash(&format!("{ha1}:{}:{ha2}", challenge.nonce)),
+    };
+
+    let mut header = format!(
+        "Digest username={}, realm={}, nonce={}, uri={}, algorithm={}, response=\"{response}\"",
+        quoted(username),
+        quoted(&challenge.realm),
+        quoted(&challenge.nonce),
+        quoted(&uri),
+        challenge.algorithm
+    );
+    if qop.is_some() {
+        header.push_str(&format!(", qop=auth, nc={nc}, cnonce=\"{cnonce}\""));
+    }
+    if let Some(opaque) = &challenge.opaque {
+        header.push_str(&format!(", opaque={}", quoted(opaque)));
+    }
+    let mut value = HeaderValue::from_str(&header).ok()?;
+    value.set_sensitive(true);
+    Some(value)
+}
+
+/// An RFC 9110 quoted-string, the inverse of the unescaping in `auth_params`.
+fn quoted(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+#[derive(Debug, PartialEq)]
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    qop: Option<String>,
+    algorithm: String,
+}
+
+impl DigestChallenge {
+    fn parse(header: &str) -> Option<Self> {
+        let (scheme, params) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+        let mut challenge = DigestChallenge {
+            realm: String::new(),
+            nonce: String::new(),
+            opaque: None,
+            qop: None,
+            algorithm: "MD5".to_string(),
+        };
+        for (name, value) in auth_params(params) {
+            match name.to_ascii_lowercase().as_str() {
+                "realm" => challenge.realm = value,
+                "nonce" => challenge.nonce = value,
+                "opaque" => challenge.opaque = Some(value),
+                "qop" => challenge.qop = Some(value),
+                "algorithm" => challenge.algorithm = value,
+                _ => {}
+            }
+        }
+        (!challenge.nonce.is_empty()).then_some(challenge)
+    }
+}
+
+/// Split `a="x, y", b=z` into name/value pairs, honoring quoted strings.
+fn auth_params(params: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut rest = params.trim();
+    while let Some((name, after)) = rest.split_once('=') {
+        let name = name.trim().trim_start_matches(',').trim().to_string();
+        let after = after.trim_start();
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            (value, &quoted[end..])
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            (after[..end].trim().to_string(), &after[end..])
+        };
+        pairs.push((name, value));
+        rest = remaining.trim_start().trim_start_matches(',');
+    }
+    pairs
+}
+
+/// Credentials from a netrc file (`machine`, `login`, `password`, `default`).
+#[derive(Debug, Clone, Default)]
+pub struct Netrc {
+    machines: Vec<(String, NetrcEntry)>,
+    default: Option<NetrcEntry>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct NetrcEntry {
+    login: Option<String>,
+    password: Option<String>,
+}
+
+impl Netrc {
+    /// `$NETRC`, falling back to `~/.netrc`.
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("NETRC")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".netrc")))
+    }
+
+    /// Load the netrc file from its default location; a missing file is empty.
+    pub fn load_default() -> std::io::Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut netrc = Netrc::default();
+        // Which entry tokens currently apply to: a machine index or `default`
+        let mut current: Option<Option<usize>> = None;
+        let mut in_macdef = false;
+        for line in text.lines() {
+            // A macro definition runs until the next empty line
+            if in_macdef {
+                in_macdef = !line.trim().is_empty();
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                let entry = match current {
+                    Some(Some(i)) => Some(&mut netrc.machines[i].1),
+                    Some(None) => netrc.default.as_mut(),
+                    None => None,
+                };
+                match token {
+                    "machine" => {
+                        let Some(host) = tokens.next() else { break };
+                        netrc
+                            .machines
+                            .push((host.to_ascii_lowercase(), NetrcEntry::default()));
+                        current = Some(Some(netrc.machines.len() - 1));
+                    }
+                    "default" => {
+                        netrc.default = Some(NetrcEntry::default());
+                        current = Some(None);
+                    }
+                    "login" => {
+                        if let (Some(entry), Some(login)) = (entry, tokens.next()) {
+                            entry.login = Some(login.to_string());
+                        }
+                    }
+                    "password" => {
+                        if let (Some(entry), Some(password)) = (entry, tokens.next()) {
+                            entry.password = Some(password.to_string());
+                        }
+                    }
+                    "account" => {
+                        tokens.next();
+                    }
+                    "macdef" => {
+                        in_macdef = true;
+                        break;
+                    }
+                    _ if token.starts_with('#') => break,
+                    _ => {}
+                }
+            }
+        }
+        netrc
+    }
+
+    /// Basic credentials for `host`, from its `machine` entry or `default`.
+    pub fn lookup(&self, host: &str) -> Option<Auth> {
+        let host = host.to_ascii_lowercase();
+        let entry = self
+            .machines
+            .iter()
+            .find(|(machine, _)| *machine == host)
+            .map(|(_, entry)| entry)
+            .or(self.default.as_ref())?;
+        Some(Auth::Basic {
+            username: entry.login.clone()?,
+            password: entry.password.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_and_bearer_headers() {
+        assert_eq!(
+            Auth::basic("Aladdin", "open sesame").header().unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(Auth::bearer("t0k").header().unwrap(), "Bearer t0k");
+        assert!(Auth::digest("a", "b").header().is_none());
+        assert_eq!(format!("{:?}", Auth::bearer("secret")), "Bearer(***)");
+    }
+
+    #[test]
+    fn test_digest_rfc2617_example() {
+        // The worked example from RFC 2617 section 3.5
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static(
+                "Digest realm=\"testrealm@host.com\", qop=\"auth,auth-int\", \
+                 nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", \
+                 opaque=\"5ccc069c403ebaf9f0171e9517f40e41\"",
+            ),
+        );
+        let url = Url::parse("http://www.nowhere.org/dir/index.html").unwrap();
+        let value = digest_with_cnonce(
+            &headers,
+            "Mufasa",
+            "Circle Of Life",
+            &Method::GET,
+            &url,
+            "0a4f113b",
+        )
+        .unwrap();
+        let value = value.to_str().unwrap();
+        let params: std::collections::HashMap<_, _> =
+            auth_params(value.strip_prefix("Digest ").unwrap())
+                .into_iter()
+                .collect();
+        assert_eq!(params["uri"], "/dir/index.html");
+        assert_eq!(params["qop"], "auth");
+        assert_eq!(params["opaque"], "5ccc069c403ebaf9f0171e9517f40e41");
+        assert_eq!(params["nc"], "00000001");
+        assert_eq!(params["cnonce"], "0a4f113b");
+        assert_eq!(params["response"], "6629fae49393a05397450978507c4ef1");
+    }
+
+    #[test]
+    fn test_digest_quotes_parameters() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static(r#"Digest realm="a \"b\" realm", nonce="n""#),
+        );
+        let url = Url::parse("http://example.com/").unwrap();
+        let username = r#"Mu"fa\sa"#;
+        let value = digest_with_cnonce(&headers, username, "pw", &Method::GET, &url, "c").unwrap();
+        let value = value.to_str().unwrap();
+        assert!(value.starts_with(r#"Digest username="Mu\"fa\\sa", realm="a \"b\" realm""#));
+        let params: std::collections::HashMap<_, _> =
+            auth_params(value.strip_prefix("Digest ").unwrap())
+                .into_iter()
+                .collect();
+        assert_eq!(params["username"], username);
+        assert_eq!(params["realm"], r#"a "b" realm"#);
+    }
+
+    #[test]
+    fn test_netrc() {
+        let netrc = Netrc::parse(
+            "machine api.example.com login alice password s3cret\n\
+             macdef init\ncd /pub\n\n\
+             machine other.test\n  login bob\n  password pw # trailing comment\n\
+             default login anonymous password guest\n",
+        );
+        assert_eq!(
+            netrc.lookup("API.example.com"),
+            Some(Auth::basic("alice", "s3cret"))
+        );
+        assert_eq!(netrc.lookup("other.test"), Some(Auth::basic("bob", "pw")));
+        assert_eq!(
+            netrc.lookup("unknown.test"),
+            Some(Auth::basic("anonymous", "guest"))
+        );
+        assert_eq!(Netrc::parse("machine a login x").lookup("b"), None);
+    }
+}
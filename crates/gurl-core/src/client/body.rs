@@ -0,0 +1,200 @@
+// Reading response bodies within bounds.
+//
+// A body is read chunk by chunk rather than with `Response::bytes`, so that
+// neither a lying nor a missing Content-Length can make gurl buffer more
+// than it should. Two limits apply:
+//
+// - the request's `max_size` is a hard cap: a larger Content-Length is
+//   refused before reading, and crossing it mid-stream aborts the request;
+// - the client's memory limit bounds what is kept in memory. Past it, the
+//   body streams to a temp file; the envelope keeps (and converters see)
+//   only the first bytes, and reports the truncation and the file.
+
+use crate::output::envelope::Truncated;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+/// Bodies larger than this are spooled to disk unless the client says otherwise.
+pub(crate) const DEFAULT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+pub(crate) struct ReadBody {
+    /// The whole body, or its first `memory_limit` bytes when truncated
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) truncated: Option<Truncated>,
+}
+
+pub(crate) async fn read(
+    response: &mut reqwest::Response,
+    url: &Url,
+    max_size: Option<u64>,
+    memory_limit: usize,
+) -> Result<ReadBody, crate::Error> {
+    let too_large = |limit| crate::Error::BodyTooLarge {
+        url: url.to_string(),
+        limit,
+    };
+    if let (Some(limit), Some(length)) = (max_size, response.content_length())
+        && length > limit
+    {
+        return Err(too_large(limit));
+    }
+
+    let expected = response.content_length().unwrap_or(0) as usize;
+    let mut bytes = Vec::with_capacity(expected.min(memory_limit));
+    let mut size = 0u64;
+    let mut spool: Option<(PathBuf, tokio::fs::File)> = None;
+    let result = async {
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| super::request_error(&e))?
+        {
+            size += chunk.len() as u64;
+            if let Some(limit) = max_size
+                && size > limit
+            {
+                return Err(too_large(limit));
+            }
+            if spool.is_none() && bytes.len() + chunk.len() <= memory_limit {
+                bytes.extend_from_slice(&chunk);
+                continue;
+            }
+            // Over the memory limit: everything goes to the file from here on,
+            // starting with what was kept so far
+            let (path, file) = match &mut spool {
+                Some(spool) => spool,
+                None => {
+                    let (path, mut file) = temp_file().await?;
+                    file.write_all(&bytes)
+                        .await
+                        .map_err(|e| spool_error(&path, &e))?;
+                    spool.insert((path, file))
+                }
+            };
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| spool_error(path, &e))?;
+            let room = memory_limit.saturating_sub(bytes.len()).min(chunk.len());
+            bytes.extend_from_slice(&chunk[..room]);
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        if let Some((path, _)) = spool {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        return Err(e);
+    }
+    let truncated = match spool {
+        Some((path, mut file)) => {
+            file.flush().await.map_err(|e| spool_error(&path, &e))?;
+            Some(Truncated {
+                size,
+                kept: bytes.len(),
+                body_file: path,
+            })
+        }
+        None => None,
+    };
+    Ok(ReadBody { bytes, truncated })
+}
+
+/// A new file only the current user can read, under a name other users
+/// can't guess, so they can neither read the body nor plant the file first.
+async fn temp_file() -> Result<(PathBuf, tokio::fs::File), crate::Error> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = format!(
+        "gurl-body-{}-{}-{:016x}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        fastrand::u64(..)
+    );
+    let path = std::env::temp_dir().join(name);
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let file = options
+        .open(&path)
+        .await
+        .map_err(|e| spool_error(&path, &e))?;
+    Ok((path, file))
+}
+
+fn spool_error(path: &std::path::Path, e: &std::io::Error) -> crate::Error {
+    crate::Error::Request(format!("failed to write body to {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::test_server::{Reply, TestServer};
+
+    /// Serve `body` at `/big`, with a Content-Length of `length` if given
+    /// and otherwise none.
+    async fn serve(length: Option<usize>, body: Vec<u8>) -> Url {
+        let server = TestServer::start(move |_| {
+            let reply = Reply::new(200).body(body.clone()).without_length();
+            match length {
+                Some(length) => reply.header("content-length", &length.to_string()),
+                None => reply,
+            }
+        })
+        .await;
+        server.url("/big")
+    }
+
+    async fn get(url: &Url) -> reqwest::Response {
+        reqwest::get(url.clone()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_max_size() {
+        let url = serve(Some(5000), vec![b'x'; 5000]).await;
+        let result = read(&mut get(&url).await, &url, Some(4096), 1 << 20).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::BodyTooLarge { limit: 4096, .. })
+        ));
+
+        // Without a Content-Length the limit applies while streaming
+        let url = serve(None, vec![b'x'; 5000]).await;
+        let result = read(&mut get(&url).await, &url, Some(4096), 1 << 20).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::BodyTooLarge { limit: 4096, .. })
+        ));
+
+        let url = serve(None, vec![b'x'; 4096]).await;
+        let body = read(&mut get(&url).await, &url, Some(4096), 1 << 20)
+            .await
+            .unwrap();
+        assert_eq!(body.bytes.len(), 4096);
+        assert!(body.truncated.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_large_body_is_spooled() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let url = serve(None, data.clone()).await;
+        let body = read(&mut get(&url).await, &url, None, 2500).await.unwrap();
+        assert_eq!(body.bytes, data[..2500]);
+        let truncated = body.truncated.unwrap();
+        assert_eq!((truncated.size, truncated.kept), (10_000, 2500));
+        assert_eq!(std::fs::read(&truncated.body_file).unwrap(), data);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&truncated.body_file)
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(truncated.body_file).unwrap();
+    }
+}
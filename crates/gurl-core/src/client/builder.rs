@@ -0,0 +1,301 @@
+// Builder for library consumers who need more than `GurlClient::new()`.
+//
+// Transport options (user agent, timeouts, connection pool) end up in the
+// reqwest clients, which are rebuilt whenever a proxy, session or TLS setting
+// changes, so the client keeps them as `HttpOptions`. Everything else maps
+// onto the client's existing `with_*` methods or fields.
+
+use super::GurlClient;
+use super::middleware::MiddlewareChain;
+use super::proxy::ProxyRules;
+use crate::cache::HttpCache;
+use crate::client::{Cassette, LimitsConfig, Middleware, Netrc, ProxyConfig, TlsConfig};
+use crate::har::HarRecorder;
+use markitdown_rs::MarkItDown;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Settings applied to every reqwest client a `GurlClient` builds.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpOptions {
+    pub(crate) user_agent: String,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) pool_idle_timeout: Option<Duration>,
+    pub(crate) pool_max_idle_per_host: Option<usize>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("gurl/{}", env!("CARGO_PKG_VERSION")),
+            timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+        }
+    }
+}
+
+/// Configures a [`GurlClient`]. Unset options keep the defaults of
+/// `GurlClient::new()`.
+///
+/// ```no_run
+/// # fn main() -> Result<(), gurl_core::Error> {
+/// use std::time::Duration;
+///
+/// let client = gurl_core::GurlClient::builder()
+///     .user_agent("my-agent/1.0")
+///     .default_header("x-team", "search")
+///     .timeout(Duration::from_secs(30))
+///     .pool_max_idle_per_host(4)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct GurlClientBuilder {
+    options: HttpOptions,
+    default_headers: HeaderMap,
+    header_error: Option<String>,
+    proxy: Option<ProxyConfig>,
+    tls: TlsConfig,
+    converter: Option<MarkItDown>,
+    cache: Option<HttpCache>,
+    limits: LimitsConfig,
+    robots: bool,
+    netrc: Option<Netrc>,
+    middleware: MiddlewareChain,
+    memory_limit: Option<usize>,
+    har: Option<HarRecorder>,
+    cassette: Option<Cassette>,
+}
+
+impl GurlClientBuilder {
+    /// User-Agent sent with every request (default `gurl/<version>`).
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.options.user_agent = user_agent.into();
+        self
+    }
+
+    /// Header sent with every request that does not set it itself.
+    /// Invalid names or values are reported by `build`.
+    pub fn default_header(mut self, name: &str, value: &str) -> Self {
+        match (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                self.default_headers.append(name, value);
+            }
+            _ => {
+                self.header_error
+                    .get_or_insert_with(|| format!("invalid default header '{name}'"));
+            }
+        }
+        self
+    }
+
+    /// Headers sent with every request that does not set them itself.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers.extend(headers);
+        self
+    }
+
+    /// Limit on a whole request, from connecting to reading the last byte.
+    /// A request's own timeout takes precedence.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit on the wait for each read from the server.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.options.read_timeout = Some(timeout);
+        self
+    }
+
+    /// How long an idle pooled connection is kept open.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.options.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Idle connections kept per host; 0 disables pooling.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.options.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Proxies to use instead of the ones from the environment.
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Converter for non-JSON bodies, e.g. one with extra converters
+    /// registered through `MarkItDown::register`.
+    pub fn converter(mut self, converter: MarkItDown) -> Self {
+        self.converter = Some(converter);
+        self
+    }
+
+    pub fn cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn limits(mut self, limits: LimitsConfig) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn robots(mut self, enabled: bool) -> Self {
+        self.robots = enabled;
+        self
+    }
+
+    pub fn netrc(mut self, netrc: Netrc) -> Self {
+        self.netrc = Some(netrc);
+        self
+    }
+
+    /// Run `middleware` around every request. The first one added sees the
+    /// request first and the response last.
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Largest body kept in memory (default 16 MiB). Larger bodies are
+    /// streamed to a temp file and reported in `Content::truncated`.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Record every request and response into a HAR archive.
+    pub fn har(mut self, har: HarRecorder) -> Self {
+        self.har = Some(har);
+        self
+    }
+
+    /// Record interactions into a cassette, or replay them from one.
+    pub fn cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    pub fn build(self) -> Result<GurlClient, crate::Error> {
+        if let Some(e) = self.header_error {
+            return Err(crate::Error::Config(e));
+        }
+        let proxy = match &self.proxy {
+            Some(proxy) => ProxyRules::new(proxy)?,
+            // Like reqwest's own detection, an unparseable proxy variable is ignored
+            None => ProxyRules::new(&ProxyConfig::from_env()).unwrap_or_default(),
+        };
+        let mut client = GurlClient::with_transport(self.options, Arc::new(proxy))
+            .map_err(|e| crate::Error::Request(e.to_string()))?;
+        if self.tls != TlsConfig::default() {
+            client = client.with_tls(self.tls)?;
+        }
+        if let Some(converter) = self.converter {
+            client.converter = converter;
+        }
+        if let Some(cache) = self.cache {
+            client = client.with_cache(cache);
+        }
+        if let Some(netrc) = self.netrc {
+            client = client.with_netrc(netrc);
+        }
+        client.default_headers = self.default_headers;
+        client.middleware = self.middleware;
+        if let Some(limit) = self.memory_limit {
+            client.memory_limit = limit;
+        }
+        client.har = self.har;
+        client.cassette = self.cassette;
+        Ok(client.with_limits(self.limits).with_robots(self.robots))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::GurlRequest;
+    use crate::client::test_server::{Reply, TestServer};
+    use markitdown_rs::converter::{ConversionResult, DocumentConverter, StreamInfo};
+
+    struct Shout;
+
+    impl DocumentConverter for Shout {
+        fn name(&self) -> &'static str {
+            "shout"
+        }
+
+        fn accepts(&self, stream_info: &StreamInfo) -> bool {
+            stream_info.mime_type.as_deref() == Some("application/x-shout")
+        }
+
+        fn convert(&self, input: &[u8], _: &StreamInfo) -> markitdown_rs::Result<ConversionResult> {
+            Ok(ConversionResult::new(
+                String::from_utf8_lossy(input).to_uppercase(),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_invalid_default_header() {
+        let built = GurlClient::builder()
+            .default_header("bad header", "x")
+            .build();
+        assert!(matches!(built, Err(crate::Error::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_builder_options_reach_the_request() {
+        let server = TestServer::start(|_| {
+            Reply::new(200)
+                .header("content-type", "application/x-shout")
+                .body("hello")
+        })
+        .await;
+
+        let mut converter = MarkItDown::new();
+        converter.register(Box::new(Shout));
+        let client = GurlClient::builder()
+            .user_agent("agent/1.0")
+            .default_header("x-team", "search")
+            .default_header("x-trace", "default")
+            .timeout(Duration::from_secs(5))
+            .pool_max_idle_per_host(0)
+            .converter(converter)
+            .build()
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-trace", HeaderValue::from_static("mine"));
+        let request = GurlRequest::get(server.url("/")).with_headers(headers);
+        let response = client.execute(request).await.unwrap();
+
+        let received = &server.received()[0];
+        assert_eq!(received.header("user-agent"), Some("agent/1.0"));
+        assert_eq!(received.header("x-team"), Some("search"));
+        assert_eq!(received.header("x-trace"), Some("mine"));
+        assert!(!received.head.contains("default"));
+        assert_eq!(response.content.body, "HELLO");
+    }
+}
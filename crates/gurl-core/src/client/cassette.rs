@@ -0,0 +1,487 @@
+// Record and replay of HTTP interactions, for deterministic tests.
+//
+// A cassette is a directory with one JSON file per interaction: a request as
+// sent (every redirect hop and retry is its own interaction) and the
+// response it got, body included. File names sort in recording order, and
+// recording into an existing cassette adds to it, so several gurl processes
+// can record into the same one.
+//
+// In replay no request reaches the network. Each request is answered by the
+// first interaction that matches it and has not been served yet, or by the
+// last matching one once all have been, so a recorded 503 then 200 replays
+// in that order and polling keeps getting the final answer. A request that
+// matches nothing fails with `Error::NotRecorded`.
+//
+// Recorded bodies are held in memory, so a response whose body is over the
+// client's memory limit fails instead of being recorded.
+//
+// Credentials are not written to disk: request headers in
+// `har::DEFAULT_REDACTED` are stored redacted unless the matcher compares
+// them.
+
+use super::SendError;
+use super::response::HeaderField;
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
+use reqwest::ResponseBuilderExt;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Send requests and store every interaction
+    Record,
+    /// Answer requests from the stored interactions only
+    Replay,
+}
+
+/// What a request must share with a recorded one to be answered by it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matcher {
+    pub method: bool,
+    /// The full URL, query included
+    pub url: bool,
+    /// Buffered bodies only: streamed and multipart bodies are not stored
+    pub body: bool,
+    /// Request headers whose values must be equal
+    pub headers: Vec<String>,
+}
+
+impl Default for Matcher {
+    fn default() -> Self {
+        Self {
+            method: true,
+            url: true,
+            body: false,
+            headers: Vec::new(),
+        }
+    }
+}
+
+impl Matcher {
+    fn matches(&self, recorded: &RecordedRequest, request: &RecordedRequest) -> bool {
+        let values = |request: &RecordedRequest, name: &str| -> Vec<Vec<u8>> {
+            request
+                .headers
+                .iter()
+                .filter(|field| field.name.eq_ignore_ascii_case(name))
+                .map(HeaderField::bytes)
+                .collect()
+        };
+        (!self.method || recorded.method == request.method)
+            && (!self.url || recorded.url == request.url)
+            && (!self.body || recorded.body == request.body)
+            && self
+                .headers
+                .iter()
+                .all(|name| values(recorded, name) == values(request, name))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub recorded: DateTime<Utc>,
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<HeaderField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<RecordedBody>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<HeaderField>,
+    pub body: RecordedBody,
+}
+
+/// A body, as text when it is UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedBody {
+    Text(String),
+    Base64(String),
+}
+
+impl RecordedBody {
+    fn new(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Self::Text(text.to_string()),
+            Err(_) => Self::Base64(BASE64_STANDARD.encode(bytes)),
+        }
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Text(text) => text.as_bytes().to_vec(),
+            Self::Base64(encoded) => BASE64_STANDARD.decode(encoded).unwrap_or_default(),
+        }
+    }
+}
+
+impl RecordedRequest {
+    pub(super) fn new(request: &reqwest::Request) -> Self {
+        Self {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers: HeaderField::list(request.headers()),
+            body: request
+                .body()
+                .and_then(reqwest::Body::as_bytes)
+                .map(RecordedBody::new),
+        }
+    }
+}
+
+/// A directory of recorded interactions, in record or replay mode.
+#[derive(Debug)]
+pub struct Cassette {
+    dir: PathBuf,
+    mode: CassetteMode,
+    matcher: Matcher,
+    /// Loaded in replay mode
+    interactions: Vec<Interaction>,
+    /// Which interactions have answered a request
+    served: Mutex<Vec<bool>>,
+}
+
+impl Cassette {
+    /// Record into `dir`, creating it if needed and keeping what is there.
+    pub fn record(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            mode: CassetteMode::Record,
+            matcher: Matcher::default(),
+            interactions: Vec::new(),
+            served: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Replay the interactions recorded in `dir`.
+    pub fn replay(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        let interactions = paths
+            .iter()
+            .map(|path| {
+                let bytes = std::fs::read(path)?;
+                serde_json::from_slice(&bytes).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: {e}", path.display()),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<Interaction>>>()?;
+        Ok(Self {
+            dir,
+            mode: CassetteMode::Replay,
+            matcher: Matcher::default(),
+            served: Mutex::new(vec![false; interactions.len()]),
+            interactions,
+        })
+    }
+
+    /// How requests are matched to recorded ones (method and URL by default).
+    /// Credentials the matcher compares are stored as sent.
+    pub fn matching(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The recorded response for `request`.
+    pub(super) fn play(
+        &self,
+        request: &reqwest::Request,
+    ) -> Result<reqwest::Response, crate::Error> {
+        let recorded = RecordedRequest::new(request);
+        let mut served = self.served.lock().unwrap();
+        let matching: Vec<usize> = (0..self.interactions.len())
+            .filter(|&i| {
+                self.matcher
+                    .matches(&self.interactions[i].request, &recorded)
+            })
+            .collect();
+        let index = matching
+            .iter()
+            .find(|&&i| !served[i])
+            .or(matching.last())
+            .copied()
+            .ok_or_else(|| crate::Error::NotRecorded {
+                method: recorded.method.clone(),
+                url: recorded.url.clone(),
+                cassette: self.dir.display().to_string(),
+            })?;
+        served[index] = true;
+
+        let response = &self.interactions[index].response;
+        let mut builder = http::Response::builder()
+            .status(response.status)
+            .url(request.url().clone());
+        for field in &response.headers {
+            builder = builder.header(field.name.as_str(), field.bytes());
+        }
+        let response = builder.body(response.body.bytes()).map_err(|e| {
+            crate::Error::Request(format!(
+                "invalid recorded response in {}: {e}",
+                self.dir.display()
+            ))
+        })?;
+        Ok(response.into())
+    }
+
+    /// Store `request` and the response it got, whose body is read for it.
+    /// Returns an equivalent response to carry on with.
+    /// Both limits are checked against Content-Length before reading, and
+    /// again as the body arrives.
+    pub(super) async fn store(
+        &self,
+        mut request: RecordedRequest,
+        mut response: reqwest::Response,
+        max_size: Option<u64>,
+        memory_limit: usize,
+    ) -> Result<reqwest::Response, SendError> {
+        let status = response.status();
+        let version = response.version();
+        let url = response.url().clone();
+        let headers = response.headers().clone();
+        let check = |size: u64| -> Result<(), crate::Error> {
+            if let Some(limit) = max_size
+                && size > limit
+            {
+                return Err(crate::Error::BodyTooLarge {
+                    url: url.to_string(),
+                    limit,
+                });
+            }
+            if size > memory_limit as u64 {
+                return Err(crate::Error::Request(format!(
+                    "response body from {url} is over the {memory_limit}-byte memory limit \
+                     and cannot be recorded to cassette {}",
+                    self.dir.display()
+                )));
+            }
+            Ok(())
+        };
+        if let Some(length) = response.content_length() {
+            check(length)?;
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            check((body.len() + chunk.len()) as u64)?;
+            body.extend_from_slice(&chunk);
+        }
+
+        for field in &mut request.headers {
+            if crate::har::DEFAULT_REDACTED.contains(&field.name.as_str())
+                && !self
+                    .matcher
+                    .headers
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&field.name))
+            {
+                field.value = "[REDACTED]".to_string();
+                field.value_base64 = None;
+            }
+        }
+        let interaction = Interaction {
+            recorded: Utc::now(),
+            request,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers: HeaderField::list(&headers),
+                body: RecordedBody::new(&body),
+            },
+        };
+        self.write(&interaction).map_err(|e| {
+            SendError::Other(crate::Error::Request(format!(
+                "failed to write to cassette {}: {e}",
+                self.dir.display()
+            )))
+        })?;
+
+        let mut builder = http::Response::builder()
+            .status(status)
+            .version(version)
+            .url(url);
+        if let Some(map) = builder.headers_mut() {
+            *map = headers;
+        }
+        let response = builder
+            .body(body)
+            .map_err(|e| SendError::Other(crate::Error::Request(e.to_string())))?;
+        Ok(response.into())
+    }
+
+    fn write(&self, interaction: &Interaction) -> io::Result<()> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        // Sorts by time, then by order within a process
+        let name = format!(
+            "{:013}-{}-{:04}",
+            interaction.recorded.timestamp_millis(),
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let tmp = self.dir.join(format!("{name}.tmp"));
+        std::fs::write(&tmp, serde_json::to_vec_pretty(interaction)?)?;
+        std::fs::rename(&tmp, self.dir.join(format!("{name}.json")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GurlClient;
+    use crate::client::GurlRequest;
+    use crate::client::test_server::{Reply, TestServer};
+
+    /// Answer each request with the number of requests served so far.
+    async fn serve() -> TestServer {
+        let mut calls = 0;
+        TestServer::start(move |_| {
+            calls += 1;
+            Reply::text(format!("call {calls}"))
+        })
+        .await
+    }
+
+    async fn body(client: &GurlClient, url: &str) -> Result<String, crate::Error> {
+        let response = client
+            .execute(GurlRequest::get(url.parse().unwrap()))
+            .await?;
+        Ok(String::from_utf8(response.content.raw_body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let server = serve().await;
+        let url = server.url("/items").to_string();
+        let dir = std::env::temp_dir().join(format!("gurl-cassette-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let client = GurlClient::new()
+            .unwrap()
+            .with_cassette(Cassette::record(&dir).unwrap());
+        assert_eq!(body(&client, &url).await.unwrap(), "call 1");
+        assert_eq!(body(&client, &url).await.unwrap(), "call 2");
+
+        // In recording order, then the last one again
+        let client = GurlClient::new()
+            .unwrap()
+            .with_cassette(Cassette::replay(&dir).unwrap());
+        for expected in ["call 1", "call 2", "call 2"] {
+            assert_eq!(body(&client, &url).await.unwrap(), expected);
+        }
+        let missing = body(&client, server.url("/other").as_str()).await;
+        assert!(matches!(missing, Err(crate::Error::NotRecorded { .. })));
+
+        // The server has seen only the recorded requests
+        assert_eq!(server.received().len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_oversized_bodies_are_not_recorded() {
+        let server = TestServer::start(|received| {
+            let reply = Reply::text(vec![b'x'; 5000]);
+            if received.target == "/unsized" {
+                reply.without_length()
+            } else {
+                reply
+            }
+        })
+        .await;
+        let dir = std::env::temp_dir().join(format!("gurl-cassette-big-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let client = GurlClient::builder()
+            .memory_limit(4096)
+            .build()
+            .unwrap()
+            .with_cassette(Cassette::record(&dir).unwrap());
+
+        let capped = GurlRequest::get(server.url("/sized")).with_max_size(1000);
+        let result = client.execute(capped).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::BodyTooLarge { limit: 1000, .. })
+        ));
+        // Refused on Content-Length, or mid-stream without one
+        for path in ["/sized", "/unsized"] {
+            let result = body(&client, server.url(path).as_str()).await;
+            let Err(crate::Error::Request(message)) = result else {
+                panic!("{path}: {result:?}");
+            };
+            assert!(message.contains("memory limit"), "{message}");
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_matcher() {
+        let request = |method: &str, url: &str, token: &str| RecordedRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: vec![HeaderField {
+                name: "x-token".to_string(),
+                value: token.to_string(),
+                value_base64: None,
+            }],
+            body: Some(RecordedBody::new(b"q=1")),
+        };
+        let recorded = request("GET", "https://api.example.com/a", "t1");
+        let matcher = Matcher::default();
+        assert!(matcher.matches(
+            &recorded,
+            &request("GET", "https://api.example.com/a", "t2")
+        ));
+        assert!(!matcher.matches(
+            &recorded,
+            &request("POST", "https://api.example.com/a", "t1")
+        ));
+        assert!(!matcher.matches(
+            &recorded,
+            &request("GET", "https://api.example.com/b", "t1")
+        ));
+
+        let matcher = Matcher {
+            url: false,
+            headers: vec!["X-Token".to_string()],
+            ..Matcher::default()
+        };
+        assert!(matcher.matches(
+            &recorded,
+            &request("GET", "https://api.example.com/b", "t1")
+        ));
+        assert!(!matcher.matches(
+            &recorded,
+            &request("GET", "https://api.example.com/a", "t2")
+        ));
+    }
+}
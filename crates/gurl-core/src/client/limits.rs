@@ -0,0 +1,509 @@
+// Per-host politeness: token-bucket rate limiting and in-flight caps.
+//
+// Limits are enforced per host and shared by every `execute` call on the
+// same `GurlClient`. Each host gets a token bucket (sustained rate plus a
+// burst allowance) and optionally a semaphore bounding concurrent requests.
+//
+// The limiter also adapts to what the server tells it: a 429 pauses the host
+// for the Retry-After period (at most the retry policy's max delay) and
+// halves its rate, which then recovers gradually with successful responses;
+// `RateLimit-*` headers announcing an exhausted quota pause the host until
+// the quota resets. Hosts whose state is back to that of a fresh one are
+// forgotten once many hosts are tracked.
+
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// Pause applied after a 429 that carries no Retry-After.
+const DEFAULT_429_PAUSE: Duration = Duration::from_secs(1);
+
+/// An adapted rate never drops below this fraction of the configured one.
+const MIN_RATE_FACTOR: f64 = 1.0 / 16.0;
+
+/// Past this many tracked hosts, hosts at rest are dropped before adding another.
+const MAX_IDLE_HOSTS: usize = 256;
+
+/// Limits for one host. Unset fields fall back to the global defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostLimits {
+    /// Sustained request rate; unlimited when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+    /// Requests that may be sent back to back before the rate applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    /// Maximum concurrent requests; unlimited when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<usize>,
+}
+
+impl HostLimits {
+    fn or(&self, fallback: &HostLimits) -> HostLimits {
+        HostLimits {
+            requests_per_second: self.requests_per_second.or(fallback.requests_per_second),
+            burst: self.burst.or(fallback.burst),
+            max_in_flight: self.max_in_flight.or(fallback.max_in_flight),
+        }
+    }
+}
+
+/// Global politeness defaults plus per-domain overrides.
+///
+/// A domain entry also applies to its subdomains, so `example.com` covers
+/// `api.example.com` unless that host has an entry of its own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    #[serde(flatten)]
+    pub default: HostLimits,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hosts: HashMap<String, HostLimits>,
+}
+
+impl LimitsConfig {
+    pub fn with_default(mut self, limits: HostLimits) -> Self {
+        self.default = limits;
+        self
+    }
+
+    pub fn with_host(mut self, domain: impl Into<String>, limits: HostLimits) -> Self {
+        self.hosts
+            .insert(domain.into().to_ascii_lowercase(), limits);
+        self
+    }
+
+    /// Effective limits for `host`, from the most specific matching domain.
+    pub fn for_host(&self, host: &str) -> HostLimits {
+        let host = host.to_ascii_lowercase();
+        let mut domain = host.as_str();
+        loop {
+            if let Some(limits) = self.hosts.get(domain) {
+                return limits.or(&self.default);
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return self.default.clone(),
+            }
+        }
+    }
+}
+
+/// Shared limiter state for every host a client has talked to.
+#[derive(Default)]
+pub(crate) struct HostLimiter {
+    config: LimitsConfig,
+    hosts: Mutex<HashMap<String, Arc<HostState>>>,
+}
+
+/// Held for the duration of a request; releases the in-flight slot on drop.
+pub(crate) struct Permit {
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+struct HostState {
+    slots: Option<Arc<Semaphore>>,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Configured rate; `None` means unlimited
+    rate: Option<f64>,
+    /// Current rate after adapting to 429s
+    current_rate: Option<f64>,
+    capacity: f64,
+    /// May go negative: each waiter reserves a token ahead of time
+    tokens: f64,
+    refilled: Instant,
+    /// No request may start before this (server asked us to back off)
+    paused_until: Option<Instant>,
+    /// Minimum spacing between request starts (robots.txt Crawl-delay)
+    min_interval: Option<Duration>,
+    next_start: Option<Instant>,
+}
+
+impl HostLimiter {
+    pub(crate) fn new(config: LimitsConfig) -> Self {
+        Self {
+            config,
+            hosts: Mutex::default(),
+        }
+    }
+
+    fn state(&self, host: &str) -> Arc<HostState> {
+        let host = host.to_ascii_lowercase();
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        if hosts.len() >= MAX_IDLE_HOSTS && !hosts.contains_key(&host) {
+            let now = Instant::now();
+            hosts.retain(|_, state| !state.at_rest(now));
+        }
+        hosts
+            .entry(host)
+            .or_insert_with_key(|host| Arc::new(HostState::new(&self.config.for_host(host))))
+            .clone()
+    }
+
+    /// Wait until a request to `host` may be sent.
+    pub(crate) async fn acquire(&self, host: &str) -> Permit {
+        let state = self.state(host);
+        // Take the in-flight slot first so queued requests don't burn tokens
+        let slot = match &state.slots {
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        let wait = state.bucket().reserve(Instant::now());
+        if let Some(wait) = wait {
+            tokio::time::sleep_until(wait).await;
+        }
+        Permit { _slot: slot }
+    }
+
+    /// Space requests to `host` at least `delay` apart.
+    pub(crate) fn set_crawl_delay(&self, host: &str, delay: Duration) {
+        let state = self.state(host);
+        let mut bucket = state.bucket();
+        bucket.min_interval = bucket.min_interval.max(Some(delay));
+    }
+
+    /// Adapt the host's limits to a response. A 429 or an exhausted quota
+    /// pauses the host for at most `max_pause`, however long the server asks for.
+    pub(crate) fn observe(
+        &self,
+        host: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        max_pause: Duration,
+    ) {
+        let state = self.state(host);
+        let mut bucket = state.bucket();
+        let now = Instant::now();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let pause = super::retry::retry_after(headers).unwrap_or(DEFAULT_429_PAUSE);
+            bucket.pause_for(now, pause.min(max_pause));
+            bucket.slow_down();
+        } else {
+            bucket.speed_up();
+        }
+        if let Some(quota) = RateLimitHeaders::parse(headers)
+            && quota.remaining == 0
+        {
+            bucket.pause_for(now, quota.reset.min(max_pause));
+        }
+    }
+}
+
+impl HostState {
+    fn new(limits: &HostLimits) -> Self {
+        let capacity = limits.burst.unwrap_or(1).max(1) as f64;
+        Self {
+            slots: limits
+                .max_in_flight
+                .map(|n| Arc::new(Semaphore::new(n.max(1)))),
+            bucket: Mutex::new(Bucket {
+                rate: limits.requests_per_second.filter(|r| *r > 0.0),
+                current_rate: limits.requests_per_second.filter(|r| *r > 0.0),
+                capacity,
+                tokens: capacity,
+                refilled: Instant::now(),
+                paused_until: None,
+                min_interval: None,
+                next_start: None,
+            }),
+        }
+    }
+
+    fn bucket(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether forgetting this host would change nothing: no request holds or
+    /// waits for it and its bucket is back to the state of a fresh one.
+    fn at_rest(self: &Arc<Self>, now: Instant) -> bool {
+        Arc::strong_count(self) == 1
+            && self
+                .slots
+                .as_ref()
+                .is_none_or(|slots| Arc::strong_count(slots) == 1)
+            && self.bucket().at_rest(now)
+    }
+}
+
+impl Bucket {
+    /// Reserve a token, returning when the caller may proceed if not now.
+    fn reserve(&mut self, now: Instant) -> Option<Instant> {
+        let mut ready = self.paused_until.unwrap_or(now).max(now);
+        if let Some(rate) = self.current_rate {
+            let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate).min(self.capacity);
+            self.refilled = now;
+            self.tokens -= 1.0;
+            if self.tokens < 0.0 {
+                ready = ready.max(now + Duration::from_secs_f64(-self.tokens / rate));
+            }
+        }
+        if let Some(interval) = self.min_interval {
+            ready = ready.max(self.next_start.unwrap_or(now));
+            self.next_start = Some(ready + interval);
+        }
+        (ready > now).then_some(ready)
+    }
+
+    fn at_rest(&self, now: Instant) -> bool {
+        let refilled = match self.current_rate {
+            Some(rate) => {
+                let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+                self.tokens + elapsed * rate >= self.capacity
+            }
+            None => true,
+        };
+        refilled
+            && self.current_rate == self.rate
+            && self.paused_until.is_none_or(|until| until <= now)
+            && self.next_start.is_none_or(|next| next <= now)
+    }
+
+    fn pause_for(&mut self, now: Instant, pause: Duration) {
+        if let Some(until) = now.checked_add(pause) {
+            self.paused_until = self.paused_until.max(Some(until));
+        }
+    }
+
+    /// Multiplicative decrease after a 429.
+    fn slow_down(&mut self) {
+        if let (Some(rate), Some(current)) = (self.rate, self.current_rate) {
+            self.current_rate = Some((current / 2.0).max(rate * MIN_RATE_FACTOR));
+        }
+    }
+
+    /// Additive increase back towards the configured rate.
+    fn speed_up(&mut self) {
+        if let (Some(rate), Some(current)) = (self.rate, self.current_rate) {
+            self.current_rate = Some((current + rate / 10.0).min(rate));
+        }
+    }
+}
+
+/// Quota advertised by `RateLimit-*` / `X-RateLimit-*` headers, or by the
+/// structured `RateLimit` header (`limit=100, remaining=0, reset=30` or
+/// `"default";r=0;t=30`).
+#[derive(Debug, PartialEq)]
+struct RateLimitHeaders {
+    remaining: u64,
+    reset: Duration,
+}
+
+impl RateLimitHeaders {
+    fn parse(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+        };
+        let (remaining, reset) = match get("ratelimit") {
+            Some(structured) => {
+                let mut remaining = None;
+                let mut reset = None;
+                for param in structured.split([',', ';']) {
+                    match param.trim().split_once('=') {
+                        Some(("remaining" | "r", v)) => remaining = v.trim().parse().ok(),
+                        Some(("reset" | "t", v)) => reset = v.trim().parse().ok(),
+                        _ => {}
+                    }
+                }
+                (remaining?, reset.unwrap_or(0))
+            }
+            None => {
+                let remaining = get("ratelimit-remaining").or(get("x-ratelimit-remaining"))?;
+                let reset = get("ratelimit-reset").or(get("x-ratelimit-reset"));
+                (
+                    remaining.parse().ok()?,
+                    reset.and_then(|r| r.parse().ok()).unwrap_or(0),
+                )
+            }
+        };
+        Some(Self {
+            remaining,
+            reset: reset_delay(reset),
+        })
+    }
+}
+
+/// Reset values are usually seconds from now, but some APIs (GitHub's
+/// `X-RateLimit-Reset`) send a Unix timestamp instead.
+fn reset_delay(reset: u64) -> Duration {
+    const UNIX_TIMESTAMP_THRESHOLD: u64 = 1_000_000_000;
+    if reset >= UNIX_TIMESTAMP_THRESHOLD {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        Duration::from_secs(reset.saturating_sub(now))
+    } else {
+        Duration::from_secs(reset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_domain_overrides() {
+        let config = LimitsConfig::default()
+            .with_default(HostLimits {
+                requests_per_second: Some(5.0),
+                max_in_flight: Some(4),
+                ..Default::default()
+            })
+            .with_host(
+                "example.com",
+                HostLimits {
+                    requests_per_second: Some(1.0),
+                    ..Default::default()
+                },
+            );
+        let sub = config.for_host("API.example.com");
+        assert_eq!(sub.requests_per_second, Some(1.0));
+        assert_eq!(sub.max_in_flight, Some(4));
+        assert_eq!(
+            config.for_host("example.org").requests_per_second,
+            Some(5.0)
+        );
+        assert_eq!(
+            config.for_host("notexample.com").requests_per_second,
+            Some(5.0)
+        );
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let state = HostState::new(&HostLimits {
+            requests_per_second: Some(2.0),
+            burst: Some(2),
+            max_in_flight: None,
+        });
+        let mut bucket = state.bucket();
+        let now = Instant::now();
+        assert_eq!(bucket.reserve(now), None);
+        assert_eq!(bucket.reserve(now), None);
+        assert_eq!(bucket.reserve(now), Some(now + Duration::from_millis(500)));
+        assert_eq!(bucket.reserve(now), Some(now + Duration::from_millis(1000)));
+
+        bucket.slow_down();
+        assert_eq!(bucket.current_rate, Some(1.0));
+        bucket.speed_up();
+        assert!(bucket.current_rate.is_some_and(|r| (r - 1.2).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("ratelimit-remaining", HeaderValue::from_static("0"));
+        headers.insert("ratelimit-reset", HeaderValue::from_static("30"));
+        assert_eq!(
+            RateLimitHeaders::parse(&headers),
+            Some(RateLimitHeaders {
+                remaining: 0,
+                reset: Duration::from_secs(30)
+            })
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "ratelimit",
+            HeaderValue::from_static("\"default\";r=5;t=10"),
+        );
+        let quota = RateLimitHeaders::parse(&headers).unwrap();
+        assert_eq!((quota.remaining, quota.reset.as_secs()), (5, 10));
+
+        assert_eq!(RateLimitHeaders::parse(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_pause_after_429() {
+        let limiter = HostLimiter::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("3"));
+        let max_pause = Duration::from_secs(30);
+        limiter.observe(
+            "example.com",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            max_pause,
+        );
+
+        let now = Instant::now();
+        let ready = limiter.state("example.com").bucket().reserve(now);
+        assert!(ready.is_some_and(|at| at >= now + Duration::from_secs(2)));
+        assert_eq!(limiter.state("example.org").bucket().reserve(now), None);
+
+        headers.insert("retry-after", HeaderValue::from_static("86400"));
+        limiter.observe(
+            "example.net",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            max_pause,
+        );
+        let ready = limiter.state("example.net").bucket().reserve(now);
+        assert!(ready.is_some_and(|at| at <= Instant::now() + max_pause));
+    }
+
+    #[test]
+    fn test_huge_rate_limit_reset_is_capped() {
+        let limiter = HostLimiter::default();
+        let max_pause = Duration::from_secs(30);
+        for reset in ["18446744073709551615", "999999999", "4102444800"] {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-ratelimit-remaining", HeaderValue::from_static("0"));
+            headers.insert("x-ratelimit-reset", HeaderValue::from_str(reset).unwrap());
+            limiter.observe(reset, StatusCode::OK, &headers, max_pause);
+
+            let now = Instant::now();
+            let ready = limiter.state(reset).bucket().reserve(now);
+            assert!(
+                ready.is_some_and(|at| at <= Instant::now() + max_pause),
+                "{reset}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_idle_hosts_are_evicted() {
+        let limiter = HostLimiter::default();
+        let busy = limiter.state("busy.example");
+        limiter.observe(
+            "paused.example",
+            StatusCode::TOO_MANY_REQUESTS,
+            &HeaderMap::new(),
+            Duration::from_secs(30),
+        );
+        for i in 0..MAX_IDLE_HOSTS {
+            limiter.state(&format!("host{i}.example"));
+        }
+        let hosts = limiter.hosts.lock().unwrap();
+        assert!(hosts.len() < MAX_IDLE_HOSTS);
+        assert!(hosts.contains_key("busy.example"));
+        assert!(hosts.contains_key("paused.example"));
+        drop(busy);
+    }
+
+    #[test]
+    fn test_crawl_delay_spacing() {
+        let limiter = HostLimiter::default();
+        limiter.set_crawl_delay("example.com", Duration::from_secs(2));
+        let state = limiter.state("example.com");
+        let now = Instant::now();
+        assert_eq!(state.bucket().reserve(now), None);
+        assert_eq!(
+            state.bucket().reserve(now),
+            Some(now + Duration::from_secs(2))
+        );
+        assert_eq!(
+            state.bucket().reserve(now),
+            Some(now + Duration::from_secs(4))
+        );
+    }
+}
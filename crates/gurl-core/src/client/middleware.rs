@@ -0,0 +1,244 @@
+// Hooks around `GurlClient::execute` for behavior that belongs to the
+// embedding application rather than to gurl: signing, auditing, rewriting
+// URLs to internal mirrors, post-processing responses.
+//
+// Middleware runs once per `execute` call, outside the retry, redirect and
+// cache machinery: `before_request` sees the request as the caller built it
+// (before session headers, netrc or OAuth2 are applied) and `after_response`
+// sees the finished envelope, whether it came from the network or the cache.
+// Hooks run in registration order on the way in and in reverse on the way
+// out, so the first middleware registered wraps all the others.
+
+use crate::client::GurlRequest;
+use crate::output::envelope::{GurlResponse, RequestMeta};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::sync::Arc;
+use url::Url;
+
+pub trait Middleware: Send + Sync {
+    /// Inspect or change a request before it is sent. An error aborts the
+    /// request; it is passed to `on_error` and returned by `execute`.
+    fn before_request(&self, _req: &mut GurlRequest) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    /// Inspect or change a response before `execute` returns it. An error
+    /// replaces the response.
+    fn after_response(&self, _response: &mut GurlResponse) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    /// Observe a failed request.
+    fn on_error(&self, _request: &RequestMeta, _error: &crate::Error) {}
+}
+
+/// The middleware registered on a client, in order.
+#[derive(Clone, Default)]
+pub(crate) struct MiddlewareChain(Vec<Arc<dyn Middleware>>);
+
+impl MiddlewareChain {
+    pub(crate) fn push(&mut self, middleware: Arc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    pub(crate) fn before_request(&self, req: &mut GurlRequest) -> Result<(), crate::Error> {
+        self.0.iter().try_for_each(|m| m.before_request(req))
+    }
+
+    pub(crate) fn after_response(&self, response: &mut GurlResponse) -> Result<(), crate::Error> {
+        self.0
+            .iter()
+            .rev()
+            .try_for_each(|m| m.after_response(response))
+    }
+
+    pub(crate) fn on_error(&self, request: &RequestMeta, error: &crate::Error) {
+        self.0.iter().rev().for_each(|m| m.on_error(request, error));
+    }
+}
+
+/// Logs one line per request and response, to stderr or a custom sink.
+pub struct Logger {
+    sink: Box<dyn Fn(&str) + Send + Sync>,
+}
+
+impl Logger {
+    /// Log to stderr.
+    pub fn stderr() -> Self {
+        Self::new(|line| eprintln!("{line}"))
+    }
+
+    /// Log through `sink`, e.g. into an audit log.
+    pub fn new(sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self {
+            sink: Box::new(sink),
+        }
+    }
+}
+
+impl Middleware for Logger {
+    fn before_request(&self, req: &mut GurlRequest) -> Result<(), crate::Error> {
+        (self.sink)(&format!("> {} {}", req.method, req.url));
+        Ok(())
+    }
+
+    fn after_response(&self, response: &mut GurlResponse) -> Result<(), crate::Error> {
+        let cache = match &response.response.cache {
+            Some(cache) => format!(", cache {:?}", cache.status).to_lowercase(),
+            None => String::new(),
+        };
+        (self.sink)(&format!(
+            "< {} {} {} ({} ms{cache})",
+            response.response.status,
+            response.request.method,
+            response.response.final_url,
+            response.response.timing.total_ms,
+        ));
+        Ok(())
+    }
+
+    fn on_error(&self, request: &RequestMeta, error: &crate::Error) {
+        (self.sink)(&format!("! {} {}: {error}", request.method, request.url));
+    }
+}
+
+/// Adds headers to every request. By default a header the request already
+/// has is left alone; `overwrite` replaces it instead.
+#[derive(Debug, Clone, Default)]
+pub struct SetHeaders {
+    headers: HeaderMap,
+    overwrite: bool,
+}
+
+impl SetHeaders {
+    pub fn new(headers: HeaderMap) -> Self {
+        Self {
+            headers,
+            overwrite: false,
+        }
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+}
+
+impl Middleware for SetHeaders {
+    fn before_request(&self, req: &mut GurlRequest) -> Result<(), crate::Error> {
+        for name in self.headers.keys() {
+            if req.headers.contains_key(name) && !self.overwrite {
+                continue;
+            }
+            req.headers.remove(name);
+            for value in self.headers.get_all(name) {
+                req.headers.append(name, value.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends requests for URLs under one prefix to another, e.g. a public
+/// registry to an internal mirror. The first matching rule wins.
+#[derive(Debug, Clone, Default)]
+pub struct RewriteUrls {
+    rules: Vec<(String, String)>,
+}
+
+impl RewriteUrls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rewrite URLs starting with `from` to start with `to` instead.
+    pub fn rule(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.rules.push((from.into(), to.into()));
+        self
+    }
+}
+
+impl Middleware for RewriteUrls {
+    fn before_request(&self, req: &mut GurlRequest) -> Result<(), crate::Error> {
+        let url = req.url.as_str();
+        if let Some((from, to)) = self.rules.iter().find(|(from, _)| url.starts_with(from)) {
+            let rewritten = format!("{to}{}", &url[from.len()..]);
+            req.url = Url::parse(&rewritten)
+                .map_err(|e| crate::Error::InvalidUrl(format!("{rewritten}: {e}")))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn request(url: &str) -> GurlRequest {
+        GurlRequest::get(Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_set_headers_and_rewrite() {
+        let mut req = request("https://registry.npmjs.org/left-pad");
+        req.headers
+            .insert("x-team", HeaderValue::from_static("mine"));
+        let chain = {
+            let mut chain = MiddlewareChain::default();
+            chain.push(Arc::new(
+                SetHeaders::default()
+                    .header(
+                        HeaderName::from_static("x-team"),
+                        HeaderValue::from_static("search"),
+                    )
+                    .header(
+                        HeaderName::from_static("x-audit"),
+                        HeaderValue::from_static("1"),
+                    ),
+            ));
+            chain.push(Arc::new(
+                RewriteUrls::new().rule("https://registry.npmjs.org/", "https://npm.corp/mirror/"),
+            ));
+            chain
+        };
+        chain.before_request(&mut req).unwrap();
+        assert_eq!(req.url.as_str(), "https://npm.corp/mirror/left-pad");
+        assert_eq!(req.headers["x-team"], "mine");
+        assert_eq!(req.headers["x-audit"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_failed_hook_aborts_the_request() {
+        struct Deny;
+        impl Middleware for Deny {
+            fn before_request(&self, req: &mut GurlRequest) -> Result<(), crate::Error> {
+                Err(crate::Error::Config(format!("{} is not allowed", req.url)))
+            }
+        }
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let log = lines.clone();
+        let client = crate::GurlClient::builder()
+            .middleware(Logger::new(move |line| {
+                log.lock().unwrap().push(line.to_string())
+            }))
+            .middleware(Deny)
+            .build()
+            .unwrap();
+        let result = client.execute(request("http://blocked.test/")).await;
+        assert!(matches!(result, Err(crate::Error::Config(_))));
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                "> GET http://blocked.test/",
+                "! GET http://blocked.test/: invalid config: http://blocked.test/ is not allowed",
+            ]
+        );
+    }
+}
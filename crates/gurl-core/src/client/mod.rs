@@ -1,66 +1,595 @@
+mod auth;
+mod body;
+mod builder;
+mod cassette;
+mod limits;
+pub mod middleware;
+mod oauth;
+mod proxy;
 mod request;
 pub mod response;
+pub mod retry;
+mod robots;
+mod sigv4;
+#[cfg(test)]
+pub(crate) mod test_server;
 mod timing;
+mod tls;
+mod trace;
 
-pub use request::{Body, GurlRequest};
-pub use response::{ResponseMeta, Timing, TlsInfo};
+pub use auth::{Auth, Netrc};
+pub use builder::GurlClientBuilder;
+pub use cassette::{
+    Cassette, CassetteMode, Interaction, Matcher, RecordedBody, RecordedRequest, RecordedResponse,
+};
+pub use limits::{HostLimits, LimitsConfig};
+pub use middleware::Middleware;
+pub use oauth::{DevicePrompt, OAuth2, OAuthGrant, OAuthProfile};
+pub use proxy::ProxyConfig;
+pub use request::{Body, BodyStream, FormPart, GurlRequest, PartValue};
+pub use response::{CertificateInfo, HeaderField, RedirectHop, ResponseMeta, Timing, TlsInfo};
+pub use retry::{Backoff, RetryCondition, RetryInfo, RetryPolicy};
+pub use robots::RobotsDecision;
+pub use sigv4::{AwsCredentials, SigV4};
+pub use tls::{HostTls, TlsConfig};
 
+use crate::cache::{CacheInfo, CacheMode, CacheStatus, CachedResponse, HttpCache, policy};
+use crate::config::Config;
+use crate::har::{self, HarRecorder};
 use crate::output::envelope::{Content, GurlResponse, RequestMeta};
+use crate::output::links;
+use crate::session::{CookieJar, Session};
+use builder::HttpOptions;
+use limits::HostLimiter;
 use markitdown_rs::MarkItDown;
 use markitdown_rs::converter::StreamInfo;
-use reqwest::Client;
-use std::time::Instant;
+use middleware::MiddlewareChain;
+use proxy::ProxyRules;
+use reqwest::cookie::CookieStore as _;
+use reqwest::header::{
+    AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HeaderMap, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, LOCATION, SET_COOKIE,
+};
+use reqwest::{Client, ClientBuilder, Method, Proxy, RequestBuilder, StatusCode};
+use robots::{RobotsCache, RobotsTxt, Rules};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use timing::{TimedResolver, TimingLayer};
+use trace::ConnTrace;
+use url::Url;
 
 pub struct GurlClient {
+    /// User agent, timeouts and pool settings the reqwest clients are built with
+    options: HttpOptions,
     http: Client,
     http_no_redirect: Client,
     converter: MarkItDown,
+    /// Last TLS handshake seen per origin, reported for requests that reuse
+    /// a pooled or resumed connection and therefore perform no handshake
+    tls_sessions: Mutex<HashMap<String, TlsInfo>>,
+    cache: Option<HttpCache>,
+    proxy: Arc<ProxyRules>,
+    cookies: Arc<CookieJar>,
+    /// Defaults from the attached session, for headers a request leaves unset
+    session_headers: HeaderMap,
+    /// Defaults from the builder, below the session's in precedence
+    default_headers: HeaderMap,
+    /// Per-host politeness, shared by concurrent `execute` calls
+    limiter: HostLimiter,
+    /// robots.txt per origin, when compliance is enabled
+    robots: Option<RobotsCache>,
+    /// Credentials for requests that bring none of their own
+    netrc: Option<Netrc>,
+    /// CA bundles, client certificates and pins, globally and per domain
+    tls: TlsConfig,
+    /// Clients for domains with TLS settings of their own, since trust
+    /// anchors and client certificates are fixed per rustls config
+    tls_hosts: HashMap<String, (Client, Client)>,
+    /// Hooks run around every `execute` call
+    middleware: MiddlewareChain,
+    /// Bodies larger than this are spooled to a temp file
+    memory_limit: usize,
+    /// Archive of every request sent and its response
+    har: Option<HarRecorder>,
+    /// Recorded interactions, stored or played back in place of the network
+    cassette: Option<Cassette>,
 }
 
 impl GurlClient {
+    /// Client using the proxies from the environment, if any.
     pub fn new() -> Result<Self, reqwest::Error> {
-        let ua = format!("gurl/{}", env!("CARGO_PKG_VERSION"));
-        let http = Client::builder().user_agent(&ua).build()?;
-        let http_no_redirect = Client::builder()
-            .user_agent(&ua)
-            .redirect(reqwest::redirect::Policy::none())
-            .build()?;
+        // Like reqwest's own detection, an unparseable proxy variable is ignored
+        let proxy = ProxyRules::new(&ProxyConfig::from_env()).unwrap_or_default();
+        Self::with_transport(HttpOptions::default(), Arc::new(proxy))
+    }
+
+    /// Builder for clients with a custom user agent, default headers,
+    /// timeouts, pool settings or converter.
+    pub fn builder() -> GurlClientBuilder {
+        GurlClientBuilder::default()
+    }
+
+    fn with_transport(
+        options: HttpOptions,
+        proxy: Arc<ProxyRules>,
+    ) -> Result<Self, reqwest::Error> {
+        let cookies = Arc::new(CookieJar::new());
+        let tls =
+            tls::client_config(&HostTls::default()).expect("the built-in roots need no files");
+        let (http, http_no_redirect) = Self::http_clients(&options, &proxy, &cookies, &tls)?;
         Ok(Self {
+            options,
             http,
             http_no_redirect,
             converter: MarkItDown::new(),
+            tls_sessions: Mutex::new(HashMap::new()),
+            cache: None,
+            proxy,
+            cookies,
+            session_headers: HeaderMap::new(),
+            default_headers: HeaderMap::new(),
+            limiter: HostLimiter::default(),
+            robots: None,
+            netrc: None,
+            tls: TlsConfig::default(),
+            tls_hosts: HashMap::new(),
+            middleware: MiddlewareChain::default(),
+            memory_limit: body::DEFAULT_MEMORY_LIMIT,
+            har: None,
+            cassette: None,
         })
     }
 
-    pub async fn execute(&self, req: GurlRequest) -> Result<GurlResponse, crate::Error> {
+    fn http_clients(
+        options: &HttpOptions,
+        proxy: &Arc<ProxyRules>,
+        cookies: &Arc<CookieJar>,
+        tls: &rustls::ClientConfig,
+    ) -> Result<(Client, Client), reqwest::Error> {
+        let http = Self::client_builder(options, proxy, cookies, tls).build()?;
+        let http_no_redirect = Self::client_builder(options, proxy, cookies, tls)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        Ok((http, http_no_redirect))
+    }
+
+    fn client_builder(
+        options: &HttpOptions,
+        proxy: &Arc<ProxyRules>,
+        cookies: &Arc<CookieJar>,
+        tls: &rustls::ClientConfig,
+    ) -> ClientBuilder {
+        let proxy = proxy.clone();
+        let mut builder = Client::builder()
+            .user_agent(&options.user_agent)
+            .use_preconfigured_tls(tls.clone())
+            .dns_resolver(Arc::new(TimedResolver))
+            .connector_layer(TimingLayer)
+            .no_proxy()
+            .proxy(Proxy::custom(move |url| proxy.proxy_for(url).cloned()))
+            .cookie_provider(cookies.clone());
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = options.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        builder
+    }
+
+    /// Rebuild the reqwest clients after the proxy, cookie jar or TLS
+    /// settings changed.
+    fn rebuild(&mut self) -> Result<(), crate::Error> {
+        let clients = |tls: &HostTls| {
+            let config = tls::client_config(tls)?;
+            Self::http_clients(&self.options, &self.proxy, &self.cookies, &config)
+                .map_err(|e| crate::Error::Request(e.to_string()))
+        };
+        let default = clients(&self.tls.default)?;
+        let hosts = self
+            .tls
+            .hosts
+            .keys()
+            .map(|domain| Ok((domain.clone(), clients(&self.tls.for_host(domain))?)))
+            .collect::<Result<_, crate::Error>>()?;
+        (self.http, self.http_no_redirect) = default;
+        self.tls_hosts = hosts;
+        Ok(())
+    }
+
+    /// The client for requests to `url`, honoring per-host TLS settings.
+    fn client_for(&self, url: &Url, follow_redirects: bool) -> &Client {
+        let clients = url
+            .host_str()
+            .and_then(|host| self.tls.domain_for(host))
+            .and_then(|domain| self.tls_hosts.get(domain));
+        match (clients, follow_redirects) {
+            (Some((http, _)), true) => http,
+            (Some((_, http_no_redirect)), false) => http_no_redirect,
+            (None, true) => &self.http,
+            (None, false) => &self.http_no_redirect,
+        }
+    }
+
+    /// Client for fetching an OAuth2 token.
+    fn oauth_client(&self, oauth: &OAuth2) -> &Client {
+        match Url::parse(&oauth.profile.token_url) {
+            Ok(url) => self.client_for(&url, true),
+            Err(_) => &self.http,
+        }
+    }
+
+    /// Route requests through proxies. This replaces the proxies taken from
+    /// the environment; merge with `ProxyConfig::from_env()` to keep them.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Result<Self, crate::Error> {
+        self.proxy = Arc::new(ProxyRules::new(&proxy)?);
+        self.rebuild()?;
+        Ok(self)
+    }
+
+    /// Use a session's cookie jar and default headers, and record into its
+    /// HAR archive if it has one. Cookies set by responses land in the
+    /// session's jar, ready to be saved.
+    pub fn with_session(mut self, session: &Session) -> Result<Self, crate::Error> {
+        self.cookies = session.cookies.clone();
+        self.session_headers = session.header_map();
+        if let Some(path) = &session.har {
+            let har = HarRecorder::open(path).map_err(|e| {
+                crate::Error::Config(format!("failed to open HAR file {}: {e}", path.display()))
+            })?;
+            self.har = Some(har);
+        }
+        self.rebuild()?;
+        Ok(self)
+    }
+
+    /// Trust a custom CA bundle, present a client certificate, pin public
+    /// keys or skip verification, globally or per domain.
+    pub fn with_tls(mut self, tls: TlsConfig) -> Result<Self, crate::Error> {
+        self.tls = tls;
+        self.rebuild()?;
+        Ok(self)
+    }
+
+    /// Run `middleware` around every request, after any added before it.
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Cookies stored by this client (a session's jar, if one is attached).
+    pub fn cookies(&self) -> &Arc<CookieJar> {
+        &self.cookies
+    }
+
+    /// Attach a persistent HTTP cache used by every request on this client.
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Apply per-host rate limits and concurrency caps.
+    pub fn with_limits(mut self, limits: LimitsConfig) -> Self {
+        self.limiter = HostLimiter::new(limits);
+        self
+    }
+
+    /// Refuse requests that robots.txt disallows for gurl's user agent, and
+    /// honor its Crawl-delay.
+    pub fn with_robots(mut self, enabled: bool) -> Self {
+        self.robots = enabled.then(RobotsCache::default);
+        self
+    }
+
+    /// Send Basic credentials from a netrc file to matching hosts, for
+    /// requests without their own auth or Authorization header.
+    pub fn with_netrc(mut self, netrc: Netrc) -> Self {
+        self.netrc = Some(netrc);
+        self
+    }
+
+    /// Record every request sent, redirects and retries included, and its
+    /// response into a HAR archive.
+    pub fn with_har(mut self, har: HarRecorder) -> Self {
+        self.har = Some(har);
+        self
+    }
+
+    /// Store every interaction in a cassette, or answer requests from one
+    /// without touching the network. Replaying skips robots.txt checks and
+    /// OAuth2 token requests.
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    fn replaying(&self) -> bool {
+        self.cassette
+            .as_ref()
+            .is_some_and(|cassette| cassette.mode() == CassetteMode::Replay)
+    }
+
+    /// Client configured from a user config file.
+    pub fn from_config(config: &Config) -> Result<Self, crate::Error> {
+        Self::builder()
+            .proxy(config.proxy.clone().or(ProxyConfig::from_env()))
+            .tls(config.tls.clone())
+            .limits(config.limits.clone())
+            .robots(config.robots)
+            .netrc(Netrc::load_default().unwrap_or_default())
+            .build()
+    }
+
+    pub async fn execute(&self, mut req: GurlRequest) -> Result<GurlResponse, crate::Error> {
+        let before = self.middleware.before_request(&mut req);
+        let request = RequestMeta {
+            method: req.method.to_string(),
+            url: req.url.to_string(),
+            timestamp: chrono::Utc::now(),
+        };
+        let mut exchanges = Vec::new();
+        let mut result = match before {
+            Ok(()) => self.fetch(req, &mut exchanges).await,
+            Err(e) => Err(e),
+        };
+        if let Some(har) = &self.har
+            && let Err(e) = har.record(exchanges, result.as_ref().ok())
+            && let Ok(response) = &mut result
+        {
+            response.response.warnings.push(format!(
+                "could not write the HAR file {}: {e}",
+                har.path().display()
+            ));
+        }
+        let result = result.and_then(|mut response| {
+            self.middleware.after_response(&mut response)?;
+            Ok(response)
+        });
+        if let Err(e) = &result {
+            self.middleware.on_error(&request, e);
+        }
+        result
+    }
+
+    async fn fetch(
+        &self,
+        mut req: GurlRequest,
+        exchanges: &mut Vec<har::Exchange>,
+    ) -> Result<GurlResponse, crate::Error> {
+        if req.url.scheme() == "s3" {
+            sigv4::resolve_s3(
+                &mut req,
+                || AwsCredentials::load(None).ok(),
+                sigv4::s3_endpoint().as_deref(),
+            )?;
+        }
+        for (name, value) in &self.session_headers {
+            if !req.headers.contains_key(name) {
+                req.headers.insert(name, value.clone());
+            }
+        }
+        for name in self.default_headers.keys() {
+            if !req.headers.contains_key(name) {
+                for value in self.default_headers.get_all(name) {
+                    req.headers.append(name, value.clone());
+                }
+            }
+        }
+        if req.auth.is_none()
+            && !req.headers.contains_key(AUTHORIZATION)
+            && let (Some(netrc), Some(host)) = (&self.netrc, req.url.host_str())
+        {
+            req.auth = netrc.lookup(host);
+        }
+        if let Some(value) = req.auth.as_ref().and_then(Auth::header)
+            && !req.headers.contains_key(AUTHORIZATION)
+        {
+            req.headers.insert(AUTHORIZATION, value);
+        }
         let method = req.method.clone();
         let url = req.url.clone();
         let timestamp = chrono::Utc::now();
-
-        let client = if req.follow_redirects {
-            &self.http
-        } else {
-            &self.http_no_redirect
+        let started = Instant::now();
+        let request_meta = RequestMeta {
+            method: method.to_string(),
+            url: url.to_string(),
+            timestamp,
         };
-        let mut builder = client.request(method.clone(), url.clone());
-        builder = builder.headers(req.headers.clone());
-        if let Some(timeout) = req.timeout {
-            builder = builder.timeout(timeout);
+
+        let cache = self
+            .cache
+            .as_ref()
+            .filter(|_| req.cache_mode != CacheMode::NoStore);
+        let cached = cache.and_then(|c| c.lookup(&method, &url, &req.headers));
+        if req.cache_mode == CacheMode::Offline {
+            let cached = cached.ok_or_else(|| crate::Error::NotCached(url.to_string()))?;
+            let status = if cached.is_fresh(&req.headers) {
+                CacheStatus::Hit
+            } else {
+                CacheStatus::Stale
+            };
+            return Ok(Self::cached_response(request_meta, cached, status, started));
         }
-        if let Some(body) = &req.body {
-            match body {
-                Body::Raw(data) => builder = builder.body(data.clone()),
-                Body::Json(value) => builder = builder.json(value),
-                Body::Form(params) => builder = builder.form(params),
+        let cached = match cached {
+            Some(cached) if cached.is_fresh(&req.headers) => {
+                return Ok(Self::cached_response(
+                    request_meta,
+                    cached,
+                    CacheStatus::Hit,
+                    started,
+                ));
             }
+            stale => stale,
+        };
+
+        let robots = match &self.robots {
+            Some(robots) if matches!(url.scheme(), "http" | "https") && !self.replaying() => {
+                Some(self.check_robots(robots, &url).await?)
+            }
+            _ => None,
+        };
+
+        // Revalidate a stale entry, unless the caller sent their own conditions
+        let revalidating = match &cached {
+            Some(cached)
+                if !req.headers.contains_key(IF_NONE_MATCH)
+                    && !req.headers.contains_key(IF_MODIFIED_SINCE) =>
+            {
+                let validators = cached.validators();
+                let any = !validators.is_empty();
+                req.headers.extend(validators);
+                any
+            }
+            _ => false,
+        };
+
+        // Only now that the network is needed: a cache hit or offline
+        // request must not start a token request or a device sign-in
+        let oauth = match &req.auth {
+            Some(Auth::OAuth2(oauth))
+                if !req.headers.contains_key(AUTHORIZATION) && !self.replaying() =>
+            {
+                let token = oauth.token(self.oauth_client(oauth), false).await?;
+                req.headers
+                    .insert(AUTHORIZATION, auth::bearer_header(&token)?);
+                Some(oauth.clone())
+            }
+            _ => None,
+        };
+
+        let policy = req.retry.clone().unwrap_or_default();
+        let mut attempts = 0u32;
+        let mut retry_wait = Duration::ZERO;
+        let mut token_refreshed = false;
+        let mut redirects = Vec::new();
+
+        // A one-shot body is sent once: no retries, no second round for auth
+        let replayable = req.body.as_ref().is_none_or(Body::is_replayable);
+        let host = url.host_str().unwrap_or_default();
+        let (mut response, trace, start, _permit) = loop {
+            attempts += 1;
+            let retries_left = replayable && attempts <= policy.max_retries;
+            let permit = self.limiter.acquire(host).await;
+            let trace = ConnTrace::default();
+            let start = Instant::now();
+            let sent = trace
+                .scope(self.send(&req, &mut redirects, exchanges))
+                .await;
+            if let Ok(response) = &sent {
+                self.limiter.observe(
+                    host,
+                    response.status(),
+                    response.headers(),
+                    policy.max_delay,
+                );
+            }
+            // Answer a Digest challenge once; this is not a retry
+            if let Ok(response) = &sent
+                && response.status() == StatusCode::UNAUTHORIZED
+                && replayable
+                && !req.headers.contains_key(AUTHORIZATION)
+                && let Some(Auth::Digest { username, password }) = &req.auth
+                && let Some(value) = auth::digest_authorization(
+                    response.headers(),
+                    username,
+                    password,
+                    &req.method,
+                    // The challenge comes from wherever the redirects ended
+                    response.url(),
+                )
+            {
+                req.headers.insert(AUTHORIZATION, value);
+                attempts -= 1;
+                continue;
+            }
+            // The OAuth2 token was revoked or expired early: fetch a new one once
+            if let Ok(response) = &sent
+                && response.status() == StatusCode::UNAUTHORIZED
+                && replayable
+                && !token_refreshed
+                && let Some(oauth) = &oauth
+            {
+                let token = oauth.token(self.oauth_client(oauth), true).await?;
+                req.headers
+                    .insert(AUTHORIZATION, auth::bearer_header(&token)?);
+                token_refreshed = true;
+                attempts -= 1;
+                continue;
+            }
+            let retry_after = match sent {
+                Ok(response)
+                    if retries_left
+                        && policy.retries_status(
+                            &req.method,
+                            response.status(),
+                            response.headers(),
+                        ) =>
+                {
+                    retry::retry_after(response.headers())
+                }
+                Ok(response) => break (response, trace, start, permit),
+                Err(SendError::Http(e))
+                    if retries_left && policy.retries_error(&req.method, &e) =>
+                {
+                    None
+                }
+                Err(e) => return Err(e.into()),
+            };
+            // Free the in-flight slot so other requests to the host can go ahead
+            drop(permit);
+            let delay = policy.delay_for(attempts, retry_after);
+            retry_wait += delay;
+            tokio::time::sleep(delay).await;
+        };
+        let first_byte = Instant::now();
+        if req.follow_redirects
+            && redirects.len() == req.max_redirects
+            && redirect_target(&response).is_some()
+        {
+            return Err(crate::Error::TooManyRedirects {
+                url: url.to_string(),
+                max: req.max_redirects,
+            });
         }
+        let final_url = response.url().clone();
+        let mut warnings =
+            self.tls_warnings(redirects.iter().map(|hop| hop.url.as_str()), &final_url);
+        let tls = self.tls_info(&response, &trace);
+        let proxy = self.proxy.proxy_for(response.url()).map(proxy::redacted);
+        let retries = (attempts > 1).then_some(RetryInfo {
+            attempts,
+            total_retry_ms: retry_wait.as_millis() as u64,
+        });
 
-        let start = Instant::now();
-        let response = builder
-            .send()
-            .await
-            .map_err(|e| crate::Error::Request(e.to_string()))?;
-        let total_ms = start.elapsed().as_millis() as u64;
+        if revalidating
+            && response.status() == StatusCode::NOT_MODIFIED
+            && let (Some(cache), Some(mut cached)) = (cache, cached)
+        {
+            cached.freshen(response.headers(), timestamp);
+            let _ = cache.store(&method, &url, &req.headers, &cached);
+            let mut envelope =
+                Self::cached_response(request_meta, cached, CacheStatus::Revalidated, started);
+            envelope.response.timing = trace.timing(start, first_byte, Instant::now());
+            envelope.response.tls = tls;
+            envelope.response.retries = retries;
+            envelope.response.proxy = proxy;
+            envelope.response.robots = robots;
+            envelope.response.final_url = final_url.to_string();
+            envelope.response.redirects = redirects;
+            envelope.response.warnings = warnings;
+            return Ok(envelope);
+        }
 
         let status = response.status().as_u16();
         let status_text = response
@@ -68,26 +597,191 @@ impl GurlClient {
             .canonical_reason()
             .unwrap_or("")
             .to_string();
-        let headers: std::collections::HashMap<String, String> = response
-            .headers()
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-            .collect();
+        let response_headers = response.headers().clone();
+        let header_list = HeaderField::list(&response_headers);
+        let headers = HeaderField::flatten(&header_list);
 
         let content_type = headers.get("content-type").cloned().unwrap_or_default();
 
-        let body_bytes = response
-            .bytes()
-            .await
-            .map_err(|e| crate::Error::Request(e.to_string()))?;
+        let body = body::read(&mut response, &final_url, req.max_size, self.memory_limit).await?;
+        let timing = trace.timing(start, first_byte, Instant::now());
+
+        let mut content = self.convert(content_type, &final_url, body.bytes, &mut warnings);
+        content.truncated = body.truncated;
+
+        let mut cache_info = None;
+        if let Some(cache) = cache {
+            let mut info = CacheInfo {
+                status: CacheStatus::Miss,
+                age_s: None,
+                ttl_s: None,
+                stored: false,
+            };
+            // Only part of a truncated body is in the envelope, so it is not stored
+            if content.truncated.is_none()
+                && policy::is_storable(&method, status, &req.headers, &response_headers)
+            {
+                let entry = CachedResponse {
+                    status,
+                    status_text: status_text.clone(),
+                    headers: header_list.clone(),
+                    request_time: timestamp,
+                    response_time: chrono::Utc::now(),
+                    content,
+                };
+                info.stored = cache.store(&method, &url, &req.headers, &entry).is_ok();
+                info.ttl_s = Some(entry.lifetime().as_secs());
+                content = entry.content;
+            } else if !method.is_safe() && status < 400 {
+                // A successful unsafe request changes the resource (RFC 9111 section 4.4)
+                let _ = cache.invalidate(&url);
+            }
+            cache_info = Some(info);
+        }
+
+        Ok(GurlResponse {
+            gurl: env!("CARGO_PKG_VERSION").to_string(),
+            request: request_meta,
+            response: ResponseMeta {
+                status,
+                status_text,
+                headers,
+                header_list,
+                final_url: final_url.to_string(),
+                redirects,
+                timing,
+                tls,
+                retries,
+                cache: cache_info,
+                proxy,
+                robots,
+                warnings,
+            },
+            content,
+        })
+    }
+
+    /// Envelope for a response served from the cache.
+    fn cached_response(
+        request: RequestMeta,
+        cached: CachedResponse,
+        status: CacheStatus,
+        started: Instant,
+    ) -> GurlResponse {
+        let info = cached.info(status);
+        let header_list = cached.envelope_headers();
+        let final_url = request.url.clone();
+        GurlResponse {
+            gurl: env!("CARGO_PKG_VERSION").to_string(),
+            request,
+            response: ResponseMeta {
+                status: cached.status,
+                status_text: cached.status_text,
+                headers: HeaderField::flatten(&header_list),
+                header_list,
+                final_url,
+                redirects: Vec::new(),
+                timing: Timing {
+                    dns_ms: None,
+                    connect_ms: None,
+                    tls_ms: None,
+                    first_byte_ms: None,
+                    total_ms: started.elapsed().as_millis() as u64,
+                },
+                tls: None,
+                retries: None,
+                cache: Some(info),
+                proxy: None,
+                robots: None,
+                warnings: Vec::new(),
+            },
+            content: cached.content,
+        }
+    }
+
+    /// Check `url` against its origin's robots.txt, fetching it if needed.
+    async fn check_robots(
+        &self,
+        robots: &RobotsCache,
+        url: &Url,
+    ) -> Result<RobotsDecision, crate::Error> {
+        let origin = url.origin().ascii_serialization();
+        let robots_txt = match robots.get(&origin) {
+            Some(robots_txt) => robots_txt,
+            None => robots.insert(origin, self.fetch_robots(url).await),
+        };
+        let host = url.host_str().unwrap_or_default();
+        if let Some(delay) = robots_txt.crawl_delay() {
+            self.limiter.set_crawl_delay(host, delay);
+        }
+        let decision = robots_txt.decide(url);
+        if !decision.allowed {
+            return Err(crate::Error::RobotsDisallowed {
+                url: url.to_string(),
+                rule: decision.rule.unwrap_or_default(),
+            });
+        }
+        Ok(decision)
+    }
+
+    async fn fetch_robots(&self, url: &Url) -> RobotsTxt {
+        let mut robots_url = url.clone();
+        robots_url.set_path("/robots.txt");
+        robots_url.set_query(None);
+        robots_url.set_fragment(None);
+        let host = url.host_str().unwrap_or_default();
+
+        let _permit = self.limiter.acquire(host).await;
+        let sent = self
+            .client_for(&robots_url, true)
+            .get(robots_url)
+            .timeout(robots::ROBOTS_TIMEOUT)
+            .send()
+            .await;
+        let mut response = match sent {
+            Ok(response) => response,
+            Err(e) => return RobotsTxt::Unreachable(e.to_string()),
+        };
+        let status = response.status();
+        let max_pause = RetryPolicy::default().max_delay;
+        self.limiter
+            .observe(host, status, response.headers(), max_pause);
+        if status.is_client_error() {
+            return RobotsTxt::Unavailable(status.as_u16());
+        }
+        if !status.is_success() {
+            return RobotsTxt::Unreachable(status.to_string());
+        }
+        // Only the first MAX_ROBOTS_SIZE bytes are read, however much is sent
+        let mut body = Vec::new();
+        while body.len() < robots::MAX_ROBOTS_SIZE {
+            match response.chunk().await {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                Ok(None) => break,
+                Err(e) => return RobotsTxt::Unreachable(e.to_string()),
+            }
+        }
+        body.truncate(robots::MAX_ROBOTS_SIZE);
+        let agent = robots::product_token(&self.options.user_agent);
+        RobotsTxt::Rules(Rules::parse(&String::from_utf8_lossy(&body), agent))
+    }
 
-        // Build content: try JSON first, then markitdown conversion, then raw
-        let content = if content_type.contains("application/json") {
+    /// Build content: try JSON first, then markitdown conversion, then raw.
+    /// Conversion never fails the request: when a converter takes the body
+    /// but fails, the raw body is kept and a warning says why.
+    fn convert(
+        &self,
+        content_type: String,
+        url: &Url,
+        body_bytes: Vec<u8>,
+        warnings: &mut Vec<String>,
+    ) -> Content {
+        if content_type.contains("application/json") {
             // JSON: parse and return structured
             let json_val = serde_json::from_slice(&body_bytes).unwrap_or(
                 serde_json::Value::String(String::from_utf8_lossy(&body_bytes).into_owned()),
             );
-            Content {
+            return Content {
                 content_type: "json".to_string(),
                 original_type: content_type,
                 title: None,
@@ -95,79 +789,735 @@ impl GurlClient {
                 metadata: None,
                 links: None,
                 images: None,
-                raw_body: body_bytes.to_vec(),
-            }
-        } else {
-            // Try markitdown conversion
-            let stream_info = StreamInfo {
-                mime_type: Some(content_type.clone()),
-                url: Some(url.to_string()),
-                ..Default::default()
+                truncated: None,
+                raw_body: body_bytes,
             };
+        }
 
-            match self.converter.convert_bytes(&body_bytes, &stream_info) {
-                Ok(result) => {
-                    let metadata = if result.metadata.is_empty() {
-                        None
-                    } else {
-                        Some(serde_json::to_value(&result.metadata).unwrap_or_default())
-                    };
-                    Content {
-                        content_type: "markdown".to_string(),
-                        original_type: content_type,
-                        title: result.title,
-                        body: serde_json::Value::String(result.body),
-                        metadata,
-                        links: None,
-                        images: None,
-                        raw_body: body_bytes.to_vec(),
-                    }
+        // Try markitdown conversion
+        let stream_info = StreamInfo {
+            mime_type: Some(content_type.clone()),
+            url: Some(url.to_string()),
+            ..Default::default()
+        };
+
+        match self.converter.convert_bytes(&body_bytes, &stream_info) {
+            Ok(result) => {
+                let metadata = if result.metadata.is_empty() {
+                    None
+                } else {
+                    Some(serde_json::to_value(&result.metadata).unwrap_or_default())
+                };
+                Content {
+                    content_type: "markdown".to_string(),
+                    original_type: content_type,
+                    title: result.title,
+                    body: serde_json::Value::String(links::resolve_relative(&result.body, url)),
+                    metadata,
+                    links: None,
+                    images: None,
+                    truncated: None,
+                    raw_body: body_bytes,
+                }
+            }
+            Err(e) => {
+                // Fallback: raw body
+                if !matches!(e, markitdown_rs::Error::NoConverterFound) {
+                    warnings.push(format!("{e}; content is the raw body"));
                 }
-                Err(_) => {
-                    // Fallback: raw body
-                    Content {
-                        content_type: "raw".to_string(),
-                        original_type: content_type,
-                        title: None,
-                        body: serde_json::Value::String(
-                            String::from_utf8_lossy(&body_bytes).into_owned(),
-                        ),
-                        metadata: None,
-                        links: None,
-                        images: None,
-                        raw_body: body_bytes.to_vec(),
-                    }
+                Content {
+                    content_type: "raw".to_string(),
+                    original_type: content_type,
+                    title: None,
+                    body: serde_json::Value::String(
+                        String::from_utf8_lossy(&body_bytes).into_owned(),
+                    ),
+                    metadata: None,
+                    links: None,
+                    images: None,
+                    truncated: None,
+                    raw_body: body_bytes,
                 }
             }
-        };
+        }
+    }
 
-        Ok(GurlResponse {
-            gurl: env!("CARGO_PKG_VERSION").to_string(),
-            request: RequestMeta {
-                method: method.to_string(),
+    fn tls_info(&self, response: &reqwest::Response, trace: &ConnTrace) -> Option<TlsInfo> {
+        let url = response.url();
+        if url.scheme() != "https" {
+            return None;
+        }
+        let origin = url.origin().ascii_serialization();
+        let mut sessions = self.tls_sessions.lock().unwrap_or_else(|e| e.into_inner());
+        match trace.tls_info(response.version()) {
+            Some(info) => {
+                sessions.insert(origin, info.clone());
+                Some(info)
+            }
+            None => sessions.get(&origin).cloned(),
+        }
+    }
+
+    /// A warning for each host reached over HTTPS without certificate checks.
+    fn tls_warnings<'a>(
+        &self,
+        hops: impl Iterator<Item = &'a str>,
+        final_url: &Url,
+    ) -> Vec<String> {
+        let mut hosts: Vec<String> = Vec::new();
+        let urls = hops.filter_map(|hop| Url::parse(hop).ok());
+        for url in urls.chain(std::iter::once(final_url.clone())) {
+            if url.scheme() == "https"
+                && let Some(host) = url.host_str()
+                && self.tls.for_host(host).insecure == Some(true)
+                && !hosts.iter().any(|seen| seen == host)
+            {
+                hosts.push(host.to_string());
+            }
+        }
+        hosts
+            .into_iter()
+            .map(|host| {
+                format!(
+                    "INSECURE: certificate verification was disabled for {host}; \
+                     the server's identity was not checked"
+                )
+            })
+            .collect()
+    }
+
+    /// Send `req`, following redirects if it asks to and recording each one
+    /// in `redirects`, and each request sent in `exchanges` when recording a
+    /// HAR. Stops at the redirect past `max_redirects`.
+    async fn send(
+        &self,
+        req: &GurlRequest,
+        redirects: &mut Vec<RedirectHop>,
+        exchanges: &mut Vec<har::Exchange>,
+    ) -> Result<reqwest::Response, SendError> {
+        redirects.clear();
+        let mut method = req.method.clone();
+        let mut url = req.url.clone();
+        let mut headers = req.headers.clone();
+        let mut body = req.body.as_ref();
+        let mut signer = match &req.auth {
+            Some(Auth::SigV4(signer)) if !headers.contains_key(AUTHORIZATION) => Some(signer),
+            _ => None,
+        };
+        loop {
+            let start = Instant::now();
+            let (client, request) = self
+                .build(&method, &url, &headers, body, req.timeout)
+                .build_split();
+            let mut request = request?;
+            if let Some(signer) = signer {
+                signer.sign(&mut request, chrono::Utc::now());
+            }
+            let sent = self
+                .har
+                .is_some()
+                .then(|| (chrono::Utc::now(), har::Request::sent(&request, body)));
+            let result = self.transmit(&client, request, req.max_size).await;
+            if let Some((started, request)) = sent {
+                exchanges.push(har::Exchange {
+                    started,
+                    request,
+                    response: match &result {
+                        Ok(response) => Ok(har::Response::received(response)),
+                        Err(SendError::Http(e)) => Err(request_error(e).to_string()),
+                        Err(SendError::Other(e)) => Err(e.to_string()),
+                    },
+                    elapsed: start.elapsed(),
+                });
+            }
+            let response = result?;
+            let status = response.status();
+            // 303 turns into a GET, and so do 301 and 302 after a POST, as
+            // browsers do; 307 and 308 resend the same request
+            let to_get = (status == StatusCode::SEE_OTHER && method != Method::HEAD)
+                || (matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)
+                    && method == Method::POST);
+            let Some(next) = redirect_target(&response).filter(|_| {
+                req.follow_redirects
+                    && redirects.len() < req.max_redirects
+                    // A one-shot body cannot be sent again to the new location
+                    && (to_get || body.is_none_or(Body::is_replayable))
+            }) else {
+                return Ok(response);
+            };
+            redirects.push(RedirectHop {
                 url: url.to_string(),
-                timestamp,
-            },
-            response: ResponseMeta {
-                status,
-                status_text,
-                headers,
-                timing: Timing {
-                    dns_ms: None,
-                    connect_ms: None,
-                    tls_ms: None,
-                    first_byte_ms: None,
-                    total_ms,
-                },
-                tls: None,
-            },
-            content,
-        })
+                status: status.as_u16(),
+                location: next.to_string(),
+                set_cookie: response
+                    .headers()
+                    .get_all(SET_COOKIE)
+                    .iter()
+                    .filter_map(|v| v.to_str().ok().map(str::to_string))
+                    .collect(),
+                elapsed_ms: start.elapsed().as_millis() as u64,
+            });
+
+            if to_get {
+                method = Method::GET;
+                body = None;
+                headers.remove(CONTENT_TYPE);
+                headers.remove(CONTENT_LENGTH);
+            }
+            // Credentials meant for one origin must not leak to another
+            if next.origin() != url.origin() {
+                headers.remove(AUTHORIZATION);
+                headers.remove(COOKIE);
+                signer = None;
+            }
+            // Each hop is a new request as far as robots.txt is concerned
+            if let Some(robots) = &self.robots
+                && matches!(next.scheme(), "http" | "https")
+                && !self.replaying()
+            {
+                self.check_robots(robots, &next).await?;
+            }
+            url = next;
+        }
+    }
+
+    /// Send one request over the network or to the cassette.
+    async fn transmit(
+        &self,
+        client: &Client,
+        request: reqwest::Request,
+        max_size: Option<u64>,
+    ) -> Result<reqwest::Response, SendError> {
+        match &self.cassette {
+            None => Ok(client.execute(request).await?),
+            Some(cassette) if cassette.mode() == CassetteMode::Replay => {
+                let response = cassette.play(&request)?;
+                // reqwest only fills the jar from responses it received itself
+                self.cookies.set_cookies(
+                    &mut response.headers().get_all(SET_COOKIE).iter(),
+                    response.url(),
+                );
+                Ok(response)
+            }
+            Some(cassette) => {
+                let recorded = RecordedRequest::new(&request);
+                let response = client.execute(request).await?;
+                cassette
+                    .store(recorded, response, max_size, self.memory_limit)
+                    .await
+            }
+        }
+    }
+
+    fn build(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &HeaderMap,
+        body: Option<&Body>,
+        timeout: Option<Duration>,
+    ) -> RequestBuilder {
+        let mut builder = self
+            .client_for(url, false)
+            .request(method.clone(), url.clone())
+            .headers(headers.clone());
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        match body {
+            Some(body) => body.apply(builder),
+            None => builder,
+        }
+    }
+}
+
+/// Why `send` got no response: a transport error, which the retry policy
+/// may retry, or anything else, which ends the request.
+enum SendError {
+    Http(reqwest::Error),
+    Other(crate::Error),
+}
+
+impl From<reqwest::Error> for SendError {
+    fn from(e: reqwest::Error) -> Self {
+        SendError::Http(e)
+    }
+}
+
+impl From<crate::Error> for SendError {
+    fn from(e: crate::Error) -> Self {
+        SendError::Other(e)
+    }
+}
+
+impl From<SendError> for crate::Error {
+    fn from(e: SendError) -> Self {
+        match e {
+            SendError::Http(e) => request_error(&e),
+            SendError::Other(e) => e,
+        }
+    }
+}
+
+/// Classify a failed request by what went wrong, keeping the whole cause
+/// chain in the message.
+fn request_error(e: &reqwest::Error) -> crate::Error {
+    use std::error::Error as _;
+
+    let mut message = e.to_string();
+    let mut dns = None;
+    let mut tls = false;
+    let mut source = e.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        if !message.ends_with(&text) {
+            message = format!("{message}: {text}");
+        }
+        // Handshake failures arrive as rustls errors nested in io::Errors,
+        // whose source() skips over the error they wrap
+        let mut err = cause;
+        loop {
+            tls |= err.is::<rustls::Error>();
+            if let Some(e) = err.downcast_ref::<timing::DnsError>() {
+                dns = Some(e.host.clone());
+            }
+            match err
+                .downcast_ref::<std::io::Error>()
+                .and_then(|io| io.get_ref())
+            {
+                Some(inner) => err = inner,
+                None => break,
+            }
+        }
+        source = cause.source();
+    }
+    if e.is_timeout() {
+        crate::Error::Timeout(message)
+    } else if let Some(host) = dns {
+        crate::Error::Dns { host, message }
+    } else if tls {
+        crate::Error::Tls(message)
+    } else if e.is_connect() {
+        crate::Error::Connect(message)
+    } else {
+        crate::Error::Request(message)
     }
 }
 
+/// Where a redirect response points, if it is one that can be followed.
+fn redirect_target(response: &reqwest::Response) -> Option<Url> {
+    if !matches!(response.status().as_u16(), 301 | 302 | 303 | 307 | 308) {
+        return None;
+    }
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    let target = response.url().join(location).ok()?;
+    matches!(target.scheme(), "http" | "https").then_some(target)
+}
+
 impl Default for GurlClient {
     fn default() -> Self {
         Self::new().expect("failed to create HTTP client")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, RANGE};
+    use test_server::{Reply, TestServer};
+
+    async fn get(url: &str, timeout: Duration) -> Result<GurlResponse, crate::Error> {
+        let req = GurlRequest::get(Url::parse(url).unwrap()).with_timeout(timeout);
+        GurlClient::new().unwrap().execute(req).await
+    }
+
+    #[tokio::test]
+    async fn test_network_errors_are_classified() {
+        let second = Duration::from_secs(5);
+        let error = get("http://gurl.invalid/", second).await.unwrap_err();
+        assert!(
+            matches!(&error, crate::Error::Dns { host, .. } if host == "gurl.invalid"),
+            "{error:?}"
+        );
+
+        // Bound then dropped, so nothing listens on the port
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let error = get(&format!("http://127.0.0.1:{port}/"), second)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "connect", "{error:?}");
+
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let error = get(&url, Duration::from_millis(200)).await.unwrap_err();
+        assert_eq!(error.code(), "timeout", "{error:?}");
+
+        // Speaks plain HTTP where a TLS handshake is expected
+        let plain = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("https://{}/", plain.local_addr().unwrap());
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            let (mut stream, _) = plain.accept().await.unwrap();
+            let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await;
+        });
+        let error = get(&url, second).await.unwrap_err();
+        assert_eq!(error.code(), "tls", "{error:?}");
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn test_robots_checked_on_redirect_hops() {
+        let server = TestServer::start(|request| match request.target.as_str() {
+            "/robots.txt" => Reply::text("User-agent: *\nDisallow: /private\n"),
+            "/start" => Reply::redirect(302, "/private/page"),
+            _ => Reply::text("secret"),
+        })
+        .await;
+        let client = GurlClient::new().unwrap().with_robots(true);
+        let error = client
+            .execute(GurlRequest::get(server.url("/start")))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, crate::Error::RobotsDisallowed { url, .. } if url.ends_with("/private/page")),
+            "{error:?}"
+        );
+        let targets: Vec<String> = server.received().into_iter().map(|r| r.target).collect();
+        assert_eq!(targets, ["/robots.txt", "/start"]);
+    }
+
+    #[tokio::test]
+    async fn test_robots_group_follows_the_user_agent() {
+        let server = TestServer::start(|request| match request.target.as_str() {
+            "/robots.txt" => {
+                Reply::text("User-agent: gurl\nDisallow:\n\nUser-agent: mybot\nDisallow: /\n")
+            }
+            _ => Reply::text("page"),
+        })
+        .await;
+        let client = GurlClient::builder()
+            .user_agent("mybot/2.0")
+            .robots(true)
+            .build()
+            .unwrap();
+        let error = client
+            .execute(GurlRequest::get(server.url("/page")))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, crate::Error::RobotsDisallowed { .. }),
+            "{error:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_robots_txt_is_read_up_to_the_limit() {
+        let mut robots = "User-agent: *\n".to_string();
+        robots.push_str(&"# padding\n".repeat(robots::MAX_ROBOTS_SIZE / 10));
+        robots.push_str("Disallow: /\n");
+        let server = TestServer::start(move |request| match request.target.as_str() {
+            "/robots.txt" => Reply::text(robots.clone()).without_length(),
+            _ => Reply::text("page"),
+        })
+        .await;
+        let client = GurlClient::new().unwrap().with_robots(true);
+        let response = client
+            .execute(GurlRequest::get(server.url("/page")))
+            .await
+            .unwrap();
+        assert!(response.response.robots.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_needs_no_oauth_token() {
+        let server = TestServer::start(|request| match request.target.as_str() {
+            "/token" => Reply::new(200)
+                .header("content-type", "application/json")
+                .body(r#"{"access_token":"t"}"#),
+            _ => Reply::text("doc").header("cache-control", "max-age=600"),
+        })
+        .await;
+        let dir = std::env::temp_dir().join(format!("gurl-oauth-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let client = GurlClient::new()
+            .unwrap()
+            .with_cache(HttpCache::open(&dir).unwrap());
+        let oauth = OAuth2::new(
+            "api",
+            OAuthProfile {
+                token_url: server.url("/token").to_string(),
+                grant: OAuthGrant::ClientCredentials,
+                client_id: "gurl".to_string(),
+                client_secret: None,
+                scope: None,
+                audience: None,
+                refresh_token: None,
+                device_authorization_url: None,
+            },
+        )
+        .without_token_cache();
+        for cache_mode in [CacheMode::Default, CacheMode::Default, CacheMode::Offline] {
+            let req = GurlRequest::get(server.url("/doc"))
+                .with_auth(Auth::OAuth2(oauth.clone()))
+                .with_cache_mode(cache_mode);
+            client.execute(req).await.unwrap();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        let targets: Vec<String> = server.received().into_iter().map(|r| r.target).collect();
+        assert_eq!(targets, ["/token", "/doc"]);
+    }
+
+    #[tokio::test]
+    async fn test_range_requests_bypass_the_cache() {
+        let server = TestServer::start(|request| match request.header("range") {
+            Some(_) => Reply::new(206)
+                .header("content-range", "bytes 0-3/8")
+                .header("cache-control", "max-age=600")
+                .body("part"),
+            None => Reply::text("complete").header("cache-control", "max-age=600"),
+        })
+        .await;
+        let dir = std::env::temp_dir().join(format!("gurl-range-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let client = GurlClient::new()
+            .unwrap()
+            .with_cache(HttpCache::open(&dir).unwrap());
+        let fetch = |range: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(range) = range {
+                headers.insert(RANGE, HeaderValue::from_static(range));
+            }
+            let req = GurlRequest::get(server.url("/file")).with_headers(headers);
+            let client = &client;
+            async move {
+                let response = client.execute(req).await.unwrap();
+                let status = response.response.cache.unwrap().status;
+                (
+                    String::from_utf8(response.content.raw_body).unwrap(),
+                    status,
+                )
+            }
+        };
+
+        // The partial body is not stored, so the full GET goes to the origin
+        assert_eq!(
+            fetch(Some("bytes=0-3")).await,
+            ("part".into(), CacheStatus::Miss)
+        );
+        assert_eq!(fetch(None).await, ("complete".into(), CacheStatus::Miss));
+        assert_eq!(fetch(None).await, ("complete".into(), CacheStatus::Hit));
+        // and the stored full body does not answer a Range request
+        assert_eq!(
+            fetch(Some("bytes=0-3")).await,
+            ("part".into(), CacheStatus::Miss)
+        );
+        assert_eq!(server.received().len(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_s3_request_is_signed() {
+        use sha2::{Digest, Sha256};
+        let server = TestServer::start(|_| Reply::new(200)).await;
+        let signer = SigV4::new(AwsCredentials::new("AKID", "secret"), "eu-west-1", "s3");
+        let mut req = GurlRequest::get(Url::parse("s3://bucket/report.csv").unwrap())
+            .with_method(Method::PUT)
+            .with_body(Body::Raw(b"a,b\n".to_vec()))
+            .with_auth(Auth::SigV4(signer));
+        sigv4::resolve_s3(&mut req, || None, Some(server.url("/").as_str())).unwrap();
+        GurlClient::new().unwrap().execute(req).await.unwrap();
+
+        let received = &server.received()[0];
+        assert_eq!(received.target, "/bucket/report.csv");
+        let authorization = received.header("authorization").unwrap();
+        assert!(
+            authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKID/"),
+            "{authorization}"
+        );
+        assert!(authorization.contains("/eu-west-1/s3/aws4_request"));
+        assert_eq!(
+            received.header("x-amz-content-sha256"),
+            Some(crate::hex(&Sha256::digest(b"a,b\n")).as_str())
+        );
+        assert!(received.header("x-amz-date").is_some());
+    }
+
+    /// `/hop/N` redirects to `/hop/N-1`, and `/hop/0` answers.
+    async fn hops() -> TestServer {
+        TestServer::start(|request| match request.target.strip_prefix("/hop/") {
+            Some("0") | None => Reply::text("done"),
+            Some(n) => {
+                let next = n.parse::<u32>().unwrap() - 1;
+                Reply::redirect(302, &format!("/hop/{next}")).header("set-cookie", "seen=1")
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_redirect_hops_are_recorded() {
+        let server = hops().await;
+        let client = GurlClient::new().unwrap();
+        let response = client
+            .execute(GurlRequest::get(server.url("/hop/2")))
+            .await
+            .unwrap();
+        let meta = &response.response;
+        assert_eq!(meta.status, 200);
+        assert_eq!(meta.final_url, server.url("/hop/0").as_str());
+        let hops: Vec<(&str, u16, &str)> = meta
+            .redirects
+            .iter()
+            .map(|hop| (hop.url.as_str(), hop.status, hop.location.as_str()))
+            .collect();
+        assert_eq!(
+            hops,
+            [
+                (
+                    server.url("/hop/2").as_str(),
+                    302,
+                    server.url("/hop/1").as_str()
+                ),
+                (
+                    server.url("/hop/1").as_str(),
+                    302,
+                    server.url("/hop/0").as_str()
+                ),
+            ]
+        );
+        assert_eq!(meta.redirects[0].set_cookie, ["seen=1"]);
+
+        // Not following leaves the redirect as the response
+        let response = client
+            .execute(GurlRequest::get(server.url("/hop/2")).no_redirects())
+            .await
+            .unwrap();
+        assert_eq!(response.response.status, 302);
+        assert!(response.response.redirects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_redirects() {
+        let server = hops().await;
+        let client = GurlClient::new().unwrap();
+        let req = GurlRequest::get(server.url("/hop/3")).with_max_redirects(3);
+        assert_eq!(
+            client.execute(req).await.unwrap().response.redirects.len(),
+            3
+        );
+
+        let req = GurlRequest::get(server.url("/hop/3")).with_max_redirects(2);
+        let error = client.execute(req).await.unwrap_err();
+        assert!(
+            matches!(error, crate::Error::TooManyRedirects { max: 2, .. }),
+            "{error:?}"
+        );
+        // The redirect past the limit is not followed
+        assert_eq!(server.received().len(), 4 + 3);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_method_changes() {
+        let server = TestServer::start(|request| match request.target.as_str() {
+            "/done" => Reply::text("done"),
+            target => Reply::redirect(target[1..].parse().unwrap(), "/done"),
+        })
+        .await;
+        let client = GurlClient::new().unwrap();
+        for (status, method, kept) in [
+            (303, Method::POST, false),
+            (303, Method::PUT, false),
+            (301, Method::POST, false),
+            (302, Method::POST, false),
+            (302, Method::PUT, true),
+            (307, Method::POST, true),
+            (308, Method::POST, true),
+        ] {
+            let req = GurlRequest::get(server.url(&format!("/{status}")))
+                .with_method(method.clone())
+                .with_body(Body::Json(serde_json::json!({"a": 1})));
+            client.execute(req).await.unwrap();
+            let landed = server.received().pop().unwrap();
+            assert_eq!(landed.target, "/done");
+            let expected = if kept { method.as_str() } else { "GET" };
+            assert_eq!(landed.method, expected, "{status} after {method}");
+            assert_eq!(landed.body.is_empty(), !kept, "{status} after {method}");
+            assert_eq!(
+                landed.header("content-type").is_some(),
+                kept,
+                "{status} after {method}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cross_origin_redirect_drops_credentials() {
+        let other = TestServer::start(|_| Reply::text("landed")).await;
+        let target = other.url("/land").to_string();
+        let server = TestServer::start(move |request| match request.target.as_str() {
+            "/same" => Reply::text("same"),
+            "/local" => Reply::redirect(302, "/same"),
+            _ => Reply::redirect(302, &target),
+        })
+        .await;
+        let client = GurlClient::new().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        headers.insert(COOKIE, HeaderValue::from_static("sid=1"));
+
+        let req = GurlRequest::get(server.url("/local")).with_headers(headers.clone());
+        client.execute(req).await.unwrap();
+        let same = server.received().pop().unwrap();
+        assert_eq!(same.header("authorization"), Some("Bearer secret"));
+        assert_eq!(same.header("cookie"), Some("sid=1"));
+
+        let req = GurlRequest::get(server.url("/away")).with_headers(headers);
+        client.execute(req).await.unwrap();
+        let signer = SigV4::new(AwsCredentials::new("AKID", "secret"), "us-east-1", "s3");
+        let req = GurlRequest::get(server.url("/away")).with_auth(Auth::SigV4(signer));
+        client.execute(req).await.unwrap();
+
+        let away = server.received();
+        assert_eq!(away[1].header("authorization"), Some("Bearer secret"));
+        assert!(away[2].header("authorization").is_some());
+        let landed = other.received();
+        assert_eq!(landed.len(), 2);
+        for request in &landed {
+            assert_eq!(request.header("authorization"), None);
+            assert_eq!(request.header("cookie"), None);
+            assert_eq!(request.header("x-amz-date"), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_conversion_is_a_warning() {
+        // Claims to be a DOCX but is no zip archive
+        let server = TestServer::start(|_| {
+            Reply::new(200)
+                .header(
+                    "content-type",
+                    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                )
+                .body("not a zip")
+        })
+        .await;
+        let response = GurlClient::new()
+            .unwrap()
+            .execute(GurlRequest::get(server.url("/report.docx")))
+            .await
+            .unwrap();
+        assert_eq!(response.content.content_type, "raw");
+        assert_eq!(response.content.body, "not a zip");
+        assert_eq!(response.response.warnings.len(), 1);
+        assert!(
+            response.response.warnings[0].starts_with("conversion failed"),
+            "{:?}",
+            response.response.warnings
+        );
+    }
+}
@@ -0,0 +1,521 @@
+// OAuth2 access tokens (RFC 6749, device flow RFC 8628).
+//
+// A profile names a token endpoint and a grant. The access token is cached
+// on disk, one `<profile>.json` per profile under the gurl cache directory,
+// until shortly before it expires. When a server rejects a cached token
+// with 401 the client asks for a new one, through the refresh token when
+// there is one, and retries once.
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Tokens this close to expiry are treated as expired
+const EXPIRY_MARGIN: chrono::Duration = chrono::Duration::seconds(30);
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(30);
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OAuthGrant {
+    #[default]
+    ClientCredentials,
+    RefreshToken,
+    /// Ask the user to approve the login in a browser, printing the code to stderr
+    DeviceCode,
+}
+
+/// One `oauth:` entry of the config file.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct OAuthProfile {
+    pub token_url: String,
+    #[serde(default)]
+    pub grant: OAuthGrant,
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+    /// Initial refresh token for the `refresh_token` grant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Required for the `device_code` grant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_authorization_url: Option<String>,
+}
+
+impl fmt::Debug for OAuthProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuthProfile")
+            .field("token_url", &self.token_url)
+            .field("grant", &self.grant)
+            .field("client_id", &self.client_id)
+            .field("scope", &self.scope)
+            .finish_non_exhaustive()
+    }
+}
+
+/// OAuth2 credentials for a request: a named profile plus where its tokens
+/// are cached.
+#[derive(Clone)]
+pub struct OAuth2 {
+    pub name: String,
+    pub profile: OAuthProfile,
+    token_cache: Option<PathBuf>,
+    device_prompt: Option<PromptFn>,
+}
+
+type PromptFn = Arc<dyn Fn(&DevicePrompt) + Send + Sync>;
+
+impl fmt::Debug for OAuth2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuth2")
+            .field("name", &self.name)
+            .field("profile", &self.profile)
+            .field("token_cache", &self.token_cache)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for OAuth2 {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.profile == other.profile
+            && self.token_cache == other.token_cache
+    }
+}
+
+/// What the user has to do to approve a device code sign-in (RFC 8628).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DevicePrompt {
+    pub verification_uri: String,
+    /// The verification URI with the code filled in, if the provider has one
+    pub verification_uri_complete: Option<String>,
+    pub user_code: String,
+}
+
+/// A token as cached on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedToken {
+    access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    /// The endpoint and client that issued the token; a profile edited to
+    /// point elsewhere must not reuse it
+    token_url: String,
+    client_id: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    interval: Option<u64>,
+}
+
+enum TokenError {
+    /// The endpoint answered with an OAuth error code
+    OAuth(String, String),
+    Other(crate::Error),
+}
+
+impl From<TokenError> for crate::Error {
+    fn from(e: TokenError) -> Self {
+        match e {
+            TokenError::OAuth(code, description) if description.is_empty() => {
+                crate::Error::Auth(code)
+            }
+            TokenError::OAuth(code, description) => {
+                crate::Error::Auth(format!("{code}: {description}"))
+            }
+            TokenError::Other(e) => e,
+        }
+    }
+}
+
+impl OAuth2 {
+    /// Credentials for `profile`, caching tokens in the default directory.
+    pub fn new(name: impl Into<String>, profile: OAuthProfile) -> Self {
+        Self {
+            name: name.into(),
+            profile,
+            token_cache: Self::default_cache_dir(),
+            device_prompt: None,
+        }
+    }
+
+    /// Show the device code sign-in instructions through `prompt`. The
+    /// device code grant fails without one.
+    pub fn on_device_prompt(
+        mut self,
+        prompt: impl Fn(&DevicePrompt) + Send + Sync + 'static,
+    ) -> Self {
+        self.device_prompt = Some(Arc::new(prompt));
+        self
+    }
+
+    /// Cache tokens in `dir` instead of the default directory.
+    pub fn with_token_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.token_cache = Some(dir.into());
+        self
+    }
+
+    /// Keep tokens in memory only; every request fetches a new one.
+    pub fn without_token_cache(mut self) -> Self {
+        self.token_cache = None;
+        self
+    }
+
+    /// The user's cache directory (e.g. `~/.cache/gurl/oauth`).
+    pub fn default_cache_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|d| d.join("gurl").join("oauth"))
+    }
+
+    /// An access token, from the cache if it is still valid. With `rejected`
+    /// the cached token was refused by a server and a new one is fetched.
+    pub(crate) async fn token(
+        &self,
+        http: &Client,
+        rejected: bool,
+    ) -> Result<String, crate::Error> {
+        let cached = self.load();
+        if !rejected
+            && let Some(token) = &cached
+            && token
+                .expires_at
+                .is_none_or(|at| at - EXPIRY_MARGIN > Utc::now())
+        {
+            return Ok(token.access_token.clone());
+        }
+
+        let refresh_token = cached
+            .as_ref()
+            .and_then(|t| t.refresh_token.clone())
+            .or_else(|| self.profile.refresh_token.clone());
+        let response = match refresh_token {
+            Some(refresh_token) => {
+                let params = [
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token.as_str()),
+                ];
+                match self.request(http, &self.profile.token_url, &params).await {
+                    Ok(response) => Some((response, refresh_token)),
+                    // A revoked refresh token: fall back to the profile's grant
+                    Err(TokenError::OAuth(..))
+                        if self.profile.grant != OAuthGrant::RefreshToken =>
+                    {
+                        None
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            None => None,
+        };
+        let (response, previous_refresh) = match response {
+            Some((response, refresh_token)) => (response, Some(refresh_token)),
+            None => (self.grant(http).await?, None),
+        };
+
+        let token = CachedToken {
+            access_token: response.access_token,
+            expires_at: response
+                .expires_in
+                .map(|secs| Utc::now() + chrono::Duration::seconds(secs as i64)),
+            // Servers may keep the refresh token and not send it again
+            refresh_token: response.refresh_token.or(previous_refresh),
+            token_url: self.profile.token_url.clone(),
+            client_id: self.profile.client_id.clone(),
+        };
+        // A token that could not be cached still works for this request
+        let _ = self.save(&token);
+        Ok(token.access_token)
+    }
+
+    async fn grant(&self, http: &Client) -> Result<TokenResponse, crate::Error> {
+        match self.profile.grant {
+            OAuthGrant::ClientCredentials => {
+                let params = [("grant_type", "client_credentials")];
+                Ok(self.request(http, &self.profile.token_url, &params).await?)
+            }
+            OAuthGrant::RefreshToken => Err(crate::Error::Auth(format!(
+                "OAuth profile '{}' has no refresh token",
+                self.name
+            ))),
+            OAuthGrant::DeviceCode => self.device_flow(http).await,
+        }
+    }
+
+    async fn device_flow(&self, http: &Client) -> Result<TokenResponse, crate::Error> {
+        let prompt = self.device_prompt.as_ref().ok_or_else(|| {
+            crate::Error::Config(format!(
+                "OAuth profile '{}' uses the device code grant, which needs a device prompt",
+                self.name
+            ))
+        })?;
+        let url = self
+            .profile
+            .device_authorization_url
+            .as_ref()
+            .ok_or_else(|| {
+                crate::Error::Config(format!(
+                    "OAuth profile '{}' needs a device_authorization_url",
+                    self.name
+                ))
+            })?;
+        let response = self
+            .post(http, url, &[])
+            .await
+            .map_err(crate::Error::from)?;
+        let device: DeviceAuthorization = serde_json::from_slice(&response)
+            .map_err(|e| crate::Error::Auth(format!("invalid device authorization: {e}")))?;
+
+        prompt(&DevicePrompt {
+            verification_uri: device.verification_uri,
+            verification_uri_complete: device.verification_uri_complete,
+            user_code: device.user_code,
+        });
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(device.expires_in);
+        let mut interval = Duration::from_secs(device.interval.unwrap_or(5));
+        let params = [
+            ("grant_type", DEVICE_CODE_GRANT),
+            ("device_code", device.device_code.as_str()),
+        ];
+        loop {
+            if tokio::time::Instant::now() + interval > deadline {
+                return Err(crate::Error::Auth("device code expired".to_string()));
+            }
+            tokio::time::sleep(interval).await;
+            match self.request(http, &self.profile.token_url, &params).await {
+                Ok(response) => return Ok(response),
+                Err(TokenError::OAuth(code, _)) if code == "authorization_pending" => {}
+                Err(TokenError::OAuth(code, _)) if code == "slow_down" => {
+                    interval += Duration::from_secs(5);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    async fn request(
+        &self,
+        http: &Client,
+        url: &str,
+        params: &[(&str, &str)],
+    ) -> Result<TokenResponse, TokenError> {
+        let body = self.post(http, url, params).await?;
+        serde_json::from_slice(&body).map_err(|e| {
+            TokenError::Other(crate::Error::Auth(format!("invalid token response: {e}")))
+        })
+    }
+
+    /// POST a form to an OAuth endpoint, adding the client and scope params.
+    async fn post(
+        &self,
+        http: &Client,
+        url: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Vec<u8>, TokenError> {
+        let profile = &self.profile;
+        let mut form: Vec<(&str, &str)> = params.to_vec();
+        form.push(("client_id", &profile.client_id));
+        if let Some(secret) = &profile.client_secret {
+            form.push(("client_secret", secret));
+        }
+        if let Some(scope) = &profile.scope {
+            form.push(("scope", scope));
+        }
+        if let Some(audience) = &profile.audience {
+            form.push(("audience", audience));
+        }
+
+        let response = http
+            .post(url)
+            .form(&form)
+            .timeout(TOKEN_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| TokenError::Other(crate::Error::Auth(format!("{url}: {e}"))))?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| TokenError::Other(crate::Error::Auth(format!("{url}: {e}"))))?;
+        if status.is_success() {
+            return Ok(body.to_vec());
+        }
+        match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(error) => Err(TokenError::OAuth(
+                error.error,
+                error.error_description.unwrap_or_default(),
+            )),
+            Err(_) => Err(TokenError::Other(crate::Error::Auth(format!(
+                "{url} returned {status}"
+            )))),
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        let valid = !self.name.is_empty()
+            && !self.name.starts_with('.')
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        let dir = self.token_cache.as_ref()?;
+        valid.then(|| dir.join(format!("{}.json", self.name)))
+    }
+
+    fn load(&self) -> Option<CachedToken> {
+        let json = std::fs::read(self.path()?).ok()?;
+        let token: CachedToken = serde_json::from_slice(&json).ok()?;
+        (token.token_url == self.profile.token_url && token.client_id == self.profile.client_id)
+            .then_some(token)
+    }
+
+    fn save(&self, token: &CachedToken) -> io::Result<()> {
+        let Some(path) = self.path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        crate::write_private(&tmp, &serde_json::to_vec_pretty(token)?)?;
+        std::fs::rename(&tmp, &path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::test_server::{Reply, TestServer};
+
+    /// A token endpoint answering each request with the next canned response.
+    async fn mock_endpoint(responses: Vec<(u16, &'static str)>) -> (String, TestServer) {
+        let mut responses = responses.into_iter();
+        let server = TestServer::start(move |_| {
+            let (status, body) = responses.next().expect("unexpected token request");
+            Reply::new(status)
+                .header("content-type", "application/json")
+                .body(body)
+        })
+        .await;
+        (server.url("/token").to_string(), server)
+    }
+
+    fn profile(token_url: &str, grant: OAuthGrant) -> OAuthProfile {
+        OAuthProfile {
+            token_url: token_url.to_string(),
+            grant,
+            client_id: "gurl".to_string(),
+            client_secret: Some("s3cret".to_string()),
+            scope: Some("read".to_string()),
+            audience: None,
+            refresh_token: None,
+            device_authorization_url: None,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gurl-oauth-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_cached_then_refreshed() {
+        let (url, server) = mock_endpoint(vec![
+            (200, r#"{"access_token":"a1","token_type":"Bearer","expires_in":3600,"refresh_token":"r1"}"#),
+            (200, r#"{"access_token":"a2","token_type":"Bearer","expires_in":3600}"#),
+        ])
+        .await;
+        let oauth = OAuth2::new("api", profile(&url, OAuthGrant::ClientCredentials))
+            .with_token_cache(temp_dir("cc"));
+        let http = Client::new();
+
+        assert_eq!(oauth.token(&http, false).await.unwrap(), "a1");
+        // Served from the disk cache, without a second request
+        assert_eq!(oauth.token(&http, false).await.unwrap(), "a1");
+        // A 401 swaps the token through the refresh token, which is kept
+        assert_eq!(oauth.token(&http, true).await.unwrap(), "a2");
+        assert_eq!(oauth.load().unwrap().refresh_token.as_deref(), Some("r1"));
+
+        let requests: Vec<String> = server.received().iter().map(|r| r.body_text()).collect();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("grant_type=client_credentials"));
+        assert!(requests[0].contains("client_secret=s3cret") && requests[0].contains("scope=read"));
+        assert!(requests[1].contains("grant_type=refresh_token&refresh_token=r1"));
+    }
+
+    #[tokio::test]
+    async fn test_expired_refresh_token_falls_back_to_grant() {
+        let (url, _) = mock_endpoint(vec![
+            (400, r#"{"error":"invalid_grant"}"#),
+            (200, r#"{"access_token":"fresh"}"#),
+        ])
+        .await;
+        let mut config = profile(&url, OAuthGrant::ClientCredentials);
+        config.refresh_token = Some("revoked".to_string());
+        let oauth = OAuth2::new("api", config).without_token_cache();
+        assert_eq!(oauth.token(&Client::new(), false).await.unwrap(), "fresh");
+
+        let (url, _) = mock_endpoint(vec![(400, r#"{"error":"invalid_grant"}"#)]).await;
+        let mut config = profile(&url, OAuthGrant::RefreshToken);
+        config.refresh_token = Some("revoked".to_string());
+        let oauth = OAuth2::new("api", config).without_token_cache();
+        let err = oauth.token(&Client::new(), false).await.unwrap_err();
+        assert!(matches!(err, crate::Error::Auth(e) if e == "invalid_grant"));
+    }
+
+    #[tokio::test]
+    async fn test_device_flow_polls_until_approved() {
+        let (url, server) = mock_endpoint(vec![
+            (200, r#"{"device_code":"d1","user_code":"ABCD","verification_uri":"https://example.com/device","expires_in":60,"interval":0}"#),
+            (400, r#"{"error":"authorization_pending"}"#),
+            (200, r#"{"access_token":"dev"}"#),
+        ])
+        .await;
+        let mut config = profile(&url, OAuthGrant::DeviceCode);
+        config.device_authorization_url = Some(url.clone());
+        let oauth = OAuth2::new("dev", config.clone()).without_token_cache();
+        let err = oauth.token(&Client::new(), false).await.unwrap_err();
+        assert!(matches!(err, crate::Error::Config(_)));
+
+        let shown = Arc::new(std::sync::Mutex::new(None));
+        let sink = shown.clone();
+        let oauth = OAuth2::new("dev", config)
+            .without_token_cache()
+            .on_device_prompt(move |prompt| *sink.lock().unwrap() = Some(prompt.clone()));
+        assert_eq!(oauth.token(&Client::new(), false).await.unwrap(), "dev");
+        assert!(server.received()[2].body_text().contains("device_code=d1"));
+        let shown = shown.lock().unwrap().clone().unwrap();
+        assert_eq!(shown.user_code, "ABCD");
+        assert_eq!(shown.verification_uri, "https://example.com/device");
+    }
+}
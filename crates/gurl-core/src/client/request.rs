@@ -1,6 +1,22 @@
-use reqwest::Method;
-use reqwest::header::HeaderMap;
+use crate::cache::CacheMode;
+use crate::client::auth::Auth;
+use crate::client::response::HeaderField;
+use crate::client::retry::RetryPolicy;
+use base64::prelude::*;
+use futures_util::{TryStreamExt, future, stream};
+use mime_guess::Mime;
+use reqwest::header::{CONTENT_LENGTH, HeaderMap, HeaderName, HeaderValue};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Method, RequestBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
 use url::Url;
 
 pub struct GurlRequest {
@@ -9,13 +25,144 @@ pub struct GurlRequest {
     pub headers: HeaderMap,
     pub body: Option<Body>,
     pub follow_redirects: bool,
+    /// Redirects followed before giving up, when `follow_redirects` is set
+    pub max_redirects: usize,
     pub timeout: Option<Duration>,
+    /// Refuse response bodies larger than this many bytes
+    pub max_size: Option<u64>,
+    pub retry: Option<RetryPolicy>,
+    pub cache_mode: CacheMode,
+    pub auth: Option<Auth>,
 }
 
 pub enum Body {
     Raw(Vec<u8>),
     Json(serde_json::Value),
     Form(Vec<(String, String)>),
+    /// multipart/form-data; file parts are streamed from disk
+    Multipart(Vec<FormPart>),
+    /// A file streamed from disk, reopened for every attempt
+    File(PathBuf),
+    /// A stream that can be read only once, such as stdin. Requests with
+    /// one are not retried and do not follow redirects that resend the body.
+    Stream(BodyStream),
+}
+
+impl Body {
+    /// Whether the body can be sent again, for retries and redirects.
+    pub(crate) fn is_replayable(&self) -> bool {
+        !matches!(self, Body::Stream(_))
+    }
+
+    pub(crate) fn apply(&self, builder: RequestBuilder) -> RequestBuilder {
+        match self {
+            Body::Raw(data) => builder.body(data.clone()),
+            Body::Json(value) => builder.json(value),
+            Body::Form(params) => builder.form(params),
+            Body::Multipart(parts) => {
+                let form = parts.iter().fold(Form::new(), |form, part| {
+                    form.part(part.name.clone(), part.to_part())
+                });
+                builder.multipart(form)
+            }
+            Body::File(path) => match std::fs::metadata(path) {
+                Ok(meta) => builder
+                    .header(CONTENT_LENGTH, meta.len())
+                    .body(file_body(path)),
+                Err(_) => builder.body(file_body(path)),
+            },
+            Body::Stream(stream) => builder.body(stream.take()),
+        }
+    }
+}
+
+/// One field of a multipart form. Serialized as `{"name": .., "text": ..}`
+/// or `{"name": .., "file": ..}`, plus the optional fields.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FormPart {
+    pub name: String,
+    #[serde(flatten)]
+    pub value: PartValue,
+    /// File name sent with the part; defaults to the file's own for files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// Content-Type of the part; guessed from the file name for files.
+    /// An unparseable type is ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartValue {
+    Text(String),
+    File(PathBuf),
+}
+
+impl FormPart {
+    pub fn text(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: PartValue::Text(value.into()),
+            filename: None,
+            content_type: None,
+        }
+    }
+
+    pub fn file(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            name: name.into(),
+            filename: path.file_name().map(|n| n.to_string_lossy().into_owned()),
+            value: PartValue::File(path),
+            content_type: None,
+        }
+    }
+
+    pub fn with_filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    fn to_part(&self) -> Part {
+        let (part, guessed) = match &self.value {
+            PartValue::Text(text) => (Part::text(text.clone()), None),
+            PartValue::File(path) => {
+                let part = match std::fs::metadata(path) {
+                    Ok(meta) => Part::stream_with_length(file_body(path), meta.len()),
+                    Err(_) => Part::stream(file_body(path)),
+                };
+                (
+                    part,
+                    Some(mime_guess::from_path(path).first_or_octet_stream()),
+                )
+            }
+        };
+        let mime = self
+            .content_type
+            .as_deref()
+            .and_then(|mime| mime.parse::<Mime>().ok())
+            .or(guessed);
+        let part = match mime {
+            Some(mime) => part
+                .mime_str(mime.as_ref())
+                .expect("a parsed media type is valid"),
+            None => part,
+        };
+        let filename = self.filename.clone().or_else(|| match &self.value {
+            PartValue::File(path) => path.file_name().map(|n| n.to_string_lossy().into_owned()),
+            PartValue::Text(_) => None,
+        });
+        match filename {
+            Some(filename) => part.file_name(filename),
+            None => part,
+        }
+    }
 }
 
 impl GurlRequest {
@@ -26,7 +173,12 @@ impl GurlRequest {
             headers: HeaderMap::new(),
             body: None,
             follow_redirects: true,
+            max_redirects: 10,
             timeout: None,
+            max_size: None,
+            retry: None,
+            cache_mode: CacheMode::Default,
+            auth: None,
         }
     }
 
@@ -50,8 +202,480 @@ impl GurlRequest {
         self
     }
 
+    pub fn with_max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
     pub fn with_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = Some(timeout);
         self
     }
+
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    pub fn with_cache_mode(mut self, mode: CacheMode) -> Self {
+        self.cache_mode = mode;
+        self
+    }
+
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+}
+
+/// Parses curl's `-F` syntax: `name=value` or `name=@path`, then optional
+/// `;type=...` and `;filename=...`. Options are only recognized after a file
+/// or when one of them comes first, so plain values may contain ';'.
+impl FromStr for FormPart {
+    type Err = String;
+
+    fn from_str(field: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = field
+            .split_once('=')
+            .ok_or_else(|| format!("invalid form field (expected 'key=value'): {field}"))?;
+        let (value, options) = match rest.split_once(';') {
+            Some((value, options))
+                if value.starts_with('@')
+                    || options.starts_with("type=")
+                    || options.starts_with("filename=") =>
+            {
+                (value, Some(options))
+            }
+            _ => (rest, None),
+        };
+        let mut part = match value.strip_prefix('@') {
+            Some(path) => FormPart::file(name, path),
+            None => FormPart::text(name, value),
+        };
+        for option in options.into_iter().flat_map(|options| options.split(';')) {
+            match option.trim().split_once('=') {
+                Some(("type", mime)) if mime.parse::<Mime>().is_ok() => {
+                    part = part.with_content_type(mime);
+                }
+                Some(("filename", filename)) => part = part.with_filename(filename),
+                _ => return Err(format!("invalid option '{option}' in form field: {field}")),
+            }
+        }
+        Ok(part)
+    }
+}
+
+/// A one-shot body read from an `AsyncRead`.
+pub struct BodyStream(Mutex<Option<reqwest::Body>>);
+
+impl BodyStream {
+    pub fn new(reader: impl AsyncRead + Send + 'static) -> Self {
+        Self(Mutex::new(Some(reqwest::Body::wrap_stream(
+            ReaderStream::new(reader),
+        ))))
+    }
+
+    pub fn stdin() -> Self {
+        Self::new(tokio::io::stdin())
+    }
+
+    /// The stream, or one that fails if it was already sent.
+    fn take(&self) -> reqwest::Body {
+        self.0.lock().unwrap().take().unwrap_or_else(|| {
+            let sent = io::Error::other("the request body was a stream and was already sent");
+            reqwest::Body::wrap_stream(stream::once(future::ready(Err::<Vec<u8>, _>(sent))))
+        })
+    }
+}
+
+/// Stream a file, opening it only when the body is sent so that each
+/// attempt reads it from the start.
+fn file_body(path: &Path) -> reqwest::Body {
+    let open = tokio::fs::File::open(path.to_path_buf());
+    reqwest::Body::wrap_stream(stream::once(open).map_ok(ReaderStream::new).try_flatten())
+}
+
+/// The serialized form of a request, as `gurl exec` reads it:
+///
+/// ```json
+/// {"method": "POST", "url": "https://api.example.com/items",
+///  "headers": {"accept": "application/json"}, "body": {"json": {"name": "x"}}}
+/// ```
+///
+/// Only `url` is required; the rest default as in `GurlRequest::get`. A
+/// header value may be a list, for repeated headers, and a value that is not
+/// UTF-8 is `{"value": <Latin-1>, "value_base64": ..}` as in the envelope's
+/// header list. `timeout` is in seconds.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequestData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    url: Url,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, HeaderValues>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<BodyData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    follow_redirects: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_redirects: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry: Option<RetryPolicy>,
+    #[serde(default)]
+    cache_mode: CacheMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth: Option<Auth>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum HeaderValues {
+    One(HeaderText),
+    Many(Vec<HeaderText>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum HeaderText {
+    Text(String),
+    /// Not UTF-8: `value` for reading, `value_base64` for the exact bytes
+    Bytes {
+        value: String,
+        value_base64: String,
+    },
+}
+
+impl HeaderText {
+    fn new(name: &HeaderName, value: &HeaderValue) -> Self {
+        let field = HeaderField::new(name, value);
+        match field.value_base64 {
+            Some(value_base64) => HeaderText::Bytes {
+                value: field.value,
+                value_base64,
+            },
+            None => HeaderText::Text(field.value),
+        }
+    }
+
+    fn to_value(&self) -> Result<HeaderValue, String> {
+        let bytes = match self {
+            HeaderText::Text(text) => text.as_bytes().to_vec(),
+            HeaderText::Bytes { value_base64, .. } => BASE64_STANDARD
+                .decode(value_base64)
+                .map_err(|e| format!("invalid value_base64: {e}"))?,
+        };
+        HeaderValue::from_bytes(&bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// The serialized form of a `Body`: `{"text": ..}` or `{"base64": ..}` for
+/// raw bytes, `{"json": ..}`, `{"form": ..}` with a map or a list of pairs,
+/// `{"multipart": [..]}` or `{"file": path}`. Streams have none.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum BodyData {
+    Text(String),
+    Base64(String),
+    Json(serde_json::Value),
+    Form(FormData),
+    Multipart(Vec<FormPart>),
+    File(PathBuf),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FormData {
+    Pairs(Vec<(String, String)>),
+    Map(BTreeMap<String, String>),
+}
+
+impl TryFrom<&Body> for BodyData {
+    type Error = &'static str;
+
+    fn try_from(body: &Body) -> Result<Self, Self::Error> {
+        Ok(match body {
+            Body::Raw(bytes) => match std::str::from_utf8(bytes) {
+                Ok(text) => BodyData::Text(text.to_string()),
+                Err(_) => BodyData::Base64(BASE64_STANDARD.encode(bytes)),
+            },
+            Body::Json(value) => BodyData::Json(value.clone()),
+            Body::Form(pairs) => BodyData::Form(FormData::Pairs(pairs.clone())),
+            Body::Multipart(parts) => BodyData::Multipart(parts.clone()),
+            Body::File(path) => BodyData::File(path.clone()),
+            Body::Stream(_) => return Err("a stream body cannot be serialized"),
+        })
+    }
+}
+
+impl TryFrom<BodyData> for Body {
+    type Error = base64::DecodeError;
+
+    fn try_from(data: BodyData) -> Result<Self, Self::Error> {
+        Ok(match data {
+            BodyData::Text(text) => Body::Raw(text.into_bytes()),
+            BodyData::Base64(encoded) => Body::Raw(BASE64_STANDARD.decode(encoded)?),
+            BodyData::Json(value) => Body::Json(value),
+            BodyData::Form(FormData::Pairs(pairs)) => Body::Form(pairs),
+            BodyData::Form(FormData::Map(map)) => Body::Form(map.into_iter().collect()),
+            BodyData::Multipart(parts) => Body::Multipart(parts),
+            BodyData::File(path) => Body::File(path),
+        })
+    }
+}
+
+impl Serialize for Body {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        BodyData::try_from(self)
+            .map_err(ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Body::try_from(BodyData::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for GurlRequest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut headers = BTreeMap::<String, HeaderValues>::new();
+        for name in self.headers.keys() {
+            let mut values: Vec<HeaderText> = self
+                .headers
+                .get_all(name)
+                .iter()
+                .map(|value| HeaderText::new(name, value))
+                .collect();
+            let values = match values.len() {
+                1 => HeaderValues::One(values.remove(0)),
+                _ => HeaderValues::Many(values),
+            };
+            headers.insert(name.to_string(), values);
+        }
+        RequestData {
+            method: Some(self.method.to_string()),
+            url: self.url.clone(),
+            headers,
+            body: self
+                .body
+                .as_ref()
+                .map(BodyData::try_from)
+                .transpose()
+                .map_err(ser::Error::custom)?,
+            follow_redirects: Some(self.follow_redirects),
+            max_redirects: Some(self.max_redirects),
+            timeout: self.timeout.map(|timeout| timeout.as_secs_f64()),
+            max_size: self.max_size,
+            retry: self.retry.clone(),
+            cache_mode: self.cache_mode,
+            auth: self.auth.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for GurlRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = RequestData::deserialize(deserializer)?;
+        let mut req = GurlRequest::get(data.url);
+        if let Some(method) = data.method {
+            req.method = Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .map_err(|_| de::Error::custom(format!("invalid HTTP method: {method}")))?;
+        }
+        for (name, values) in data.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| de::Error::custom(format!("invalid header name: {name}")))?;
+            let values = match values {
+                HeaderValues::One(value) => vec![value],
+                HeaderValues::Many(values) => values,
+            };
+            for value in values {
+                let value = value.to_value().map_err(|e| {
+                    de::Error::custom(format!("invalid value for header {name}: {e}"))
+                })?;
+                req.headers.append(&name, value);
+            }
+        }
+        req.body = data
+            .body
+            .map(Body::try_from)
+            .transpose()
+            .map_err(de::Error::custom)?;
+        if let Some(follow) = data.follow_redirects {
+            req.follow_redirects = follow;
+        }
+        if let Some(max) = data.max_redirects {
+            req.max_redirects = max;
+        }
+        req.timeout = data
+            .timeout
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(de::Error::custom)?;
+        req.max_size = data.max_size;
+        req.retry = data.retry;
+        req.cache_mode = data.cache_mode;
+        req.auth = data.auth;
+        Ok(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::test_server::{Reply, TestServer};
+
+    async fn capture() -> TestServer {
+        TestServer::start(|_| Reply::new(200)).await
+    }
+
+    #[tokio::test]
+    async fn test_multipart_file_upload() {
+        let dir = std::env::temp_dir().join(format!("gurl-multipart-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("report.csv");
+        std::fs::write(&path, "a,b\n1,2\n").unwrap();
+
+        let server = capture().await;
+        let body = Body::Multipart(vec![
+            FormPart::text("title", "Q3"),
+            FormPart::file("data", &path),
+            FormPart::file("copy", &path)
+                .with_filename("renamed.txt")
+                .with_content_type("text/plain"),
+        ]);
+        let request = GurlRequest::get(server.url("/upload"))
+            .with_method(Method::POST)
+            .with_body(body);
+        let client = crate::GurlClient::new().unwrap();
+        client.execute(request).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let received = &server.received()[0];
+        assert!(
+            received
+                .header("content-type")
+                .is_some_and(|v| v.starts_with("multipart/form-data; boundary="))
+        );
+        let request = received.body_text();
+        assert!(request.contains("name=\"title\"\r\n\r\nQ3\r\n"));
+        assert!(request.contains(
+            "name=\"data\"; filename=\"report.csv\"\r\nContent-Type: text/csv\r\n\r\na,b\n1,2\n\r\n"
+        ));
+        assert!(request.contains(
+            "name=\"copy\"; filename=\"renamed.txt\"\r\nContent-Type: text/plain\r\n\r\na,b\n1,2\n\r\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_file_body_is_streamed_with_its_length() {
+        let path = std::env::temp_dir().join(format!("gurl-file-body-{}", std::process::id()));
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let server = capture().await;
+        let request = GurlRequest::get(server.url("/upload"))
+            .with_method(Method::PUT)
+            .with_body(Body::File(path.clone()));
+        let client = crate::GurlClient::new().unwrap();
+        client.execute(request).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let received = &server.received()[0];
+        assert_eq!(received.header("content-length"), Some("100000"));
+        assert_eq!(received.body, data);
+    }
+
+    #[test]
+    fn test_request_from_json() {
+        let req: GurlRequest = serde_json::from_str(
+            r#"{
+                "method": "post",
+                "url": "https://api.example.com/items",
+                "headers": {"accept": "application/json", "x-tag": ["a", "b"]},
+                "body": {"form": {"q": "rust", "page": "2"}},
+                "timeout": 2.5,
+                "retry": {"max_retries": 3, "retry_on": ["5xx", "429"]},
+                "cache_mode": "no_store",
+                "auth": {"bearer": "t0ken"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(req.method, Method::POST);
+        assert_eq!(req.headers["accept"], "application/json");
+        assert_eq!(req.headers.get_all("x-tag").iter().count(), 2);
+        assert!(matches!(&req.body, Some(Body::Form(pairs)) if pairs.len() == 2));
+        assert_eq!(req.timeout, Some(Duration::from_millis(2500)));
+        let retry = req.retry.as_ref().unwrap();
+        assert_eq!(retry.max_retries, 3);
+        assert_eq!(retry.base_delay, Duration::from_secs(1));
+        assert_eq!(req.cache_mode, CacheMode::NoStore);
+        assert!(matches!(&req.auth, Some(Auth::Bearer(token)) if token == "t0ken"));
+        assert!(req.follow_redirects);
+
+        // Only the URL is required; unknown fields are refused
+        let req: GurlRequest = serde_json::from_str(r#"{"url": "https://example.com/"}"#).unwrap();
+        assert_eq!((req.method, req.max_redirects), (Method::GET, 10));
+        assert!(
+            serde_json::from_str::<GurlRequest>(r#"{"url": "https://example.com/", "data": 1}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_request_json_round_trip() {
+        let req = GurlRequest::get("https://example.com/upload".parse().unwrap())
+            .with_method(Method::PUT)
+            .with_body(Body::Multipart(vec![
+                FormPart::text("title", "x"),
+                FormPart::file("doc", "/tmp/a.pdf").with_content_type("application/pdf"),
+            ]))
+            .no_redirects();
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(
+            json["body"]["multipart"][1],
+            serde_json::json!({"name": "doc", "file": "/tmp/a.pdf", "filename": "a.pdf", "content_type": "application/pdf"})
+        );
+        let back: GurlRequest = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+        assert!(!back.follow_redirects);
+
+        let raw = GurlRequest::get("https://example.com/".parse().unwrap())
+            .with_body(Body::Raw(vec![0xff, 0x00]));
+        assert_eq!(
+            serde_json::to_value(&raw).unwrap()["body"],
+            serde_json::json!({"base64": "/wA="})
+        );
+        let stream = raw.with_body(Body::Stream(BodyStream::new(tokio::io::empty())));
+        assert!(serde_json::to_value(&stream).is_err());
+    }
+
+    #[test]
+    fn test_non_utf8_header_round_trip() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-name", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        headers.append("x-name", HeaderValue::from_static("plain"));
+        let req = GurlRequest::get("https://example.com/".parse().unwrap()).with_headers(headers);
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(
+            json["headers"]["x-name"],
+            serde_json::json!([{"value": "caf\u{e9}", "value_base64": "Y2Fm6Q=="}, "plain"])
+        );
+        let back: GurlRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(back.headers, req.headers);
+
+        let invalid = r#"{"url": "https://example.com/", "headers": {"x": {"value": "", "value_base64": "!"}}}"#;
+        assert!(serde_json::from_str::<GurlRequest>(invalid).is_err());
+    }
 }
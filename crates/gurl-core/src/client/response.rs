@@ -1,32 +1,220 @@
+use crate::cache::CacheInfo;
+use crate::client::retry::RetryInfo;
+use crate::client::robots::RobotsDecision;
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, SET_COOKIE};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResponseMeta {
     pub status: u16,
     pub status_text: String,
-    pub headers: std::collections::HashMap<String, String>,
+    /// One value per header name, for lookups like `headers.content-type`.
+    /// Repeated headers are joined with ", ", except Set-Cookie, whose values
+    /// contain commas and are joined with newlines instead.
+    pub headers: HashMap<String, String>,
+    /// Every header value as received, repeats included
+    pub header_list: Vec<HeaderField>,
+    /// URL of the final response, after any redirects
+    pub final_url: String,
+    /// Redirects followed to reach `final_url`, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<RedirectHop>,
     pub timing: Timing,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tls: Option<TlsInfo>,
+    /// Present only when the request needed more than one attempt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<RetryInfo>,
+    /// Present only when the client has a cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheInfo>,
+    /// Proxy the request went through, password redacted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Present only when robots.txt compliance is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub robots: Option<RobotsDecision>,
+    /// Things the caller should know about, like disabled certificate checks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// A header as received. Names are lowercase. Values that are not valid
+/// UTF-8 are shown as Latin-1 in `value` and kept byte for byte in
+/// `value_base64`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeaderField {
+    pub name: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_base64: Option<String>,
+}
+
+impl HeaderField {
+    pub fn new(name: &HeaderName, value: &HeaderValue) -> Self {
+        let bytes = value.as_bytes();
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Self {
+                name: name.to_string(),
+                value: text.to_string(),
+                value_base64: None,
+            },
+            Err(_) => Self {
+                name: name.to_string(),
+                value: bytes.iter().map(|&b| b as char).collect(),
+                value_base64: Some(BASE64_STANDARD.encode(bytes)),
+            },
+        }
+    }
+
+    /// The exact bytes of the value.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.value_base64
+            .as_ref()
+            .and_then(|encoded| BASE64_STANDARD.decode(encoded).ok())
+            .unwrap_or_else(|| self.value.as_bytes().to_vec())
+    }
+
+    /// Every header in `map`. Values of one name stay in the order they were
+    /// received; names appear in the order first seen.
+    pub fn list(map: &HeaderMap) -> Vec<Self> {
+        map.iter()
+            .map(|(name, value)| Self::new(name, value))
+            .collect()
+    }
+
+    /// The fields back as a `HeaderMap`, skipping any that are not valid headers.
+    pub fn map(fields: &[Self]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for field in fields {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(field.name.as_bytes()),
+                HeaderValue::from_bytes(&field.bytes()),
+            ) {
+                map.append(name, value);
+            }
+        }
+        map
+    }
+
+    /// The flat view used for `ResponseMeta::headers`.
+    pub fn flatten(fields: &[Self]) -> HashMap<String, String> {
+        let mut flat: HashMap<String, String> = HashMap::new();
+        for field in fields {
+            let name = field.name.to_ascii_lowercase();
+            let separator = if name == SET_COOKIE.as_str() {
+                "\n"
+            } else {
+                ", "
+            };
+            flat.entry(name)
+                .and_modify(|joined| {
+                    joined.push_str(separator);
+                    joined.push_str(&field.value);
+                })
+                .or_insert_with(|| field.value.clone());
+        }
+        flat
+    }
 }
 
+/// One redirect response that was followed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+    /// Target of the redirect, resolved against `url`
+    pub location: String,
+    /// Cookies the redirect response set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub set_cookie: Vec<String>,
+    /// Time until the redirect response's headers arrived
+    pub elapsed_ms: u64,
+}
+
+/// Request timing. `dns_ms`, `connect_ms` and `tls_ms` are the durations of
+/// each connection phase and are absent when a pooled connection was reused.
+/// `first_byte_ms` and `total_ms` are measured from the start of the request.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Timing {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dns_ms: Option<u64>,
+    /// TCP connect, after DNS resolution
     #[serde(skip_serializing_if = "Option::is_none")]
     pub connect_ms: Option<u64>,
+    /// TLS handshake, after TCP connect
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tls_ms: Option<u64>,
+    /// Time until response headers were received
     #[serde(skip_serializing_if = "Option::is_none")]
     pub first_byte_ms: Option<u64>,
+    /// Time until the full body was read
     pub total_ms: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// TLS session details for an HTTPS request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TlsInfo {
     pub version: String,
     pub cipher: String,
+    /// Application protocol negotiated over the connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<String>,
+    /// Issuer of the leaf certificate
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cert_issuer: Option<String>,
+    /// Certificate chain presented by the server, leaf first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub certificates: Vec<CertificateInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sans: Vec<String>,
+    pub serial: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// Days until `not_after`; negative once the certificate has expired
+    pub expires_in_days: i64,
+    /// SHA-256 fingerprint of the DER-encoded certificate
+    pub sha256: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_list_keeps_every_value() {
+        let mut map = HeaderMap::new();
+        map.append(
+            "set-cookie",
+            HeaderValue::from_static("a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT"),
+        );
+        map.append("link", HeaderValue::from_static("</a>; rel=next"));
+        map.append("set-cookie", HeaderValue::from_static("b=2"));
+        map.append("link", HeaderValue::from_static("</b>; rel=prev"));
+        map.append("x-name", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+
+        let list = HeaderField::list(&map);
+        assert_eq!(list.len(), 5);
+        let flat = HeaderField::flatten(&list);
+        assert_eq!(
+            flat["set-cookie"],
+            "a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT\nb=2"
+        );
+        assert_eq!(flat["link"], "</a>; rel=next, </b>; rel=prev");
+
+        // Latin-1 text survives the round trip byte for byte
+        assert_eq!(flat["x-name"], "café");
+        let json = serde_json::to_string(&list).unwrap();
+        let back: Vec<HeaderField> = serde_json::from_str(&json).unwrap();
+        assert_eq!(HeaderField::map(&back), map);
+    }
 }
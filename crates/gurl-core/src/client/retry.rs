@@ -0,0 +1,368 @@
+// Retry engine: backoff strategies, retry conditions and Retry-After handling.
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How the delay grows between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    /// delay, delay, delay
+    Fixed,
+    /// delay, delay*2, delay*3
+    Linear,
+    /// delay, delay*2, delay*4
+    #[serde(alias = "exp")]
+    Exponential,
+}
+
+impl FromStr for Backoff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Self::Fixed),
+            "linear" => Ok(Self::Linear),
+            "exponential" | "exp" => Ok(Self::Exponential),
+            other => Err(format!(
+                "unknown backoff strategy '{other}' (expected fixed, linear or exponential)"
+            )),
+        }
+    }
+}
+
+/// A failure class that makes a request eligible for another attempt.
+/// Serialized as in `--retry-on`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetryCondition {
+    /// Any 5xx status code
+    #[serde(rename = "5xx")]
+    ServerError,
+    /// 429 Too Many Requests
+    #[serde(rename = "429")]
+    TooManyRequests,
+    /// The request timed out
+    #[serde(rename = "timeout")]
+    Timeout,
+    /// Connection refused, reset or otherwise failed to establish
+    #[serde(rename = "connection", alias = "connect")]
+    Connection,
+}
+
+impl FromStr for RetryCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "5xx" => Ok(Self::ServerError),
+            "429" => Ok(Self::TooManyRequests),
+            "timeout" => Ok(Self::Timeout),
+            "connection" | "connect" => Ok(Self::Connection),
+            other => Err(format!(
+                "unknown retry condition '{other}' (expected 5xx, 429, timeout or connection)"
+            )),
+        }
+    }
+}
+
+/// Serialized with delays in seconds; missing fields take their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt
+    pub max_retries: u32,
+    pub backoff: Backoff,
+    /// Delay before the first retry; later delays derive from it
+    #[serde(with = "crate::secs")]
+    pub base_delay: Duration,
+    /// Upper bound for any single delay, including one requested via Retry-After
+    #[serde(with = "crate::secs")]
+    pub max_delay: Duration,
+    /// Randomize each delay to avoid synchronized retries from many clients
+    pub jitter: bool,
+    pub retry_on: Vec<RetryCondition>,
+    /// Also retry methods that are not idempotent (POST, PATCH) after a transport
+    /// error, 5xx or 429 without Retry-After. Off by default: the server may have
+    /// acted on the request.
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Backoff::Exponential,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_on: vec![
+                RetryCondition::ServerError,
+                RetryCondition::Timeout,
+                RetryCondition::Connection,
+            ],
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_retry_on(mut self, conditions: Vec<RetryCondition>) -> Self {
+        self.retry_on = conditions;
+        self
+    }
+
+    pub fn with_retry_non_idempotent(mut self, retry: bool) -> Self {
+        self.retry_non_idempotent = retry;
+        self
+    }
+
+    /// Whether a response with this status should be retried for a request
+    /// with this method. A 429 with Retry-After was not acted on, so it is
+    /// retried whatever the method.
+    pub fn retries_status(&self, method: &Method, status: StatusCode, headers: &HeaderMap) -> bool {
+        let rejected = status == StatusCode::TOO_MANY_REQUESTS && headers.contains_key(RETRY_AFTER);
+        if !self.retry_non_idempotent && !is_idempotent(method) && !rejected {
+            return false;
+        }
+        (status.is_server_error() && self.retry_on.contains(&RetryCondition::ServerError))
+            || (status == StatusCode::TOO_MANY_REQUESTS
+                && self.retry_on.contains(&RetryCondition::TooManyRequests))
+    }
+
+    /// Whether a transport error should be retried for a request with this method.
+    pub fn retries_error(&self, method: &Method, err: &reqwest::Error) -> bool {
+        if !self.retry_non_idempotent && !is_idempotent(method) {
+            return false;
+        }
+        (err.is_timeout() && self.retry_on.contains(&RetryCondition::Timeout))
+            || (err.is_connect() && self.retry_on.contains(&RetryCondition::Connection))
+    }
+
+    /// Delay before retry number `retry` (1-based), without jitter.
+    pub fn backoff_delay(&self, retry: u32) -> Duration {
+        let factor = match self.backoff {
+            Backoff::Fixed => 1,
+            Backoff::Linear => retry,
+            Backoff::Exponential => 2u32.saturating_pow(retry.saturating_sub(1)),
+        };
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Delay before retry number `retry`, honoring a server-provided Retry-After.
+    pub fn delay_for(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(after) = retry_after {
+            return after.min(self.max_delay);
+        }
+        let delay = self.backoff_delay(retry);
+        if self.jitter {
+            // "Equal jitter": keep half the delay, randomize the other half
+            let half = delay / 2;
+            half + half.mul_f64(fastrand::f64())
+        } else {
+            delay
+        }
+    }
+}
+
+/// RFC 9110 section 9.2.2: repeating these has the same effect as sending them once.
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// Parse a Retry-After header, either delta-seconds or an HTTP-date.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Retry summary reported in the envelope when more than one attempt was made.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryInfo {
+    pub attempts: u32,
+    pub total_retry_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_backoff_delays() {
+        let base = Duration::from_millis(100);
+        let fixed = RetryPolicy::new(3)
+            .with_backoff(Backoff::Fixed)
+            .with_base_delay(base);
+        let linear = RetryPolicy::new(3)
+            .with_backoff(Backoff::Linear)
+            .with_base_delay(base);
+        let exp = RetryPolicy::new(3).with_base_delay(base);
+
+        let delays = |p: &RetryPolicy| (1..=3).map(|n| p.backoff_delay(n)).collect::<Vec<_>>();
+        assert_eq!(delays(&fixed), [base, base, base]);
+        assert_eq!(delays(&linear), [base, base * 2, base * 3]);
+        assert_eq!(delays(&exp), [base, base * 2, base * 4]);
+
+        let capped = exp.with_max_delay(Duration::from_millis(250));
+        assert_eq!(capped.backoff_delay(3), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_jitter_stays_in_range() {
+        let policy = RetryPolicy::new(1).with_base_delay(Duration::from_millis(1000));
+        for _ in 0..50 {
+            let delay = policy.delay_for(1, None);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1000));
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        let policy = RetryPolicy::new(1).with_max_delay(Duration::from_secs(5));
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(120))),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn test_retry_conditions() {
+        let none = HeaderMap::new();
+        let policy = RetryPolicy::new(2);
+        assert!(policy.retries_status(&Method::GET, StatusCode::SERVICE_UNAVAILABLE, &none));
+        assert!(!policy.retries_status(&Method::GET, StatusCode::TOO_MANY_REQUESTS, &none));
+        assert!(!policy.retries_status(&Method::GET, StatusCode::NOT_FOUND, &none));
+
+        let conditions = "5xx,429"
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<RetryCondition>, _>>()
+            .unwrap();
+        let policy = policy.with_retry_on(conditions);
+        assert!(policy.retries_status(&Method::GET, StatusCode::TOO_MANY_REQUESTS, &none));
+        assert!("bogus".parse::<RetryCondition>().is_err());
+    }
+
+    #[test]
+    fn test_statuses_retry_idempotent_methods_only() {
+        let none = HeaderMap::new();
+        let mut retry_after = HeaderMap::new();
+        retry_after.insert(RETRY_AFTER, HeaderValue::from_static("1"));
+        let policy = RetryPolicy::new(2).with_retry_on(vec![
+            RetryCondition::ServerError,
+            RetryCondition::TooManyRequests,
+        ]);
+        let retries = |policy: &RetryPolicy, method, status, headers| {
+            policy.retries_status(&method, status, headers)
+        };
+        assert!(retries(
+            &policy,
+            Method::PUT,
+            StatusCode::BAD_GATEWAY,
+            &none
+        ));
+        assert!(!retries(
+            &policy,
+            Method::POST,
+            StatusCode::BAD_GATEWAY,
+            &none
+        ));
+        assert!(!retries(
+            &policy,
+            Method::PATCH,
+            StatusCode::TOO_MANY_REQUESTS,
+            &none
+        ));
+        // Rejected before being acted on
+        assert!(retries(
+            &policy,
+            Method::POST,
+            StatusCode::TOO_MANY_REQUESTS,
+            &retry_after
+        ));
+        assert!(!retries(
+            &policy,
+            Method::POST,
+            StatusCode::BAD_GATEWAY,
+            &retry_after
+        ));
+
+        let policy = policy.with_retry_non_idempotent(true);
+        assert!(retries(
+            &policy,
+            Method::POST,
+            StatusCode::BAD_GATEWAY,
+            &none
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transport_errors_retry_idempotent_methods_only() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let err = reqwest::Client::new()
+            .post(format!("http://{addr}/"))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_connect());
+
+        let policy = RetryPolicy::new(2);
+        assert!(policy.retries_error(&Method::GET, &err));
+        assert!(policy.retries_error(&Method::PUT, &err));
+        assert!(!policy.retries_error(&Method::POST, &err));
+        assert!(!policy.retries_error(&Method::PATCH, &err));
+
+        let policy = policy.with_retry_non_idempotent(true);
+        assert!(policy.retries_error(&Method::POST, &err));
+    }
+}
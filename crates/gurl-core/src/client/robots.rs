@@ -0,0 +1,327 @@
+// robots.txt compliance (RFC 9309).
+//
+// When enabled, the client fetches `/robots.txt` once per origin, keeps the
+// rules that apply to the client's user agent and checks every request URL against
+// them before it goes out. Crawl-delay is enforced through the per-host
+// limiter (see limits.rs).
+//
+// Following the RFC, a robots.txt that is unavailable (4xx) allows
+// everything, while one that is unreachable (5xx, network error) disallows
+// everything until it can be fetched.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// The product token of a User-Agent header, matched against `User-agent`
+/// lines: "gurl/0.3.0" and "gurl (+https://example.com)" are both "gurl".
+pub(crate) fn product_token(user_agent: &str) -> &str {
+    user_agent
+        .trim()
+        .split(['/', ' '])
+        .next()
+        .unwrap_or_default()
+}
+
+/// How long a fetched robots.txt is reused before being fetched again.
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// robots.txt fetches give up after this long and count as unreachable.
+pub(crate) const ROBOTS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parsers must handle at least this much of a robots.txt (RFC 9309 section 2.5).
+pub(crate) const MAX_ROBOTS_SIZE: usize = 500 * 1024;
+
+/// Outcome of the robots.txt check, reported in the envelope.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RobotsDecision {
+    pub allowed: bool,
+    /// The rule that decided, e.g. `Disallow: /private`, or why robots.txt
+    /// could not be consulted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crawl_delay_s: Option<f64>,
+}
+
+/// A fetched robots.txt, reduced to what applies to gurl.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RobotsTxt {
+    Rules(Rules),
+    /// 4xx: no restrictions
+    Unavailable(u16),
+    /// 5xx or network failure: assume complete disallow
+    Unreachable(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Rules {
+    rules: Vec<Rule>,
+    crawl_delay: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl RobotsTxt {
+    pub(crate) fn decide(&self, url: &Url) -> RobotsDecision {
+        match self {
+            RobotsTxt::Rules(rules) => rules.decide(url),
+            RobotsTxt::Unavailable(status) => RobotsDecision {
+                allowed: true,
+                rule: Some(format!("robots.txt unavailable ({status})")),
+                crawl_delay_s: None,
+            },
+            RobotsTxt::Unreachable(reason) => RobotsDecision {
+                allowed: false,
+                rule: Some(format!("robots.txt unreachable ({reason})")),
+                crawl_delay_s: None,
+            },
+        }
+    }
+
+    pub(crate) fn crawl_delay(&self) -> Option<Duration> {
+        match self {
+            RobotsTxt::Rules(rules) => rules
+                .crawl_delay
+                .and_then(|d| Duration::try_from_secs_f64(d).ok()),
+            _ => None,
+        }
+    }
+}
+
+impl Rules {
+    /// Parse robots.txt, keeping the groups for `agent` or, if there are
+    /// none, the `*` groups.
+    pub(crate) fn parse(text: &str, agent: &str) -> Self {
+        let mut ours = Rules::default();
+        let mut wildcard = Rules::default();
+        let mut found_ours = false;
+        // Which collections the current group feeds
+        let mut group = (false, false);
+        let mut in_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        group = (false, false);
+                        in_agents = true;
+                    }
+                    let token = product_token(value);
+                    if !token.is_empty() && token.eq_ignore_ascii_case(agent) {
+                        group.0 = true;
+                        found_ours = true;
+                    } else if token == "*" {
+                        group.1 = true;
+                    }
+                }
+                directive @ ("allow" | "disallow" | "crawl-delay") => {
+                    in_agents = false;
+                    for (feeds, rules) in [(group.0, &mut ours), (group.1, &mut wildcard)] {
+                        if !feeds {
+                            continue;
+                        }
+                        if directive == "crawl-delay" {
+                            if let Ok(delay) = value.parse::<f64>()
+                                && delay.is_finite()
+                                && delay >= 0.0
+                            {
+                                rules.crawl_delay = Some(delay);
+                            }
+                        } else if !value.is_empty() {
+                            rules.rules.push(Rule {
+                                allow: directive == "allow",
+                                pattern: value.to_string(),
+                            });
+                        }
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+        if found_ours { ours } else { wildcard }
+    }
+
+    fn decide(&self, url: &Url) -> RobotsDecision {
+        let mut target = url.path().to_string();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        // The most specific (longest) match wins; allow wins ties
+        let best = self
+            .rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &target))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow));
+        let allowed = target == "/robots.txt" || best.is_none_or(|rule| rule.allow);
+        RobotsDecision {
+            allowed,
+            rule: best.map(|rule| {
+                let kind = if rule.allow { "Allow" } else { "Disallow" };
+                format!("{kind}: {}", rule.pattern)
+            }),
+            crawl_delay_s: self.crawl_delay,
+        }
+    }
+}
+
+/// Match a robots.txt path pattern: a prefix match where `*` matches any
+/// sequence and a trailing `$` anchors the end.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern.as_bytes(), true),
+        None => (pattern.as_bytes(), false),
+    };
+    let path = path.as_bytes();
+    let (mut p, mut s) = (0, 0);
+    // Position after the last `*` seen, and where in `path` it resumed
+    let mut backtrack: Option<(usize, usize)> = None;
+    loop {
+        if p == pattern.len() && (!anchored || s == path.len()) {
+            return true;
+        }
+        if p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+            backtrack = Some((p, s));
+            continue;
+        }
+        if p < pattern.len() && s < path.len() && pattern[p] == path[s] {
+            p += 1;
+            s += 1;
+            continue;
+        }
+        match backtrack {
+            Some((bp, bs)) if bs < path.len() => {
+                backtrack = Some((bp, bs + 1));
+                p = bp;
+                s = bs + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Fetched robots.txt files, per origin.
+#[derive(Default)]
+pub(crate) struct RobotsCache {
+    entries: Mutex<HashMap<String, (Instant, Arc<RobotsTxt>)>>,
+}
+
+impl RobotsCache {
+    pub(crate) fn get(&self, origin: &str) -> Option<Arc<RobotsTxt>> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(origin)
+            .filter(|(fetched, robots)| {
+                // Retry an unreachable robots.txt sooner than a fetched one
+                let ttl = match robots.as_ref() {
+                    RobotsTxt::Unreachable(_) => Duration::from_secs(60),
+                    _ => ROBOTS_TTL,
+                };
+                fetched.elapsed() < ttl
+            })
+            .map(|(_, robots)| robots.clone())
+    }
+
+    pub(crate) fn insert(&self, origin: String, robots: RobotsTxt) -> Arc<RobotsTxt> {
+        let robots = Arc::new(robots);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(origin, (Instant::now(), robots.clone()));
+        robots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "\
+User-agent: googlebot
+Disallow: /
+
+User-agent: *
+Disallow: /private
+Allow: /private/public-*.html$
+Crawl-delay: 2
+
+# gurl gets its own group
+User-Agent: Gurl/1.0
+User-agent: otherbot
+Disallow: /search?
+Disallow: /tmp/
+Allow: /tmp/keep
+Crawl-delay: 0.5
+";
+
+    fn decide(rules: &Rules, url: &str) -> RobotsDecision {
+        rules.decide(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_agent_group_selection() {
+        let rules = Rules::parse(ROBOTS, "gurl");
+        assert_eq!(rules.crawl_delay, Some(0.5));
+        assert!(decide(&rules, "https://a.test/private").allowed);
+        assert!(!decide(&rules, "https://a.test/search?q=1").allowed);
+        assert!(decide(&rules, "https://a.test/search").allowed);
+
+        let other = Rules::parse(ROBOTS, "somebot");
+        assert_eq!(other.crawl_delay, Some(2.0));
+        assert!(!decide(&other, "https://a.test/private/x").allowed);
+    }
+
+    #[test]
+    fn test_product_token() {
+        assert_eq!(product_token("gurl/0.3.0"), "gurl");
+        assert_eq!(product_token("otherbot"), "otherbot");
+        assert_eq!(product_token("Mozilla/5.0 (compatible; x)"), "Mozilla");
+    }
+
+    #[test]
+    fn test_longest_match_wins() {
+        let rules = Rules::parse(ROBOTS, "gurl");
+        let keep = decide(&rules, "https://a.test/tmp/keep/me");
+        assert!(keep.allowed);
+        assert_eq!(keep.rule.as_deref(), Some("Allow: /tmp/keep"));
+        let tmp = decide(&rules, "https://a.test/tmp/other");
+        assert!(!tmp.allowed);
+        assert_eq!(tmp.rule.as_deref(), Some("Disallow: /tmp/"));
+        assert!(decide(&rules, "https://a.test/robots.txt").allowed);
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        assert!(pattern_matches("/private", "/private/x"));
+        assert!(pattern_matches("/*.pdf$", "/docs/a.pdf"));
+        assert!(!pattern_matches("/*.pdf$", "/docs/a.pdf?x"));
+        assert!(pattern_matches(
+            "/private/public-*.html$",
+            "/private/public-1.html"
+        ));
+        assert!(!pattern_matches(
+            "/private/public-*.html$",
+            "/private/public-1.htm"
+        ));
+        assert!(pattern_matches("/a*b*c", "/axxbyyc/z"));
+        assert!(!pattern_matches("/a*b*c", "/axxcyyb"));
+    }
+
+    #[test]
+    fn test_unreachable_disallows() {
+        let url = Url::parse("https://a.test/page").unwrap();
+        assert!(RobotsTxt::Unavailable(404).decide(&url).allowed);
+        assert!(!RobotsTxt::Unreachable("503".into()).decide(&url).allowed);
+    }
+}
@@ -0,0 +1,508 @@
+// AWS Signature Version 4 request signing.
+//
+// Credentials come from the standard environment variables, falling back
+// to the shared credentials file (`~/.aws/credentials`) for `AWS_PROFILE`.
+// Each attempt is signed just before it is sent, so retries carry a fresh
+// `X-Amz-Date`.
+//
+// `s3://bucket/key` URLs resolve to the bucket's virtual-hosted endpoint,
+// or to `<endpoint>/bucket/key` when `AWS_ENDPOINT_URL_S3` or
+// `AWS_ENDPOINT_URL` points at an S3-compatible store such as MinIO. They
+// are signed with the default credentials; without any they go out
+// unsigned, which is what public buckets expect.
+
+use super::{Auth, GurlRequest};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderName, HeaderValue};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use url::Url;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const DEFAULT_REGION: &str = "us-east-1";
+
+#[derive(Clone, PartialEq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwsCredentials {
+    pub fn new(access_key_id: impl Into<String>, secret_access_key: impl Into<String>) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    pub fn with_session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = env("AWS_ACCESS_KEY_ID")?;
+        let secret_access_key = env("AWS_SECRET_ACCESS_KEY")?;
+        Some(Self {
+            access_key_id,
+            secret_access_key,
+            session_token: env("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    /// A profile from the shared credentials file (`AWS_SHARED_CREDENTIALS_FILE`
+    /// or `~/.aws/credentials`).
+    pub fn from_profile(profile: &str) -> Option<Self> {
+        let path = env("AWS_SHARED_CREDENTIALS_FILE")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".aws").join("credentials")))?;
+        let ini = parse_ini(&std::fs::read_to_string(path).ok()?);
+        let section = ini.get(profile)?;
+        Some(Self {
+            access_key_id: section.get("aws_access_key_id")?.clone(),
+            secret_access_key: section.get("aws_secret_access_key")?.clone(),
+            session_token: section.get("aws_session_token").cloned(),
+        })
+    }
+
+    /// The environment, then the given profile (default: `AWS_PROFILE` or
+    /// `default`). An explicit profile takes precedence over the environment.
+    pub fn load(profile: Option<&str>) -> Result<Self, crate::Error> {
+        let from_env = profile.is_none().then(Self::from_env).flatten();
+        let profile = profile_name(profile);
+        from_env
+            .or_else(|| Self::from_profile(&profile))
+            .ok_or_else(|| {
+                crate::Error::Auth(format!(
+                    "no AWS credentials in the environment or profile '{profile}'"
+                ))
+            })
+    }
+}
+
+/// Signs requests for one region and service.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SigV4 {
+    pub credentials: AwsCredentials,
+    pub region: String,
+    pub service: String,
+}
+
+impl SigV4 {
+    pub fn new(
+        credentials: AwsCredentials,
+        region: impl Into<String>,
+        service: impl Into<String>,
+    ) -> Self {
+        Self {
+            credentials,
+            region: region.into(),
+            service: service.into(),
+        }
+    }
+
+    /// Credentials and region from the environment and AWS profile files.
+    pub fn from_env(
+        profile: Option<&str>,
+        service: impl Into<String>,
+    ) -> Result<Self, crate::Error> {
+        Ok(Self::new(
+            AwsCredentials::load(profile)?,
+            region(profile),
+            service,
+        ))
+    }
+
+    /// Add `Authorization`, `X-Amz-Date` and the other signed headers.
+    pub(crate) fn sign(&self, request: &mut reqwest::Request, now: DateTime<Utc>) {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        let payload_hash = match request.body().map(|body| body.as_bytes()) {
+            None => crate::hex(&Sha256::digest(b"")),
+            Some(Some(bytes)) => crate::hex(&Sha256::digest(bytes)),
+            // A streamed body cannot be hashed up front
+            Some(None) => UNSIGNED_PAYLOAD.to_string(),
+        };
+
+        let headers = request.headers_mut();
+        headers.insert("x-amz-date", HeaderValue::from_str(&amz_date).unwrap());
+        // S3 requires the payload hash as a header; other services take it
+        // from the canonical request alone
+        if self.service == "s3" {
+            headers.insert(
+                "x-amz-content-sha256",
+                HeaderValue::from_str(&payload_hash).unwrap(),
+            );
+        }
+        if let Some(token) = &self.credentials.session_token
+            && let Ok(value) = HeaderValue::from_str(token)
+        {
+            headers.insert("x-amz-security-token", value);
+        }
+
+        let url = request.url();
+        let mut host = url.host_str().unwrap_or_default().to_string();
+        if let Some(port) = url.port() {
+            host.push_str(&format!(":{port}"));
+        }
+        // Host plus every x-amz-* header and the content type, sorted by name
+        let mut signed: Vec<(String, String)> = request
+            .headers()
+            .iter()
+            .filter(|(name, _)| {
+                name.as_str().starts_with("x-amz-") || *name == reqwest::header::CONTENT_TYPE
+            })
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?.split_whitespace().collect::<Vec<_>>();
+                Some((name.to_string(), value.join(" ")))
+            })
+            .collect();
+        signed.push(("host".to_string(), host));
+        signed.sort();
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let signed_headers = signed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{payload_hash}",
+            request.method(),
+            self.canonical_path(url),
+            canonical_query(url),
+        );
+        let scope = format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let string_to_sign = format!(
+            "{ALGORITHM}\n{amz_date}\n{scope}\n{}",
+            crate::hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = [self.region.as_str(), self.service.as_str(), "aws4_request"]
+            .iter()
+            .fold(
+                hmac(
+                    format!("AWS4{}", self.credentials.secret_access_key).as_bytes(),
+                    date.as_bytes(),
+                ),
+                |key, part| hmac(&key, part.as_bytes()),
+            );
+        let signature = crate::hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "{ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.credentials.access_key_id
+        );
+        let mut value = HeaderValue::from_str(&authorization).unwrap();
+        value.set_sensitive(true);
+        request
+            .headers_mut()
+            .insert(HeaderName::from_static("authorization"), value);
+    }
+
+    /// Each path segment URI-encoded; twice for every service but S3.
+    fn canonical_path(&self, url: &Url) -> String {
+        let path = url.path();
+        if path.is_empty() {
+            return "/".to_string();
+        }
+        path.split('/')
+            .map(|segment| {
+                let decoded = percent_decode(segment);
+                let encoded = uri_encode(&decoded);
+                if self.service == "s3" {
+                    encoded
+                } else {
+                    uri_encode(encoded.as_bytes())
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| (uri_encode(name.as_bytes()), uri_encode(value.as_bytes())))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn uri_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn percent_decode(segment: &str) -> Vec<u8> {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = segment
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The `https://` URL an `s3://bucket/key` URL stands for: path-style under
+/// `endpoint` when one is given, the bucket's virtual host otherwise.
+pub(crate) fn s3_url(url: &Url, region: &str, endpoint: Option<&str>) -> Result<Url, crate::Error> {
+    let bucket = url
+        .host_str()
+        .filter(|bucket| !bucket.is_empty())
+        .ok_or_else(|| crate::Error::InvalidUrl(format!("{url}: missing bucket")))?;
+    let mut resolved = match endpoint {
+        Some(endpoint) => {
+            let mut base = Url::parse(endpoint)
+                .map_err(|e| crate::Error::InvalidUrl(format!("{endpoint}: {e}")))?;
+            let path = format!(
+                "{}/{bucket}{}",
+                base.path().trim_end_matches('/'),
+                url.path()
+            );
+            base.set_path(&path);
+            base
+        }
+        None => Url::parse(&format!(
+            "https://{bucket}.s3.{region}.amazonaws.com{}",
+            url.path()
+        ))
+        .map_err(|e| crate::Error::InvalidUrl(format!("{url}: {e}")))?,
+    };
+    resolved.set_query(url.query());
+    Ok(resolved)
+}
+
+/// Point an `s3://` request at its HTTP endpoint. A request without auth is
+/// signed with `credentials()` if that finds any, and sent unsigned if not.
+pub(crate) fn resolve_s3(
+    req: &mut GurlRequest,
+    credentials: impl FnOnce() -> Option<AwsCredentials>,
+    endpoint: Option<&str>,
+) -> Result<(), crate::Error> {
+    if req.auth.is_none()
+        && let Some(credentials) = credentials()
+    {
+        req.auth = Some(Auth::SigV4(SigV4::new(credentials, region(None), "s3")));
+    }
+    let region = match &req.auth {
+        Some(Auth::SigV4(signer)) => signer.region.clone(),
+        _ => region(None),
+    };
+    req.url = s3_url(&req.url, &region, endpoint)?;
+    Ok(())
+}
+
+/// `AWS_ENDPOINT_URL_S3`, falling back to `AWS_ENDPOINT_URL`.
+pub(crate) fn s3_endpoint() -> Option<String> {
+    env("AWS_ENDPOINT_URL_S3").or_else(|| env("AWS_ENDPOINT_URL"))
+}
+
+/// `AWS_REGION`, `AWS_DEFAULT_REGION`, then the profile's `region` in the
+/// config file (`AWS_CONFIG_FILE` or `~/.aws/config`).
+pub fn region(profile: Option<&str>) -> String {
+    env("AWS_REGION")
+        .or_else(|| env("AWS_DEFAULT_REGION"))
+        .or_else(|| {
+            let path = env("AWS_CONFIG_FILE")
+                .map(PathBuf::from)
+                .or_else(|| dirs::home_dir().map(|home| home.join(".aws").join("config")))?;
+            let ini = parse_ini(&std::fs::read_to_string(path).ok()?);
+            let profile = profile_name(profile);
+            // The config file names sections `[profile x]`, except `[default]`
+            let section = ini
+                .get(&format!("profile {profile}"))
+                .or_else(|| ini.get(&profile))?;
+            section.get("region").cloned()
+        })
+        .unwrap_or_else(|| DEFAULT_REGION.to_string())
+}
+
+fn profile_name(profile: Option<&str>) -> String {
+    profile
+        .map(str::to_string)
+        .or_else(|| env("AWS_PROFILE"))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Sections of an AWS-style INI file, keyed by section name.
+fn parse_ini(text: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.trim().to_string());
+            sections.entry(name.trim().to_string()).or_default();
+        } else if let (Some(section), Some((key, value))) = (&current, line.split_once('=')) {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+
+    fn example_signer(service: &str) -> SigV4 {
+        let credentials =
+            AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+        SigV4::new(credentials, "us-east-1", service)
+    }
+
+    fn example_date() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z")
+            .unwrap()
+            .to_utc()
+    }
+
+    #[test]
+    fn test_aws_suite_get_vanilla() {
+        // From the AWS SigV4 test suite
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let mut request = reqwest::Request::new(Method::GET, url);
+        example_signer("service").sign(&mut request, example_date());
+        assert_eq!(
+            request.headers()["authorization"],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+    }
+
+    #[test]
+    fn test_canonical_path_and_query() {
+        let url = Url::parse("https://example.com/a b/%C3%A9?b=2&a=x y&a=1").unwrap();
+        assert_eq!(canonical_query(&url), "a=1&a=x%20y&b=2");
+        assert_eq!(example_signer("s3").canonical_path(&url), "/a%20b/%C3%A9");
+        assert_eq!(
+            example_signer("execute-api").canonical_path(&url),
+            "/a%2520b/%25C3%25A9"
+        );
+    }
+
+    #[test]
+    fn test_s3_signing_hashes_payload_and_session_token() {
+        let mut signer = example_signer("s3");
+        signer.credentials = signer.credentials.with_session_token("tok");
+        let url = Url::parse("https://bucket.s3.amazonaws.com/key").unwrap();
+        let mut request = reqwest::Request::new(Method::PUT, url);
+        *request.body_mut() = Some(b"hello".to_vec().into());
+        signer.sign(&mut request, example_date());
+
+        let headers = request.headers();
+        assert_eq!(
+            headers["x-amz-content-sha256"],
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(headers["x-amz-security-token"], "tok");
+        let authorization = headers["authorization"].to_str().unwrap();
+        assert!(
+            authorization.contains(
+                "SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token"
+            )
+        );
+    }
+
+    #[test]
+    fn test_s3_url() {
+        let url = Url::parse("s3://photos/2024/cat.jpg?versionId=3").unwrap();
+        assert_eq!(
+            s3_url(&url, "eu-west-1", None).unwrap().as_str(),
+            "https://photos.s3.eu-west-1.amazonaws.com/2024/cat.jpg?versionId=3"
+        );
+        assert_eq!(
+            s3_url(&url, "us-east-1", Some("http://localhost:9000"))
+                .unwrap()
+                .as_str(),
+            "http://localhost:9000/photos/2024/cat.jpg?versionId=3"
+        );
+    }
+
+    #[test]
+    fn test_s3_without_credentials_is_unsigned() {
+        let endpoint = Some("http://localhost:9000");
+        let mut req = GurlRequest::get(Url::parse("s3://public/data.csv").unwrap());
+        resolve_s3(&mut req, || None, endpoint).unwrap();
+        assert_eq!(req.url.as_str(), "http://localhost:9000/public/data.csv");
+        assert!(req.auth.is_none());
+
+        let mut req = GurlRequest::get(Url::parse("s3://private/data.csv").unwrap());
+        resolve_s3(
+            &mut req,
+            || Some(AwsCredentials::new("AKID", "secret")),
+            endpoint,
+        )
+        .unwrap();
+        assert!(matches!(&req.auth, Some(Auth::SigV4(signer)) if signer.service == "s3"));
+    }
+
+    #[test]
+    fn test_parse_ini() {
+        let ini = parse_ini(
+            "[default]\naws_access_key_id = AKID\n# comment\n[profile dev]\nregion=eu-west-1\n",
+        );
+        assert_eq!(ini["default"]["aws_access_key_id"], "AKID");
+        assert_eq!(ini["profile dev"]["region"], "eu-west-1");
+    }
+}
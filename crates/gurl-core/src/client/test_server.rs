@@ -0,0 +1,172 @@
+// Local HTTP/1.1 stub for tests: answers each connection with a reply chosen
+// by the test and keeps every request it received.
+
+use reqwest::Url;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A request as it arrived on the wire.
+#[derive(Debug, Clone)]
+pub(crate) struct Received {
+    pub method: String,
+    /// Path and query, as in the request line
+    pub target: String,
+    /// Request line and headers, without the blank line
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl Received {
+    /// First value of header `name`, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    pub fn body_text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// What the server answers with. A Content-Length is added unless the reply
+/// is built with `without_length`, in which case the body ends with the
+/// connection.
+pub(crate) struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    content_length: bool,
+}
+
+impl Reply {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            content_length: true,
+        }
+    }
+
+    pub fn text(body: impl Into<Vec<u8>>) -> Self {
+        Self::new(200)
+            .header("content-type", "text/plain")
+            .body(body)
+    }
+
+    pub fn redirect(status: u16, location: &str) -> Self {
+        Self::new(status).header("location", location)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn without_length(mut self) -> Self {
+        self.content_length = false;
+        self
+    }
+
+    async fn write_to(&self, stream: &mut tokio::net::TcpStream) {
+        let mut head = format!("HTTP/1.1 {} X\r\n", self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if self.content_length {
+            head.push_str(&format!("content-length: {}\r\n", self.body.len()));
+        }
+        head.push_str("connection: close\r\n\r\n");
+        if stream.write_all(head.as_bytes()).await.is_err() {
+            return;
+        }
+        // In chunks, so a client that stops reading early is not an error here
+        for chunk in self.body.chunks(1000) {
+            if stream.write_all(chunk).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+pub(crate) struct TestServer {
+    base: Url,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl TestServer {
+    /// Serve on a random local port, answering each request with `reply`.
+    pub async fn start<F>(mut reply: F) -> Self
+    where
+        F: FnMut(&Received) -> Reply + Send + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let seen = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let Some(request) = read_request(&mut stream).await else {
+                    continue;
+                };
+                let response = reply(&request);
+                seen.lock().unwrap().push(request);
+                response.write_to(&mut stream).await;
+            }
+        });
+        Self {
+            base: base.parse().unwrap(),
+            received,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> Url {
+        self.base.join(path).unwrap()
+    }
+
+    /// Every request received so far, in order.
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<Received> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        let n = stream.read(&mut buf).await.ok().filter(|n| *n > 0)?;
+        data.extend_from_slice(&buf[..n]);
+    };
+    let head = String::from_utf8_lossy(&data[..end]).into_owned();
+    let mut request = Received {
+        method: String::new(),
+        target: String::new(),
+        head,
+        body: data.split_off(end + 4),
+    };
+    let mut line = request.head.split(' ');
+    request.method = line.next().unwrap_or_default().to_string();
+    request.target = line.next().unwrap_or_default().to_string();
+    let length: usize = request
+        .header("content-length")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+    while request.body.len() < length {
+        let n = stream.read(&mut buf).await.ok().filter(|n| *n > 0)?;
+        request.body.extend_from_slice(&buf[..n]);
+    }
+    Some(request)
+}
@@ -1,4 +1,238 @@
 // Timing utilities for HTTP requests.
-// Currently we only measure total_ms via Instant.
-// Granular timing (DNS, TLS, connect) requires hyper connection-level hooks,
-// which we'll add when needed. reqwest doesn't expose these directly.
+//
+// Each connection phase is observed through a hook installed on the client:
+//   - DNS: a resolver that wraps tokio's getaddrinfo lookup
+//   - TCP connect: a connector layer around reqwest's connector service
+//   - TLS: the rustls session store, which rustls consults while building
+//     the ClientHello, i.e. right after the TCP connection is up
+
+use crate::client::response::Timing;
+use crate::client::trace::{ConnTrace, record};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use rustls::NamedGroup;
+use rustls::client::Tls13ClientSessionValue;
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue};
+use rustls::pki_types::ServerName;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+impl ConnTrace {
+    /// Build the envelope timing for a request started at `start` whose
+    /// headers arrived at `first_byte` and whose body finished at `end`.
+    pub(crate) fn timing(&self, start: Instant, first_byte: Instant, end: Instant) -> Timing {
+        let e = self.events();
+        let ms = |from: Instant, to: Instant| to.saturating_duration_since(from).as_millis() as u64;
+
+        let dns_ms = e.dns_start.zip(e.dns_end).map(|(s, e)| ms(s, e));
+        // TCP connect starts after DNS (or immediately for IP literals) and
+        // ends when the TLS handshake begins (or the connection is handed over)
+        let tcp_start = e.dns_end.or(e.connect_start);
+        let tcp_end = e.tls_start.or(e.connect_end);
+        let connect_ms = e
+            .connect_end
+            .and(tcp_start.zip(tcp_end))
+            .map(|(s, e)| ms(s, e));
+        let tls_ms = e.tls_start.zip(e.connect_end).map(|(s, e)| ms(s, e));
+
+        Timing {
+            dns_ms,
+            connect_ms,
+            tls_ms,
+            first_byte_ms: Some(ms(start, first_byte)),
+            total_ms: ms(start, end),
+        }
+    }
+}
+
+/// DNS resolver that records lookup time.
+pub(crate) struct TimedResolver;
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            record(|e| e.dns_start = Some(Instant::now()));
+            let host = name.as_str().to_string();
+            let addrs = tokio::net::lookup_host((host.clone(), 0))
+                .await
+                .map_err(|source| DnsError { host, source })?;
+            record(|e| e.dns_end = Some(Instant::now()));
+            Ok(Box::new(addrs) as Addrs)
+        })
+    }
+}
+
+/// A failed lookup, told apart from other connection errors when reporting.
+#[derive(Debug)]
+pub(crate) struct DnsError {
+    pub(crate) host: String,
+    source: std::io::Error,
+}
+
+impl std::fmt::Display for DnsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+/// Connector layer that records when connection setup starts and completes.
+#[derive(Clone)]
+pub(crate) struct TimingLayer;
+
+impl<S> tower::Layer<S> for TimingLayer {
+    type Service = TimedConnector<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnector { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct TimedConnector<S> {
+    inner: S,
+}
+
+impl<S, R> tower::Service<R> for TimedConnector<S>
+where
+    S: tower::Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let connecting = self.inner.call(req);
+        Box::pin(async move {
+            record(|e| e.connect_start = Some(Instant::now()));
+            let conn = connecting.await;
+            if conn.is_ok() {
+                record(|e| e.connect_end = Some(Instant::now()));
+            }
+            conn
+        })
+    }
+}
+
+/// rustls session store that marks the start of each TLS handshake.
+///
+/// rustls asks the store for a key-exchange hint when it builds the
+/// ClientHello, so that lookup is the first observable step of the
+/// handshake. Storage itself is delegated to the default in-memory cache.
+#[derive(Debug)]
+pub(crate) struct TimedSessionStore {
+    inner: ClientSessionMemoryCache,
+}
+
+impl Default for TimedSessionStore {
+    fn default() -> Self {
+        Self {
+            inner: ClientSessionMemoryCache::new(256),
+        }
+    }
+}
+
+impl ClientSessionStore for TimedSessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group);
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        record(|e| e.tls_start = Some(Instant::now()));
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        self.inner.insert_tls13_ticket(server_name, value);
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        self.inner.take_tls13_ticket(server_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_phase_durations() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let trace = ConnTrace::default();
+        {
+            let mut e = trace.events();
+            e.connect_start = Some(at(0));
+            e.dns_start = Some(at(1));
+            e.dns_end = Some(at(11));
+            e.tls_start = Some(at(31));
+            e.connect_end = Some(at(61));
+        }
+
+        let timing = trace.timing(t0, at(100), at(150));
+        assert_eq!(timing.dns_ms, Some(10));
+        assert_eq!(timing.connect_ms, Some(20));
+        assert_eq!(timing.tls_ms, Some(30));
+        assert_eq!(timing.first_byte_ms, Some(100));
+        assert_eq!(timing.total_ms, 150);
+    }
+
+    #[test]
+    fn test_reused_connection_has_no_phases() {
+        let t0 = Instant::now();
+        let timing = ConnTrace::default().timing(t0, t0, t0);
+        assert_eq!(timing.dns_ms, None);
+        assert_eq!(timing.connect_ms, None);
+        assert_eq!(timing.tls_ms, None);
+        assert_eq!(timing.first_byte_ms, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_real_request_is_timed() {
+        use crate::client::GurlRequest;
+        use crate::client::test_server::{Reply, TestServer};
+
+        let server = TestServer::start(|_| Reply::text("ok")).await;
+        let mut url = server.url("/");
+        url.set_host(Some("localhost")).unwrap();
+        let client = crate::GurlClient::new().unwrap();
+        let timing = client
+            .execute(GurlRequest::get(url))
+            .await
+            .unwrap()
+            .response
+            .timing;
+        assert!(timing.dns_ms.is_some());
+        assert!(timing.connect_ms.is_some());
+        assert_eq!(timing.tls_ms, None);
+        let first_byte = timing.first_byte_ms.unwrap();
+        assert!(first_byte <= timing.total_ms);
+    }
+}
@@ -0,0 +1,704 @@
+// rustls configuration shared by all gurl HTTP clients.
+//
+// We build the rustls config ourselves (instead of letting reqwest do it) so
+// gurl can observe the handshake: the certificate verifier sees the peer's
+// chain, and every cipher suite's AEAD is wrapped so the suite that ends up
+// keying the connection reports itself. Both record into the request's
+// connection trace (see trace.rs).
+//
+// The same verifier enforces the trust settings from `TlsConfig`: a custom
+// CA bundle replaces the built-in roots, public key pins are checked against
+// the server's own certificate after the chain verifies, and `insecure`
+// skips chain verification altogether (pins still apply).
+//
+// Client certificates are loaded from PEM or PKCS#12.
+
+use crate::client::response::{CertificateInfo, TlsInfo};
+use crate::client::timing::TimedSessionStore;
+use crate::client::trace::{ConnTrace, record};
+use crate::hex;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::{Resumption, WebPkiServerVerifier};
+use rustls::crypto::cipher::{
+    AeadKey, Iv, KeyBlockShape, MessageDecrypter, MessageEncrypter, Tls12AeadAlgorithm,
+    Tls13AeadAlgorithm, UnsupportedOperationError,
+};
+use rustls::crypto::{CipherSuiteCommon, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{
+    CipherSuite, ClientConfig, ConnectionTrafficSecrets, DigitallySignedStruct, RootCertStore,
+    SignatureScheme, SupportedCipherSuite, Tls12CipherSuite, Tls13CipherSuite,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+/// TLS settings for one host. Unset fields fall back to the global defaults.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostTls {
+    /// PEM bundle of CA certificates to trust instead of the built-in roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cacert: Option<PathBuf>,
+    /// Client certificate: a PEM chain, or a PKCS#12 archive (.p12, .pfx)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<PathBuf>,
+    /// Private key for a PEM `cert`, if it is not in the same file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+    /// Password of a PKCS#12 `cert`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_password: Option<String>,
+    /// Accept any server certificate. Responses carry a warning.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insecure: Option<bool>,
+    /// Accepted public keys as `sha256//<base64>`, the SHA-256 of a
+    /// certificate's SubjectPublicKeyInfo. One must match the server's own
+    /// (end-entity) certificate, as with curl's `--pinnedpubkey`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pins: Option<Vec<String>>,
+}
+
+impl HostTls {
+    /// These settings, with unset fields taken from `fallback`.
+    pub fn or(&self, fallback: &HostTls) -> HostTls {
+        HostTls {
+            cacert: self.cacert.clone().or_else(|| fallback.cacert.clone()),
+            cert: self.cert.clone().or_else(|| fallback.cert.clone()),
+            key: self.key.clone().or_else(|| fallback.key.clone()),
+            cert_password: self
+                .cert_password
+                .clone()
+                .or_else(|| fallback.cert_password.clone()),
+            insecure: self.insecure.or(fallback.insecure),
+            pins: self.pins.clone().or_else(|| fallback.pins.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for HostTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostTls")
+            .field("cacert", &self.cacert)
+            .field("cert", &self.cert)
+            .field("key", &self.key)
+            .field(
+                "cert_password",
+                &self.cert_password.as_ref().map(|_| "<redacted>"),
+            )
+            .field("insecure", &self.insecure)
+            .field("pins", &self.pins)
+            .finish()
+    }
+}
+
+/// Global TLS settings plus per-domain overrides.
+///
+/// A domain entry also applies to its subdomains, so `corp.internal` covers
+/// `api.corp.internal` unless that host has an entry of its own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    #[serde(flatten)]
+    pub default: HostTls,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hosts: HashMap<String, HostTls>,
+}
+
+impl TlsConfig {
+    pub fn with_default(mut self, tls: HostTls) -> Self {
+        self.default = tls;
+        self
+    }
+
+    pub fn with_host(mut self, domain: impl Into<String>, tls: HostTls) -> Self {
+        self.hosts.insert(domain.into().to_ascii_lowercase(), tls);
+        self
+    }
+
+    /// Settings that win over the default and every host entry, such as
+    /// command-line flags.
+    pub fn with_override(mut self, tls: &HostTls) -> Self {
+        self.default = tls.or(&self.default);
+        for host in self.hosts.values_mut() {
+            *host = tls.or(host);
+        }
+        self
+    }
+
+    /// Effective settings for `host`, from the most specific matching domain.
+    pub fn for_host(&self, host: &str) -> HostTls {
+        match self.domain_for(host) {
+            Some(domain) => self.hosts[domain].or(&self.default),
+            None => self.default.clone(),
+        }
+    }
+
+    /// The most specific domain entry that applies to `host`.
+    pub(crate) fn domain_for(&self, host: &str) -> Option<&str> {
+        let host = host.to_ascii_lowercase();
+        let mut domain = host.as_str();
+        loop {
+            if let Some((key, _)) = self.hosts.get_key_value(domain) {
+                return Some(key);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
+}
+
+pub(crate) fn client_config(tls: &HostTls) -> Result<ClientConfig, crate::Error> {
+    let provider = Arc::new(traced_provider());
+    let roots = match &tls.cacert {
+        Some(path) => load_roots(path)?,
+        None => RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        },
+    };
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(|e| crate::Error::Tls(e.to_string()))?;
+    let verifier = TracedVerifier {
+        inner,
+        insecure: tls.insecure.unwrap_or(false),
+        pins: tls
+            .pins
+            .iter()
+            .flatten()
+            .map(|pin| parse_pin(pin))
+            .collect::<Result<_, _>>()?,
+    };
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default TLS versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let mut config = match &tls.cert {
+        Some(cert) => {
+            let (chain, key) =
+                load_identity(cert, tls.key.as_deref(), tls.cert_password.as_deref())?;
+            builder
+                .with_client_auth_cert(chain, key)
+                .map_err(|e| crate::Error::Tls(format!("{}: {e}", cert.display())))?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    // reqwest is built without HTTP/2, so only offer HTTP/1.1
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    config.resumption = Resumption::store(Arc::new(TimedSessionStore::default()));
+    Ok(config)
+}
+
+/// Trust anchors from a PEM bundle.
+fn load_roots(path: &Path) -> Result<RootCertStore, crate::Error> {
+    let file_error = |e: &dyn fmt::Display| crate::Error::Tls(format!("{}: {e}", path.display()));
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(path).map_err(|e| file_error(&e))? {
+        let cert = cert.map_err(|e| file_error(&e))?;
+        roots.add(cert).map_err(|e| file_error(&e))?;
+    }
+    if roots.is_empty() {
+        return Err(file_error(&"no certificates found"));
+    }
+    Ok(roots)
+}
+
+/// Client certificate chain and private key. A PEM key may sit in the
+/// certificate file itself.
+fn load_identity(
+    cert: &Path,
+    key: Option<&Path>,
+    password: Option<&str>,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), crate::Error> {
+    let file_error =
+        |path: &Path, e: &dyn fmt::Display| crate::Error::Tls(format!("{}: {e}", path.display()));
+    let pkcs12 = cert
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("p12") || ext.eq_ignore_ascii_case("pfx"));
+    if pkcs12 {
+        let data = std::fs::read(cert).map_err(|e| file_error(cert, &e))?;
+        let store = p12_keystore::KeyStore::from_pkcs12(
+            &data,
+            password.unwrap_or_default(),
+            p12_keystore::Pkcs12ImportPolicy::Strict,
+        )
+        .map_err(|e| file_error(cert, &e))?;
+        let (_, chain) = store
+            .private_key_chain()
+            .ok_or_else(|| file_error(cert, &"no private key with a certificate"))?;
+        let certs = chain
+            .certs()
+            .iter()
+            .map(|c| CertificateDer::from(c.as_der().to_vec()))
+            .collect();
+        let key = PrivatePkcs8KeyDer::from(chain.key().as_der().to_vec());
+        return Ok((certs, key.into()));
+    }
+
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| file_error(cert, &e))?;
+    if certs.is_empty() {
+        return Err(file_error(cert, &"no certificates found"));
+    }
+    let key_path = key.unwrap_or(cert);
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| file_error(key_path, &e))?;
+    Ok((certs, key))
+}
+
+/// The digest in a `sha256//<base64>` pin.
+fn parse_pin(pin: &str) -> Result<[u8; 32], crate::Error> {
+    pin.trim()
+        .strip_prefix("sha256//")
+        .and_then(|digest| BASE64_STANDARD.decode(digest).ok())
+        .and_then(|digest| digest.try_into().ok())
+        .ok_or_else(|| crate::Error::Tls(format!("invalid pin '{pin}', expected sha256//<base64>")))
+}
+
+/// SHA-256 of a certificate's SubjectPublicKeyInfo.
+fn spki_sha256(der: &CertificateDer<'_>) -> Option<[u8; 32]> {
+    use x509_parser::prelude::*;
+
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    Some(Sha256::digest(cert.public_key().raw).into())
+}
+
+impl ConnTrace {
+    /// TLS details of the handshake performed for this request, if any.
+    pub(crate) fn tls_info(&self, version: reqwest::Version) -> Option<TlsInfo> {
+        let e = self.events();
+        let tls_version = e.tls_version?;
+        let certificates: Vec<CertificateInfo> =
+            e.peer_chain.iter().filter_map(certificate_info).collect();
+        Some(TlsInfo {
+            version: tls_version.to_string(),
+            cipher: e.cipher.clone().unwrap_or_default(),
+            alpn: alpn_protocol(version).map(str::to_string),
+            cert_issuer: certificates.first().map(|c| c.issuer.clone()),
+            certificates,
+        })
+    }
+}
+
+fn alpn_protocol(version: reqwest::Version) -> Option<&'static str> {
+    match version {
+        reqwest::Version::HTTP_10 => Some("http/1.0"),
+        reqwest::Version::HTTP_11 => Some("http/1.1"),
+        reqwest::Version::HTTP_2 => Some("h2"),
+        reqwest::Version::HTTP_3 => Some("h3"),
+        _ => None,
+    }
+}
+
+fn certificate_info(der: &CertificateDer<'_>) -> Option<CertificateInfo> {
+    use x509_parser::prelude::*;
+
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let sans = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|ext| {
+            ext.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    GeneralName::IPAddress(ip) => ip_address(ip),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let not_before = chrono::DateTime::from_timestamp(cert.validity().not_before.timestamp(), 0)?;
+    let not_after = chrono::DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)?;
+
+    Some(CertificateInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        sans,
+        serial: cert.raw_serial_as_string(),
+        not_before,
+        not_after,
+        expires_in_days: (not_after - chrono::Utc::now()).num_days(),
+        sha256: hex(&Sha256::digest(der.as_ref())),
+    })
+}
+
+fn ip_address(octets: &[u8]) -> Option<String> {
+    match octets.len() {
+        4 => Some(std::net::Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?).to_string()),
+        16 => Some(std::net::Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?).to_string()),
+        _ => None,
+    }
+}
+
+/// Certificate verifier that records the peer chain before delegating to
+/// webpki, then checks public key pins.
+#[derive(Debug)]
+struct TracedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    /// Skip chain and hostname verification
+    insecure: bool,
+    /// SPKI digests, one of which the end-entity key must match; empty means no pinning
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for TracedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        record(|e| {
+            e.peer_chain = std::iter::once(end_entity)
+                .chain(intermediates)
+                .map(|c| c.clone().into_owned())
+                .collect();
+        });
+        if !self.insecure {
+            self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )?;
+        }
+        // Only the end-entity key is proven by the handshake: a server can
+        // append any certificate it likes, so a pin matching one of the
+        // others means nothing when the chain is not verified
+        let pinned = self.pins.is_empty()
+            || spki_sha256(end_entity).is_some_and(|digest| self.pins.contains(&digest));
+        if !pinned {
+            return Err(rustls::Error::General(
+                "the server's public key does not match a pinned key".to_string(),
+            ));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// The ring provider with every cipher suite wrapped by a traced AEAD.
+fn traced_provider() -> CryptoProvider {
+    // Cipher suites must be 'static, so the wrapped set is built (and leaked) once
+    static SUITES: LazyLock<Vec<SupportedCipherSuite>> = LazyLock::new(|| {
+        rustls::crypto::ring::default_provider()
+            .cipher_suites
+            .into_iter()
+            .map(traced_suite)
+            .collect()
+    });
+
+    CryptoProvider {
+        cipher_suites: SUITES.clone(),
+        ..rustls::crypto::ring::default_provider()
+    }
+}
+
+fn traced_suite(suite: SupportedCipherSuite) -> SupportedCipherSuite {
+    match suite {
+        SupportedCipherSuite::Tls13(s) => {
+            let aead_alg = Box::leak(Box::new(TracedAead {
+                inner: s.aead_alg,
+                suite: s.common.suite,
+            }));
+            SupportedCipherSuite::Tls13(Box::leak(Box::new(Tls13CipherSuite {
+                common: copy_common(&s.common),
+                hkdf_provider: s.hkdf_provider,
+                aead_alg,
+                quic: s.quic,
+            })))
+        }
+        SupportedCipherSuite::Tls12(s) => {
+            let aead_alg = Box::leak(Box::new(TracedAead {
+                inner: s.aead_alg,
+                suite: s.common.suite,
+            }));
+            SupportedCipherSuite::Tls12(Box::leak(Box::new(Tls12CipherSuite {
+                common: copy_common(&s.common),
+                prf_provider: s.prf_provider,
+                kx: s.kx,
+                sign: s.sign,
+                aead_alg,
+            })))
+        }
+    }
+}
+
+fn copy_common(common: &CipherSuiteCommon) -> CipherSuiteCommon {
+    CipherSuiteCommon {
+        suite: common.suite,
+        hash_provider: common.hash_provider,
+        confidentiality_limit: common.confidentiality_limit,
+    }
+}
+
+/// AEAD wrapper that reports its cipher suite when a connection is keyed with it.
+struct TracedAead<A: ?Sized + 'static> {
+    inner: &'static A,
+    suite: CipherSuite,
+}
+
+impl<A: ?Sized> TracedAead<A> {
+    fn mark(&self, version: &'static str) {
+        let suite = self.suite;
+        record(|e| {
+            e.tls_version = Some(version);
+            e.cipher = Some(
+                suite
+                    .as_str()
+                    .map_or_else(|| format!("{suite:?}"), str::to_string),
+            );
+        });
+    }
+}
+
+impl Tls13AeadAlgorithm for TracedAead<dyn Tls13AeadAlgorithm> {
+    fn encrypter(&self, key: AeadKey, iv: Iv) -> Box<dyn MessageEncrypter> {
+        self.mark("TLSv1.3");
+        self.inner.encrypter(key, iv)
+    }
+
+    fn decrypter(&self, key: AeadKey, iv: Iv) -> Box<dyn MessageDecrypter> {
+        self.inner.decrypter(key, iv)
+    }
+
+    fn key_len(&self) -> usize {
+        self.inner.key_len()
+    }
+
+    fn extract_keys(
+        &self,
+        key: AeadKey,
+        iv: Iv,
+    ) -> Result<ConnectionTrafficSecrets, UnsupportedOperationError> {
+        self.inner.extract_keys(key, iv)
+    }
+
+    fn fips(&self) -> bool {
+        self.inner.fips()
+    }
+}
+
+impl Tls12AeadAlgorithm for TracedAead<dyn Tls12AeadAlgorithm> {
+    fn encrypter(&self, key: AeadKey, iv: &[u8], extra: &[u8]) -> Box<dyn MessageEncrypter> {
+        self.mark("TLSv1.2");
+        self.inner.encrypter(key, iv, extra)
+    }
+
+    fn decrypter(&self, key: AeadKey, iv: &[u8]) -> Box<dyn MessageDecrypter> {
+        self.inner.decrypter(key, iv)
+    }
+
+    fn key_block_shape(&self) -> KeyBlockShape {
+        self.inner.key_block_shape()
+    }
+
+    fn extract_keys(
+        &self,
+        key: AeadKey,
+        iv: &[u8],
+        explicit: &[u8],
+    ) -> Result<ConnectionTrafficSecrets, UnsupportedOperationError> {
+        self.inner.extract_keys(key, iv, explicit)
+    }
+
+    fn fips(&self) -> bool {
+        self.inner.fips()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SELF_SIGNED: &str = "-----BEGIN CERTIFICATE-----
+MIIBqTCCAU6gAwIBAgIUIAGcTxGulVfMfnTEMfi94DJfbsEwCgYIKoZIzj0EAwIw
+FDESMBAGA1UEAwwJZ3VybC50ZXN0MCAXDTI2MTAxODAwMTIzOFoYDzIxMjYwOTI0
+MDAxMjM4WjAUMRIwEAYDVQQDDAlndXJsLnRlc3QwWTATBgcqhkjOPQIBBggqhkjO
+PQMBBwNCAAQjgEPYi94orJ9xCLm8Dzf2LEFWrz6+WQrOVmM/FLEadh/PYqde4BkR
+YKYV2a+ynEp+o5LaUEFBi19VE9T/P0bao3wwejAdBgNVHQ4EFgQUd0Ribpo42e6G
+ss3MlelVy1nCp8owHwYDVR0jBBgwFoAUd0Ribpo42e6Gss3MlelVy1nCp8owDwYD
+VR0TAQH/BAUwAwEB/zAnBgNVHREEIDAegglndXJsLnRlc3SCCyouZ3VybC50ZXN0
+hwQKAAABMAoGCCqGSM49BAMCA0kAMEYCIQDoijkxJUrXX8bn64zBGiWrVqquaNhS
+YcByCC3USCss0QIhAODc8LT4b4tc1l0MyuBmKziKlBRfJ0t50Co4hjmrXKAw
+-----END CERTIFICATE-----
+";
+
+    const OTHER: &str = "-----BEGIN CERTIFICATE-----
+MIIBgTCCASegAwIBAgIUZnV08Trc/vD2bwKhtaIVr2wg2ewwCgYIKoZIzj0EAwIw
+FTETMBEGA1UEAwwKb3RoZXIudGVzdDAgFw0yNjEwMTgwMjIyNTVaGA8yMTI2MDky
+NDAyMjI1NVowFTETMBEGA1UEAwwKb3RoZXIudGVzdDBZMBMGByqGSM49AgEGCCqG
+SM49AwEHA0IABMX+I20V6swSG/Tj4nMdRr/E45NzgDHPe2ssTVBmZIze+2upaakU
+dhPsLUnGCPGv4XDeun8/ISp5he8HG8Lt1/ejUzBRMB0GA1UdDgQWBBTOUYgi+cPa
+kufnjUQzue13b3nHczAfBgNVHSMEGDAWgBTOUYgi+cPakufnjUQzue13b3nHczAP
+BgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIA7rd3+Ou5cvQsdtRv6N
+gvKvThGBObuN2f654D7XFRYIAiEAmdWcKGdjHwzIRwDzD4+IzRfNMB9TOFVpaEEg
+BUwDTdw=
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_certificate_info() {
+        let der = CertificateDer::from_pem_slice(SELF_SIGNED.as_bytes()).unwrap();
+        let info = certificate_info(&der).unwrap();
+        assert_eq!(info.subject, "CN=gurl.test");
+        assert_eq!(info.issuer, "CN=gurl.test");
+        assert_eq!(info.sans, ["gurl.test", "*.gurl.test", "10.0.0.1"]);
+        assert_eq!(info.not_after.to_rfc3339(), "2126-09-24T00:12:38+00:00");
+        assert!(info.expires_in_days > 30000);
+        assert_eq!(info.sha256.len(), 64);
+    }
+
+    #[test]
+    fn test_tls_info_from_trace() {
+        let trace = ConnTrace::default();
+        assert!(trace.tls_info(reqwest::Version::HTTP_11).is_none());
+        {
+            let mut e = trace.events();
+            e.tls_version = Some("TLSv1.3");
+            e.cipher = Some("TLS13_AES_128_GCM_SHA256".to_string());
+            e.peer_chain = vec![CertificateDer::from_pem_slice(SELF_SIGNED.as_bytes()).unwrap()];
+        }
+        let info = trace.tls_info(reqwest::Version::HTTP_11).unwrap();
+        assert_eq!(info.version, "TLSv1.3");
+        assert_eq!(info.alpn.as_deref(), Some("http/1.1"));
+        assert_eq!(info.cert_issuer.as_deref(), Some("CN=gurl.test"));
+        assert_eq!(info.certificates.len(), 1);
+    }
+
+    #[test]
+    fn test_pins() {
+        let der = CertificateDer::from_pem_slice(SELF_SIGNED.as_bytes()).unwrap();
+        let pin = parse_pin("sha256//8S0FdFMkNnQ3YZLdhXVRlyaRYRROjyNkPn3KnxzbCT0=").unwrap();
+        assert_eq!(spki_sha256(&der), Some(pin));
+        assert!(parse_pin("8S0FdFMkNnQ3YZLdhXVRlyaRYRROjyNkPn3KnxzbCT0=").is_err());
+        assert!(parse_pin("sha256//AAAA").is_err());
+    }
+
+    #[test]
+    fn test_pins_match_the_end_entity_only() {
+        let pinned = CertificateDer::from_pem_slice(SELF_SIGNED.as_bytes()).unwrap();
+        let other = CertificateDer::from_pem_slice(OTHER.as_bytes()).unwrap();
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let verifier = TracedVerifier {
+            inner: WebPkiServerVerifier::builder_with_provider(
+                Arc::new(roots),
+                Arc::new(traced_provider()),
+            )
+            .build()
+            .unwrap(),
+            // Self-signed test certificates: check the pins alone
+            insecure: true,
+            pins: vec![parse_pin("sha256//8S0FdFMkNnQ3YZLdhXVRlyaRYRROjyNkPn3KnxzbCT0=").unwrap()],
+        };
+        let name = ServerName::try_from("gurl.test").unwrap();
+        let verify = |end_entity: &CertificateDer<'_>, intermediates: &[CertificateDer<'_>]| {
+            verifier.verify_server_cert(end_entity, intermediates, &name, &[], UnixTime::now())
+        };
+        assert!(verify(&pinned, &[]).is_ok());
+        // A server holding another key cannot pass by appending the pinned certificate
+        assert!(verify(&other, std::slice::from_ref(&pinned)).is_err());
+        assert!(verify(&other, &[]).is_err());
+    }
+
+    #[test]
+    fn test_host_settings_merge() {
+        let tls = TlsConfig::default()
+            .with_default(HostTls {
+                cacert: Some("/ca.pem".into()),
+                ..Default::default()
+            })
+            .with_host(
+                "Corp.Internal",
+                HostTls {
+                    cert: Some("/me.p12".into()),
+                    ..Default::default()
+                },
+            );
+        let host = tls.for_host("api.corp.internal");
+        assert_eq!(host.cacert, Some("/ca.pem".into()));
+        assert_eq!(host.cert, Some("/me.p12".into()));
+        assert_eq!(tls.domain_for("corp.internal"), Some("corp.internal"));
+        assert_eq!(tls.domain_for("example.com"), None);
+
+        let flags = HostTls {
+            insecure: Some(true),
+            ..Default::default()
+        };
+        let tls = tls.with_override(&flags);
+        assert_eq!(tls.for_host("api.corp.internal").insecure, Some(true));
+        assert_eq!(tls.for_host("example.com").insecure, Some(true));
+    }
+
+    #[test]
+    fn test_client_config_from_files() {
+        let dir = std::env::temp_dir().join(format!("gurl-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cacert = dir.join("ca.pem");
+        std::fs::write(&cacert, SELF_SIGNED).unwrap();
+        let tls = HostTls {
+            cacert: Some(cacert.clone()),
+            ..Default::default()
+        };
+        assert!(client_config(&tls).is_ok());
+
+        let missing = HostTls {
+            cacert: Some(dir.join("missing.pem")),
+            ..Default::default()
+        };
+        assert!(matches!(client_config(&missing), Err(crate::Error::Tls(_))));
+        // A certificate without its key is not a usable identity
+        let no_key = HostTls {
+            cert: Some(cacert),
+            ..Default::default()
+        };
+        assert!(matches!(client_config(&no_key), Err(crate::Error::Tls(_))));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_every_suite_is_traced() {
+        let provider = traced_provider();
+        let default = rustls::crypto::ring::default_provider();
+        let names =
+            |suites: &[SupportedCipherSuite]| suites.iter().map(|s| s.suite()).collect::<Vec<_>>();
+        assert_eq!(
+            names(&provider.cipher_suites),
+            names(&default.cipher_suites)
+        );
+    }
+}
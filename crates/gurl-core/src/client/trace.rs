@@ -0,0 +1,71 @@
+// Connection-level events for a single request attempt.
+//
+// reqwest doesn't expose what happens while a connection is set up, so gurl
+// observes it through the hooks reqwest does let us install (a DNS resolver,
+// a connector layer and rustls callbacks, see timing.rs and tls.rs).
+//
+// Hooks record into the `ConnTrace` scoped (task-local) to the request being
+// sent. Connection setup runs inside the request's own task, so events are
+// attributed to the right request even when requests run concurrently. A
+// request served from a pooled connection records no connection events.
+
+use rustls::pki_types::CertificateDer;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+tokio::task_local! {
+    static TRACE: ConnTrace;
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct ConnTrace(Arc<Mutex<ConnEvents>>);
+
+#[derive(Default)]
+pub(crate) struct ConnEvents {
+    pub connect_start: Option<Instant>,
+    pub dns_start: Option<Instant>,
+    pub dns_end: Option<Instant>,
+    pub tls_start: Option<Instant>,
+    pub connect_end: Option<Instant>,
+    pub tls_version: Option<&'static str>,
+    pub cipher: Option<String>,
+    /// Certificates presented by the server, leaf first
+    pub peer_chain: Vec<CertificateDer<'static>>,
+}
+
+impl ConnTrace {
+    /// Run `fut` with this trace receiving connection events.
+    pub(crate) async fn scope<F: Future>(&self, fut: F) -> F::Output {
+        TRACE.scope(self.clone(), fut).await
+    }
+
+    pub(crate) fn events(&self) -> MutexGuard<'_, ConnEvents> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Record an event into the trace of the request currently being sent.
+pub(crate) fn record(f: impl FnOnce(&mut ConnEvents)) {
+    // Outside a traced request (e.g. a connection finishing in the background
+    // after the request that started it moved on) there is nothing to record
+    let _ = TRACE.try_with(|trace| f(&mut trace.events()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_events_recorded_in_scope_only() {
+        let trace = ConnTrace::default();
+        trace
+            .scope(async { record(|e| e.connect_start = Some(Instant::now())) })
+            .await;
+        record(|e| e.connect_end = Some(Instant::now()));
+
+        let events = trace.events();
+        assert!(events.connect_start.is_some());
+        assert!(events.connect_end.is_none());
+    }
+}
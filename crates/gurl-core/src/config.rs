@@ -0,0 +1,136 @@
+// User configuration, read from `~/.config/gurl/config.yaml`.
+//
+// Every section is optional; a missing file is the same as an empty one.
+//
+// ```yaml
+// limits:
+//   requests_per_second: 5
+//   max_in_flight: 4
+//   hosts:
+//     api.github.com:
+//       requests_per_second: 1
+//       burst: 5
+// robots: true
+// proxy:
+//   https: http://proxy.corp:3128
+//   no_proxy: localhost,.internal.corp
+//   hosts:
+//     github.com: socks5h://127.0.0.1:1080
+// oauth:
+//   internal:
+//     token_url: https://auth.corp/oauth/token
+//     client_id: gurl
+//     client_secret: ...
+//     scope: api.read
+// tls:
+//   hosts:
+//     corp.internal:
+//       cacert: /etc/ssl/corp-ca.pem
+//       cert: /home/me/.certs/me.p12
+//       cert_password: ...
+//       pins: [sha256//8S0FdFMkNnQ3YZLdhXVRlyaRYRROjyNkPn3KnxzbCT0=]
+// ```
+
+use crate::client::{LimitsConfig, OAuthProfile, ProxyConfig, TlsConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Per-host rate limits and concurrency caps
+    pub limits: LimitsConfig,
+    /// Check robots.txt before every request
+    pub robots: bool,
+    /// Proxies; the environment fills in whatever is not set here
+    pub proxy: ProxyConfig,
+    /// OAuth2 profiles by name, used with `--oauth <name>`
+    pub oauth: HashMap<String, OAuthProfile>,
+    /// CA bundles, client certificates and pins, globally or per domain
+    pub tls: TlsConfig,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/gurl/config.yaml`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+        Some(base.join("gurl").join("config.yaml"))
+    }
+
+    /// Load the config from its default location.
+    pub fn load() -> Result<Self, crate::Error> {
+        match Self::default_path() {
+            Some(path) => Self::from_path(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, crate::Error> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_yaml(&text)
+                .map_err(|e| crate::Error::Config(format!("{}: {e}", path.display()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(crate::Error::Config(format!("{}: {e}", path.display()))),
+        }
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, serde_yaml::Error> {
+        // An empty document deserializes as null rather than an empty map
+        if text.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::OAuthGrant;
+
+    #[test]
+    fn test_parse_limits() {
+        let config = Config::from_yaml(
+            "limits:\n  requests_per_second: 5\n  hosts:\n    api.github.com:\n      burst: 3\n",
+        )
+        .unwrap();
+        let github = config.limits.for_host("api.github.com");
+        assert_eq!(github.requests_per_second, Some(5.0));
+        assert_eq!(github.burst, Some(3));
+
+        assert_eq!(Config::from_yaml("").unwrap(), Config::default());
+        assert!(Config::from_yaml("limits: [1]").is_err());
+    }
+
+    #[test]
+    fn test_parse_oauth_profiles() {
+        let config = Config::from_yaml(
+            "oauth:\n  ci:\n    token_url: https://auth.test/token\n    client_id: gurl\n  \
+             me:\n    token_url: https://auth.test/token\n    client_id: cli\n    \
+             grant: device_code\n    device_authorization_url: https://auth.test/device\n",
+        )
+        .unwrap();
+        assert_eq!(config.oauth["ci"].grant, OAuthGrant::ClientCredentials);
+        assert_eq!(config.oauth["me"].grant, OAuthGrant::DeviceCode);
+        assert!(Config::from_yaml("oauth:\n  x:\n    client_id: a\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_tls() {
+        let config = Config::from_yaml(
+            "tls:\n  cacert: /etc/ssl/corp-ca.pem\n  hosts:\n    corp.internal:\n      \
+             cert: /certs/me.p12\n      insecure: true\n",
+        )
+        .unwrap();
+        let tls = config.tls.for_host("api.corp.internal");
+        assert_eq!(tls.cacert.unwrap().to_str(), Some("/etc/ssl/corp-ca.pem"));
+        assert_eq!(tls.cert.unwrap().to_str(), Some("/certs/me.p12"));
+        assert_eq!(tls.insecure, Some(true));
+        assert_eq!(config.tls.for_host("example.com").insecure, None);
+    }
+}
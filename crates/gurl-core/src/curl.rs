@@ -0,0 +1,830 @@
+// curl command lines, in both directions.
+//
+// `CurlCommand::parse` takes a command as pasted from API docs or a
+// browser's "Copy as cURL" (quotes, `$'...'` strings and line continuations
+// included) and turns the options that describe the request into a
+// `GurlRequest`. Options that only change curl's own output are ignored;
+// anything else is refused rather than silently dropped, since the request
+// sent would not be the one the command describes.
+//
+// `Display` goes the other way and renders a request as a single-line curl
+// command that sends the same thing.
+
+use crate::client::{Auth, Body, FormPart, GurlRequest, PartValue, RetryPolicy};
+use reqwest::Method;
+use reqwest::header::{CONTENT_TYPE, HeaderName, HeaderValue};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+use url::Url;
+
+/// A request together with the curl options that are client settings in
+/// gurl.
+pub struct CurlCommand {
+    pub request: GurlRequest,
+    /// `-k`: skip server certificate verification
+    pub insecure: bool,
+    /// `-b` with a file name: a Netscape cookie file to send cookies from
+    pub cookie_file: Option<PathBuf>,
+    /// More options in curl syntax, rendered before the URL. `parse` leaves
+    /// this empty.
+    pub options: Vec<String>,
+}
+
+/// Long name, short name and whether the option takes a value.
+const OPTIONS: &[(&str, Option<char>, bool)] = &[
+    ("request", Some('X'), true),
+    ("url", None, true),
+    ("header", Some('H'), true),
+    ("user-agent", Some('A'), true),
+    ("referer", Some('e'), true),
+    ("cookie", Some('b'), true),
+    ("data", Some('d'), true),
+    ("data-ascii", None, true),
+    ("data-raw", None, true),
+    ("data-binary", None, true),
+    ("data-urlencode", None, true),
+    ("json", None, true),
+    ("form", Some('F'), true),
+    ("form-string", None, true),
+    ("get", Some('G'), false),
+    ("head", Some('I'), false),
+    ("user", Some('u'), true),
+    ("basic", None, false),
+    ("digest", None, false),
+    ("oauth2-bearer", None, true),
+    ("insecure", Some('k'), false),
+    ("location", Some('L'), false),
+    ("max-redirs", None, true),
+    ("max-time", Some('m'), true),
+    ("max-filesize", None, true),
+    ("retry", None, true),
+    // gurl always asks for compressed responses
+    ("compressed", None, false),
+    // Options that only change what curl prints
+    ("silent", Some('s'), false),
+    ("show-error", Some('S'), false),
+    ("verbose", Some('v'), false),
+    ("include", Some('i'), false),
+    ("no-progress-meter", None, false),
+    ("progress-bar", Some('#'), false),
+];
+
+/// What the data options (`-d`, `--data-binary`, ...) contributed, in order.
+enum Data {
+    Bytes(Vec<u8>),
+    /// `--data-binary @file`, streamed when it is the only data
+    File(PathBuf),
+}
+
+impl Data {
+    fn into_bytes(self) -> Result<Vec<u8>, crate::Error> {
+        match self {
+            Data::Bytes(bytes) => Ok(bytes),
+            Data::File(path) => {
+                std::fs::read(&path).map_err(|e| invalid(&format!("{}: {e}", path.display())))
+            }
+        }
+    }
+}
+
+impl CurlCommand {
+    pub fn new(request: GurlRequest) -> Self {
+        Self {
+            request,
+            insecure: false,
+            cookie_file: None,
+            options: Vec::new(),
+        }
+    }
+
+    /// Parse a curl command line; the leading `curl` is optional.
+    pub fn parse(command: &str) -> Result<Self, crate::Error> {
+        let words = split_words(command)?;
+        let args = match words.split_first() {
+            Some((first, rest)) if first == "curl" => rest,
+            _ => &words[..],
+        };
+        Self::from_args(args)
+    }
+
+    /// Parse curl's arguments, without the program name.
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Result<Self, crate::Error> {
+        let mut method = None;
+        let mut url = None;
+        let mut headers = Vec::new();
+        let mut cookies = Vec::new();
+        let mut data = Vec::new();
+        let mut json = false;
+        let mut parts = Vec::new();
+        let mut get = false;
+        let mut user = None;
+        let mut digest = false;
+        let mut bearer = None;
+        let mut insecure = false;
+        let mut cookie_file = None;
+        let mut follow = false;
+        let mut max_redirects = None;
+        let mut timeout = None;
+        let mut max_size = None;
+        let mut retries = None;
+
+        let mut args = args.iter().map(AsRef::as_ref);
+        while let Some(arg) = args.next() {
+            let options = match arg.strip_prefix("--") {
+                Some(long) if !long.is_empty() => vec![find_long(long)?],
+                _ if arg.len() > 1 && arg.starts_with('-') => {
+                    // A cluster like -sSL, or -XPOST with its value attached
+                    let mut options = Vec::new();
+                    for (i, c) in arg.char_indices().skip(1) {
+                        let (name, _, takes_value) = find_short(c)?;
+                        let attached = &arg[i + c.len_utf8()..];
+                        if takes_value && !attached.is_empty() {
+                            options.push((name, Some(attached)));
+                            break;
+                        }
+                        options.push((name, None));
+                    }
+                    options
+                }
+                _ => {
+                    set_url(&mut url, arg)?;
+                    continue;
+                }
+            };
+            for (name, attached) in options {
+                let takes_value = OPTIONS.iter().any(|o| o.0 == name && o.2);
+                let value = match attached {
+                    Some(value) => value,
+                    None if takes_value => args
+                        .next()
+                        .ok_or_else(|| invalid(&format!("--{name} needs a value")))?,
+                    None => "",
+                };
+                match name {
+                    "request" => method = Some(value.to_string()),
+                    "url" => set_url(&mut url, value)?,
+                    "header" => headers.push(value.to_string()),
+                    "user-agent" => headers.push(format!("User-Agent: {value}")),
+                    "referer" => headers.push(format!("Referer: {value}")),
+                    "cookie" if value.contains('=') => cookies.push(value.to_string()),
+                    "cookie" => cookie_file = Some(PathBuf::from(value)),
+                    "data" | "data-ascii" => data.push(Data::Bytes(
+                        read_data(value).map_err(|e| invalid(&format!("{value}: {e}")))?,
+                    )),
+                    "data-raw" => data.push(Data::Bytes(value.as_bytes().to_vec())),
+                    "data-binary" => data.push(match value.strip_prefix('@') {
+                        Some(path) if path != "-" => Data::File(PathBuf::from(path)),
+                        _ => Data::Bytes(
+                            read_binary(value).map_err(|e| invalid(&format!("{value}: {e}")))?,
+                        ),
+                    }),
+                    "data-urlencode" => data.push(Data::Bytes(
+                        urlencode_data(value).map_err(|e| invalid(&format!("{value}: {e}")))?,
+                    )),
+                    "json" => {
+                        json = true;
+                        data.push(Data::Bytes(
+                            read_binary(value).map_err(|e| invalid(&format!("{value}: {e}")))?,
+                        ));
+                    }
+                    "form" => parts.push(value.parse::<FormPart>().map_err(|e| invalid(&e))?),
+                    "form-string" => {
+                        let (name, value) = value
+                            .split_once('=')
+                            .ok_or_else(|| invalid(&format!("invalid form field: {value}")))?;
+                        parts.push(FormPart::text(name, value));
+                    }
+                    "get" => get = true,
+                    "head" => method = Some("HEAD".to_string()),
+                    "user" => user = Some(value.to_string()),
+                    "digest" => digest = true,
+                    "oauth2-bearer" => bearer = Some(value.to_string()),
+                    "insecure" => insecure = true,
+                    "location" => follow = true,
+                    "max-redirs" => max_redirects = Some(number(name, value)?),
+                    "max-time" => {
+                        let secs = number::<f64>(name, value)?;
+                        timeout = Some(
+                            Duration::try_from_secs_f64(secs)
+                                .map_err(|_| invalid(&format!("invalid --max-time: {value}")))?,
+                        );
+                    }
+                    "max-filesize" => max_size = Some(number(name, value)?),
+                    "retry" => retries = Some(number(name, value)?),
+                    _ => {}
+                }
+            }
+        }
+
+        let url = url.ok_or_else(|| invalid("no URL"))?;
+        let mut url = match Url::parse(url) {
+            // Like curl, take a bare host to mean http://
+            Err(url::ParseError::RelativeUrlWithoutBase) => Url::parse(&format!("http://{url}")),
+            parsed => parsed,
+        }
+        .map_err(|e| crate::Error::InvalidUrl(format!("{url}: {e}")))?;
+
+        let mut body = None;
+        let mut default_type = None;
+        if !data.is_empty() {
+            if get {
+                let mut query = url.query().map(str::to_string).unwrap_or_default();
+                for data in data {
+                    if !query.is_empty() {
+                        query.push('&');
+                    }
+                    query.push_str(&String::from_utf8_lossy(&data.into_bytes()?));
+                }
+                url.set_query(Some(&query));
+            } else {
+                default_type = Some(if json {
+                    "application/json"
+                } else {
+                    "application/x-www-form-urlencoded"
+                });
+                body = Some(match &data[..] {
+                    [Data::File(path)] => Body::File(path.clone()),
+                    _ => {
+                        let mut joined = Vec::new();
+                        for data in data {
+                            if !joined.is_empty() {
+                                joined.push(b'&');
+                            }
+                            joined.extend(data.into_bytes()?);
+                        }
+                        Body::Raw(joined)
+                    }
+                });
+            }
+        } else if !parts.is_empty() {
+            body = Some(Body::Multipart(parts));
+        }
+        let mut req = GurlRequest::get(url).with_method(match &method {
+            Some(method) => Method::from_bytes(method.as_bytes())
+                .map_err(|_| invalid(&format!("invalid method: {method}")))?,
+            None if body.is_some() => Method::POST,
+            None => Method::GET,
+        });
+        req.body = body;
+        req.timeout = timeout;
+        req.max_size = max_size;
+        req.retry = retries.map(RetryPolicy::new);
+
+        for header in &headers {
+            // "Name:" removes a header curl would add, "Name;" sends it empty
+            let (name, value) = match header.split_once(':') {
+                Some((name, value)) => (name, value.trim()),
+                None => match header.strip_suffix(';') {
+                    Some(name) => (name, ""),
+                    None => return Err(invalid(&format!("invalid header: {header}"))),
+                },
+            };
+            let name = HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| invalid(&format!("invalid header: {header}")))?;
+            if value.is_empty() && header.contains(':') {
+                if name == CONTENT_TYPE {
+                    default_type = None;
+                }
+                req.headers.remove(&name);
+                continue;
+            }
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| invalid(&format!("invalid header: {header}")))?;
+            req.headers.append(name, value);
+        }
+        if let Some(content_type) = default_type
+            && !req.headers.contains_key(CONTENT_TYPE)
+        {
+            req.headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        }
+        if json && !req.headers.contains_key("accept") {
+            req.headers
+                .insert("accept", HeaderValue::from_static("application/json"));
+        }
+        if !cookies.is_empty() {
+            let value = HeaderValue::from_str(&cookies.join("; "))
+                .map_err(|_| invalid("invalid cookie"))?;
+            req.headers.append("cookie", value);
+        }
+
+        req.auth = match (user, bearer) {
+            (Some(user), _) => {
+                let (username, password) = match user.split_once(':') {
+                    Some((username, password)) => {
+                        (username.to_string(), Some(password.to_string()))
+                    }
+                    None => (user, None),
+                };
+                Some(if digest {
+                    Auth::digest(username, password.unwrap_or_default())
+                } else {
+                    Auth::Basic { username, password }
+                })
+            }
+            (None, Some(token)) => Some(Auth::bearer(token)),
+            (None, None) => None,
+        };
+        req.follow_redirects = follow;
+        if let Some(max) = max_redirects {
+            req.max_redirects = max;
+        }
+        Ok(Self {
+            request: req,
+            insecure,
+            cookie_file,
+            options: Vec::new(),
+        })
+    }
+}
+
+impl fmt::Display for CurlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let req = &self.request;
+        let mut words = Words(vec!["curl".to_string()]);
+
+        // curl sends data as a POST unless told otherwise
+        let implied = match &req.body {
+            Some(_) => Method::POST,
+            None => Method::GET,
+        };
+        if req.method == Method::HEAD && req.body.is_none() {
+            words.arg("-I");
+        } else if req.method != implied {
+            words.args(["-X", req.method.as_str()]);
+        }
+        if self.insecure {
+            words.arg("-k");
+        }
+        if req.follow_redirects {
+            words.args(["-L", "--max-redirs", &req.max_redirects.to_string()]);
+        }
+        if let Some(timeout) = req.timeout {
+            words.args(["--max-time", &timeout.as_secs_f64().to_string()]);
+        }
+        if let Some(max) = req.max_size {
+            words.args(["--max-filesize", &max.to_string()]);
+        }
+        if let Some(retry) = &req.retry {
+            words.args(["--retry", &retry.max_retries.to_string()]);
+        }
+        for (name, value) in &req.headers {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            words.args(["-H", &format!("{name}: {value}")]);
+        }
+        if let Some(path) = &self.cookie_file {
+            words.args(["-b", &path.to_string_lossy()]);
+        }
+
+        // curl labels raw data as a form; gurl sends it without a type
+        if matches!(
+            req.body,
+            Some(Body::Raw(_) | Body::File(_) | Body::Stream(_))
+        ) && !req.headers.contains_key(CONTENT_TYPE)
+        {
+            words.args(["-H", "Content-Type:"]);
+        }
+        match &req.body {
+            None => {}
+            Some(Body::Raw(data)) => {
+                words.arg("--data-binary");
+                match std::str::from_utf8(data) {
+                    Ok(text) if !text.starts_with('@') => words.arg(text),
+                    _ => words.raw(quote_bytes(data)),
+                }
+            }
+            Some(Body::Json(value)) => {
+                if !req.headers.contains_key(CONTENT_TYPE) {
+                    words.args(["-H", "Content-Type: application/json"]);
+                }
+                words.args(["--data-raw", &value.to_string()]);
+            }
+            Some(Body::Form(pairs)) => {
+                let form = url::form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(pairs)
+                    .finish();
+                words.args(["--data-raw", &form]);
+            }
+            Some(Body::Multipart(parts)) => {
+                for part in parts {
+                    let (option, field) = form_field(part);
+                    words.args([option, &field]);
+                }
+            }
+            Some(Body::File(path)) => {
+                words.args(["--data-binary", &format!("@{}", path.display())]);
+            }
+            Some(Body::Stream(_)) => words.args(["--data-binary", "@-"]),
+        }
+
+        match &req.auth {
+            None | Some(Auth::OAuth2(_)) => {}
+            Some(Auth::Basic { username, password }) => match password {
+                Some(password) => words.args(["-u", &format!("{username}:{password}")]),
+                None => words.args(["-u", username]),
+            },
+            Some(Auth::Digest { username, password }) => {
+                words.args(["--digest", "-u", &format!("{username}:{password}")]);
+            }
+            Some(Auth::Bearer(token)) => words.args(["--oauth2-bearer", token]),
+            Some(Auth::SigV4(signer)) => {
+                let scope = format!("aws:amz:{}:{}", signer.region, signer.service);
+                words.args(["--aws-sigv4", &scope, "--user"]);
+                // Point at the credentials rather than printing them
+                words.raw("\"$AWS_ACCESS_KEY_ID:$AWS_SECRET_ACCESS_KEY\"".to_string());
+                if signer.credentials.session_token.is_some() {
+                    words.arg("-H");
+                    words.raw("\"x-amz-security-token: $AWS_SESSION_TOKEN\"".to_string());
+                }
+            }
+        }
+        for option in &self.options {
+            words.arg(option);
+        }
+        words.arg(req.url.as_str());
+        write!(f, "{}", words.0.join(" "))
+    }
+}
+
+/// Shell words, quoted as they are added.
+struct Words(Vec<String>);
+
+impl Words {
+    fn arg(&mut self, arg: &str) {
+        self.0.push(quote(arg).into_owned());
+    }
+
+    fn args<'a>(&mut self, args: impl IntoIterator<Item = &'a str>) {
+        args.into_iter().for_each(|arg| self.arg(arg));
+    }
+
+    /// A word that is already shell syntax.
+    fn raw(&mut self, word: String) {
+        self.0.push(word);
+    }
+}
+
+/// The curl option and value that send `part`.
+fn form_field(part: &FormPart) -> (&'static str, String) {
+    let name = &part.name;
+    let mut field = match &part.value {
+        // -F would read a file for these, or take ';' as an option
+        PartValue::Text(text)
+            if part.content_type.is_none()
+                && part.filename.is_none()
+                && (text.starts_with(['@', '<']) || text.contains(';')) =>
+        {
+            return ("--form-string", format!("{name}={text}"));
+        }
+        PartValue::Text(text) => format!("{name}={text}"),
+        PartValue::File(path) => format!("{name}=@{}", path.display()),
+    };
+    if let Some(mime) = &part.content_type {
+        field.push_str(&format!(";type={mime}"));
+    }
+    let own_name = match &part.value {
+        PartValue::File(path) => path.file_name().map(|n| n.to_string_lossy().into_owned()),
+        PartValue::Text(_) => None,
+    };
+    if let Some(filename) = part.filename.as_ref().filter(|_| part.filename != own_name) {
+        field.push_str(&format!(";filename={filename}"));
+    }
+    ("-F", field)
+}
+
+/// Quote `arg` for a POSIX shell, leaving it bare when that is safe.
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+/// Quote arbitrary bytes as a bash `$'...'` string.
+fn quote_bytes(bytes: &[u8]) -> String {
+    let mut quoted = String::from("$'");
+    for &b in bytes {
+        match b {
+            b'\'' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(b as char);
+            }
+            0x20..=0x7e => quoted.push(b as char),
+            _ => quoted.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// The bytes for curl's `-d` value: the value itself, or with `@file` or
+/// `@-` the contents of a file or stdin with carriage returns and newlines
+/// removed.
+pub fn read_data(value: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = match value.strip_prefix('@') {
+        Some("-") => read_stdin()?,
+        Some(path) => std::fs::read(path)?,
+        None => return Ok(value.as_bytes().to_vec()),
+    };
+    bytes.retain(|&b| b != b'\r' && b != b'\n');
+    Ok(bytes)
+}
+
+/// Like `read_data`, but keeping newlines, as `--data-binary` and `--json` do.
+fn read_binary(value: &str) -> io::Result<Vec<u8>> {
+    match value.strip_prefix('@') {
+        Some("-") => read_stdin(),
+        Some(path) => std::fs::read(path),
+        None => Ok(value.as_bytes().to_vec()),
+    }
+}
+
+fn read_stdin() -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// curl's `--data-urlencode`: `content`, `=content`, `name=content`,
+/// `@file` or `name@file`, with the content percent-encoded.
+fn urlencode_data(value: &str) -> io::Result<Vec<u8>> {
+    let encode = |content: &[u8]| url::form_urlencoded::byte_serialize(content).collect::<String>();
+    let (name, content) = match value.find(['=', '@']) {
+        Some(i) if value.as_bytes()[i] == b'@' => (&value[..i], std::fs::read(&value[i + 1..])?),
+        Some(i) => (&value[..i], value.as_bytes()[i + 1..].to_vec()),
+        None => ("", value.as_bytes().to_vec()),
+    };
+    Ok(match name {
+        "" => encode(&content),
+        name => format!("{name}={}", encode(&content)),
+    }
+    .into_bytes())
+}
+
+fn find_long<'a>(name: &str) -> Result<(&'static str, Option<&'a str>), crate::Error> {
+    OPTIONS
+        .iter()
+        .find(|o| o.0 == name)
+        .map(|o| (o.0, None))
+        .ok_or_else(|| invalid(&format!("unsupported option --{name}")))
+}
+
+fn find_short(c: char) -> Result<(&'static str, Option<char>, bool), crate::Error> {
+    OPTIONS
+        .iter()
+        .find(|o| o.1 == Some(c))
+        .copied()
+        .ok_or_else(|| invalid(&format!("unsupported option -{c}")))
+}
+
+fn set_url<'a>(url: &mut Option<&'a str>, value: &'a str) -> Result<(), crate::Error> {
+    match url.replace(value) {
+        Some(_) => Err(invalid("more than one URL")),
+        None => Ok(()),
+    }
+}
+
+fn number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, crate::Error> {
+    value
+        .parse()
+        .map_err(|_| invalid(&format!("invalid --{name}: {value}")))
+}
+
+fn invalid(message: &str) -> crate::Error {
+    crate::Error::InvalidCurl(message.to_string())
+}
+
+/// Split a command line into words as a POSIX shell would, for the quoting
+/// found in pasted commands: '...', "...", bash's $'...', backslash escapes
+/// and line continuations. Nothing is expanded.
+fn split_words(command: &str) -> Result<Vec<String>, crate::Error> {
+    let unterminated = || invalid("unterminated quote");
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(c) => {
+                    word.push(c);
+                    in_word = true;
+                }
+                None => {}
+            },
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unterminated)? {
+                            '\n' => {}
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                in_word = true;
+                // Escapes may spell out UTF-8 byte by byte
+                let mut bytes = Vec::new();
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        '\\' => {
+                            let escaped = chars.next().ok_or_else(unterminated)?;
+                            match escaped {
+                                'n' => bytes.push(b'\n'),
+                                't' => bytes.push(b'\t'),
+                                'r' => bytes.push(b'\r'),
+                                'e' | 'E' => bytes.push(0x1b),
+                                'x' | 'u' | 'U' => {
+                                    let max = match escaped {
+                                        'x' => 2,
+                                        'u' => 4,
+                                        _ => 8,
+                                    };
+                                    let mut digits = String::new();
+                                    while digits.len() < max
+                                        && let Some(&d) = chars.peek()
+                                        && d.is_ascii_hexdigit()
+                                    {
+                                        digits.push(d);
+                                        chars.next();
+                                    }
+                                    let n = u32::from_str_radix(&digits, 16)
+                                        .map_err(|_| invalid("invalid escape in $'...'"))?;
+                                    if escaped == 'x' {
+                                        bytes.push(n as u8);
+                                    } else {
+                                        let c = char::from_u32(n)
+                                            .ok_or_else(|| invalid("invalid escape in $'...'"))?;
+                                        bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                                    }
+                                }
+                                c => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                            }
+                        }
+                        c => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+                word.push_str(&String::from_utf8_lossy(&bytes));
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        let words = split_words(
+            "curl 'https://a.test/x?q=1' \\\n  -H \"Authorization: Bearer \\\"t\\\"\" \
+             --data-raw $'{\"name\":\"caf\\u00e9\\n\"}' -d a\\ b",
+        )
+        .unwrap();
+        assert_eq!(
+            words,
+            [
+                "curl",
+                "https://a.test/x?q=1",
+                "-H",
+                "Authorization: Bearer \"t\"",
+                "--data-raw",
+                "{\"name\":\"café\n\"}",
+                "-d",
+                "a b",
+            ]
+        );
+        assert!(split_words("curl 'https://a.test").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let command = CurlCommand::parse(
+            "curl -sSL -XPUT api.test/items/1 -H 'Accept: application/json' -H 'X-Drop:' \
+             -d a=1 --data-urlencode 'q=x y' -u me:secret -b 'sid=1' -b jar.txt -k --max-time 2.5",
+        )
+        .unwrap();
+        let req = &command.request;
+        assert_eq!(req.method, Method::PUT);
+        assert_eq!(req.url.as_str(), "http://api.test/items/1");
+        assert!(req.follow_redirects);
+        assert_eq!(req.timeout, Some(Duration::from_millis(2500)));
+        assert_eq!(req.headers["accept"], "application/json");
+        assert_eq!(
+            req.headers["content-type"],
+            "application/x-www-form-urlencoded"
+        );
+        assert_eq!(req.headers["cookie"], "sid=1");
+        assert!(!req.headers.contains_key("x-drop"));
+        assert!(matches!(&req.body, Some(Body::Raw(data)) if data == b"a=1&q=x+y"));
+        assert!(
+            matches!(&req.auth, Some(Auth::Basic { username, password }) if username == "me" && password.as_deref() == Some("secret"))
+        );
+        assert!(command.insecure);
+        assert_eq!(command.cookie_file, Some(PathBuf::from("jar.txt")));
+
+        let command = CurlCommand::parse("curl -G -d q=rust https://a.test/search").unwrap();
+        assert_eq!(command.request.method, Method::GET);
+        assert_eq!(command.request.url.as_str(), "https://a.test/search?q=rust");
+        assert!(command.request.body.is_none());
+        assert!(!command.request.follow_redirects);
+
+        let command =
+            CurlCommand::parse("curl https://a.test -F 'doc=@r.pdf;type=application/pdf'").unwrap();
+        assert_eq!(command.request.method, Method::POST);
+        assert!(matches!(&command.request.body, Some(Body::Multipart(parts)) if parts.len() == 1));
+
+        for bad in [
+            "curl",
+            "curl -x proxy:3128 https://a.test",
+            "curl a.test b.test",
+        ] {
+            assert!(matches!(
+                CurlCommand::parse(bad),
+                Err(crate::Error::InvalidCurl(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        let mut req = GurlRequest::get("https://a.test/items?x=1".parse().unwrap())
+            .with_method(Method::PATCH)
+            .with_body(Body::Json(serde_json::json!({"name": "it's"})))
+            .with_timeout(Duration::from_secs(5));
+        req.headers
+            .insert("x-team", HeaderValue::from_static("search"));
+        req.auth = Some(Auth::bearer("t0ken"));
+        let mut command = CurlCommand::new(req);
+        command.insecure = true;
+        let rendered = command.to_string();
+        assert_eq!(
+            rendered,
+            "curl -X PATCH -k -L --max-redirs 10 --max-time 5 -H 'x-team: search' \
+             -H 'Content-Type: application/json' --data-raw '{\"name\":\"it'\\''s\"}' \
+             --oauth2-bearer t0ken 'https://a.test/items?x=1'"
+        );
+
+        let parsed = CurlCommand::parse(&rendered).unwrap();
+        let req = &parsed.request;
+        assert_eq!(req.method, Method::PATCH);
+        assert_eq!(req.url.as_str(), "https://a.test/items?x=1");
+        assert_eq!(req.headers["x-team"], "search");
+        assert_eq!(req.headers["content-type"], "application/json");
+        assert!(matches!(&req.body, Some(Body::Raw(data)) if data == br#"{"name":"it's"}"#));
+        assert!(matches!(&req.auth, Some(Auth::Bearer(token)) if token == "t0ken"));
+        assert!(parsed.insecure);
+
+        // Raw data goes out without curl's default form type
+        let req = GurlRequest::get("https://a.test/".parse().unwrap())
+            .with_method(Method::POST)
+            .with_body(Body::Raw(b"\x01\xff".to_vec()));
+        let rendered = CurlCommand::new(req).to_string();
+        assert_eq!(
+            rendered,
+            "curl -L --max-redirs 10 -H Content-Type: --data-binary $'\\x01\\xff' https://a.test/"
+        );
+    }
+}
@@ -0,0 +1,646 @@
+// HAR 1.2 archives of what a client sent and received, for auditing.
+//
+// A `HarRecorder` attached to a `GurlClient` gets one entry per request
+// actually sent: every redirect hop and every retried or re-authenticated
+// attempt, failed ones included (status 0 with an `_error`, as browsers
+// record them). Responses served from the cache without a request get an
+// entry built from the cached response. The archive is rewritten after each
+// `execute` call, so it is valid whenever the process stops; an existing
+// archive is continued rather than replaced.
+//
+// Header values listed for redaction are replaced before anything is
+// written. Response bodies are inline (text, or base64 when not UTF-8) or
+// written to a `<name>-bodies` directory next to the archive and referenced
+// by the `_file` custom field.
+
+use crate::client::{Body, PartValue};
+use crate::output::envelope::GurlResponse;
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
+use reqwest::header::{CONTENT_TYPE, HeaderMap, LOCATION};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Headers redacted unless the recorder is told otherwise.
+pub const DEFAULT_REDACTED: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Har {
+    pub log: Log,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Log {
+    pub version: String,
+    pub creator: Creator,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Creator {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub started_date_time: DateTime<Utc>,
+    /// Total time in milliseconds, the sum of the non-negative `timings`
+    pub time: f64,
+    pub request: Request,
+    pub response: Response,
+    pub cache: serde_json::Map<String, serde_json::Value>,
+    pub timings: Timings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Why no response was received
+    #[serde(rename = "_error", default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameValue {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    pub http_version: String,
+    pub cookies: Vec<NameValue>,
+    pub headers: Vec<NameValue>,
+    pub query_string: Vec<NameValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<PostData>,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostData {
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Param>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Param {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    pub status: u16,
+    pub status_text: String,
+    pub http_version: String,
+    pub cookies: Vec<NameValue>,
+    pub headers: Vec<NameValue>,
+    pub content: Content,
+    #[serde(rename = "redirectURL")]
+    pub redirect_url: String,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    pub size: i64,
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// The body's sidecar file, relative to the archive
+    #[serde(rename = "_file", default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+/// Phase durations in milliseconds; -1 where a phase did not apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timings {
+    pub blocked: f64,
+    pub dns: f64,
+    /// Includes `ssl`, as HAR specifies
+    pub connect: f64,
+    pub ssl: f64,
+    pub send: f64,
+    pub wait: f64,
+    pub receive: f64,
+}
+
+/// One request as sent, and its response headers or the error instead.
+pub(crate) struct Exchange {
+    pub(crate) started: DateTime<Utc>,
+    pub(crate) request: Request,
+    pub(crate) response: Result<Response, String>,
+    pub(crate) elapsed: Duration,
+}
+
+impl Request {
+    /// The request as it goes out, with `body` described as post data.
+    pub(crate) fn sent(request: &reqwest::Request, body: Option<&Body>) -> Self {
+        let headers = request.headers();
+        let post_data = body.map(|body| post_data(body, headers));
+        let body_size = match body {
+            None => 0,
+            Some(_) => request
+                .body()
+                .and_then(reqwest::Body::as_bytes)
+                .map_or(-1, |bytes| bytes.len() as i64),
+        };
+        Self {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            http_version: format!("{:?}", request.version()),
+            cookies: Vec::new(),
+            headers: name_values(headers),
+            query_string: request
+                .url()
+                .query_pairs()
+                .map(|(name, value)| NameValue {
+                    name: name.into_owned(),
+                    value: value.into_owned(),
+                })
+                .collect(),
+            post_data,
+            headers_size: -1,
+            body_size,
+        }
+    }
+}
+
+impl Response {
+    /// Status and headers of a response whose body is not read yet.
+    pub(crate) fn received(response: &reqwest::Response) -> Self {
+        let headers = response.headers();
+        Self {
+            status: response.status().as_u16(),
+            status_text: response
+                .status()
+                .canonical_reason()
+                .unwrap_or("")
+                .to_string(),
+            http_version: format!("{:?}", response.version()),
+            cookies: Vec::new(),
+            headers: name_values(headers),
+            content: Content {
+                mime_type: header_str(headers, CONTENT_TYPE.as_str()),
+                ..Content::default()
+            },
+            redirect_url: header_str(headers, LOCATION.as_str()),
+            headers_size: -1,
+            body_size: -1,
+        }
+    }
+}
+
+fn post_data(body: &Body, headers: &HeaderMap) -> PostData {
+    let mut data = PostData {
+        mime_type: header_str(headers, CONTENT_TYPE.as_str()),
+        params: Vec::new(),
+        text: String::new(),
+        comment: None,
+    };
+    match body {
+        Body::Raw(bytes) => data.text = String::from_utf8_lossy(bytes).into_owned(),
+        Body::Json(value) => data.text = value.to_string(),
+        Body::Form(pairs) => {
+            data.text = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(pairs)
+                .finish();
+            data.params = pairs
+                .iter()
+                .map(|(name, value)| Param {
+                    name: name.clone(),
+                    value: Some(value.clone()),
+                    file_name: None,
+                    content_type: None,
+                })
+                .collect();
+        }
+        Body::Multipart(parts) => {
+            data.params = parts
+                .iter()
+                .map(|part| Param {
+                    name: part.name.clone(),
+                    value: match &part.value {
+                        PartValue::Text(text) => Some(text.clone()),
+                        PartValue::File(_) => None,
+                    },
+                    file_name: part.filename.clone(),
+                    content_type: part.content_type.clone(),
+                })
+                .collect();
+        }
+        Body::File(path) => data.comment = Some(format!("streamed from {}", path.display())),
+        Body::Stream(_) => data.comment = Some("streamed, not recorded".to_string()),
+    }
+    data
+}
+
+fn name_values(headers: &HeaderMap) -> Vec<NameValue> {
+    headers
+        .iter()
+        .map(|(name, value)| NameValue {
+            name: name.to_string(),
+            value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        })
+        .collect()
+}
+
+fn header_str(headers: &HeaderMap, name: &str) -> String {
+    headers
+        .get(name)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        .unwrap_or_default()
+}
+
+/// Appends entries to a HAR file.
+#[derive(Debug)]
+pub struct HarRecorder {
+    path: PathBuf,
+    /// Where bodies go when they are not inline
+    bodies: Option<PathBuf>,
+    redact: Vec<String>,
+    log: Mutex<Log>,
+}
+
+impl HarRecorder {
+    /// Record into `path`, continuing the archive already there, if any.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let log = match std::fs::read(&path) {
+            Ok(bytes) => {
+                serde_json::from_slice::<Har>(&bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .log
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Log {
+                version: "1.2".to_string(),
+                creator: Creator {
+                    name: "gurl".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+                entries: Vec::new(),
+            },
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path,
+            bodies: None,
+            redact: DEFAULT_REDACTED
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            log: Mutex::new(log),
+        })
+    }
+
+    /// Write response bodies to files in a `<name>-bodies` directory next
+    /// to the archive instead of inline.
+    pub fn sidecar_bodies(mut self, enabled: bool) -> Self {
+        self.bodies = enabled.then(|| {
+            let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+            self.path.with_file_name(format!("{stem}-bodies"))
+        });
+        self
+    }
+
+    /// Headers whose values are replaced with "[REDACTED]", in place of
+    /// `DEFAULT_REDACTED`.
+    pub fn redact_headers<S: AsRef<str>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.redact = names
+            .into_iter()
+            .map(|name| name.as_ref().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add the entries for one `execute` call and rewrite the archive.
+    /// `response` is the envelope it returned, if it succeeded.
+    pub(crate) fn record(
+        &self,
+        exchanges: Vec<Exchange>,
+        response: Option<&GurlResponse>,
+    ) -> io::Result<()> {
+        let mut entries: Vec<Entry> = exchanges.into_iter().map(Entry::from).collect();
+        if let Some(response) = response {
+            match entries.last_mut() {
+                // A 304 revalidation keeps its empty body; the envelope's is the cache's
+                Some(last) if last.response.status == response.response.status => {
+                    last.response.content = self.content(response)?;
+                    last.response.body_size = last.response.content.size;
+                    last.timings = timings(response);
+                    last.time = response.response.timing.total_ms as f64;
+                }
+                Some(_) => {}
+                None => entries.push(self.cached_entry(response)?),
+            }
+        }
+        if entries.is_empty() {
+            return Ok(());
+        }
+        for entry in &mut entries {
+            self.redact(&mut entry.request.headers);
+            self.redact(&mut entry.response.headers);
+        }
+
+        let mut log = self.log.lock().unwrap();
+        log.entries.extend(entries);
+        let har = Har { log: log.clone() };
+        let tmp = self.path.with_extension("har.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&har)?)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+
+    fn redact(&self, headers: &mut [NameValue]) {
+        for header in headers {
+            if self
+                .redact
+                .iter()
+                .any(|name| header.name.eq_ignore_ascii_case(name))
+            {
+                header.value = REDACTED.to_string();
+            }
+        }
+    }
+
+    fn content(&self, response: &GurlResponse) -> io::Result<Content> {
+        let content = &response.content;
+        let mut har = Content {
+            size: content.raw_body.len() as i64,
+            mime_type: content.original_type.clone(),
+            ..Content::default()
+        };
+        if let Some(truncated) = &content.truncated {
+            har.size = truncated.size as i64;
+        }
+        match &self.bodies {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                let name = match &content.truncated {
+                    Some(truncated) => {
+                        let name = format!(
+                            "{}-{}",
+                            crate::hex(&Sha256::digest(&content.raw_body)),
+                            truncated.size
+                        );
+                        std::fs::copy(&truncated.body_file, dir.join(&name))?;
+                        name
+                    }
+                    None => {
+                        let name = crate::hex(&Sha256::digest(&content.raw_body));
+                        std::fs::write(dir.join(&name), &content.raw_body)?;
+                        name
+                    }
+                };
+                let dir_name = dir.file_name().unwrap_or_default().to_string_lossy();
+                har.file = Some(format!("{dir_name}/{name}"));
+            }
+            None => {
+                match std::str::from_utf8(&content.raw_body) {
+                    Ok(text) => har.text = Some(text.to_string()),
+                    Err(_) => {
+                        har.text = Some(BASE64_STANDARD.encode(&content.raw_body));
+                        har.encoding = Some("base64".to_string());
+                    }
+                }
+                if let Some(truncated) = &content.truncated {
+                    har.comment = Some(format!(
+                        "truncated to the first {} of {} bytes",
+                        truncated.kept, truncated.size
+                    ));
+                }
+            }
+        }
+        Ok(har)
+    }
+
+    /// An entry for a response the cache answered without a request.
+    fn cached_entry(&self, response: &GurlResponse) -> io::Result<Entry> {
+        let meta = &response.response;
+        let url = url::Url::parse(&response.request.url).ok();
+        let content = self.content(response)?;
+        Ok(Entry {
+            started_date_time: response.request.timestamp,
+            time: meta.timing.total_ms as f64,
+            request: Request {
+                method: response.request.method.clone(),
+                url: response.request.url.clone(),
+                http_version: String::new(),
+                cookies: Vec::new(),
+                headers: Vec::new(),
+                query_string: url
+                    .iter()
+                    .flat_map(|url| url.query_pairs())
+                    .map(|(name, value)| NameValue {
+                        name: name.into_owned(),
+                        value: value.into_owned(),
+                    })
+                    .collect(),
+                post_data: None,
+                headers_size: -1,
+                body_size: 0,
+            },
+            response: Response {
+                status: meta.status,
+                status_text: meta.status_text.clone(),
+                http_version: String::new(),
+                cookies: Vec::new(),
+                headers: meta
+                    .header_list
+                    .iter()
+                    .map(|field| NameValue {
+                        name: field.name.clone(),
+                        value: field.value.clone(),
+                    })
+                    .collect(),
+                body_size: content.size,
+                content,
+                redirect_url: String::new(),
+                headers_size: -1,
+            },
+            cache: serde_json::Map::new(),
+            timings: timings(response),
+            comment: Some("served from the HTTP cache".to_string()),
+            error: None,
+        })
+    }
+}
+
+impl From<Exchange> for Entry {
+    fn from(exchange: Exchange) -> Self {
+        let wait = exchange.elapsed.as_secs_f64() * 1000.0;
+        let (response, error) = match exchange.response {
+            Ok(response) => (response, None),
+            Err(error) => (
+                Response {
+                    status: 0,
+                    status_text: String::new(),
+                    http_version: String::new(),
+                    cookies: Vec::new(),
+                    headers: Vec::new(),
+                    content: Content::default(),
+                    redirect_url: String::new(),
+                    headers_size: -1,
+                    body_size: -1,
+                },
+                Some(error),
+            ),
+        };
+        Entry {
+            started_date_time: exchange.started,
+            time: wait,
+            request: exchange.request,
+            response,
+            cache: serde_json::Map::new(),
+            timings: Timings {
+                blocked: -1.0,
+                dns: -1.0,
+                connect: -1.0,
+                ssl: -1.0,
+                send: 0.0,
+                wait,
+                receive: 0.0,
+            },
+            comment: None,
+            error,
+        }
+    }
+}
+
+/// HAR timings from the envelope's, which count from the start of the request.
+fn timings(response: &GurlResponse) -> Timings {
+    let timing = &response.response.timing;
+    let phase = |ms: Option<u64>| ms.map_or(-1.0, |ms| ms as f64);
+    let dns = timing.dns_ms.unwrap_or(0);
+    let connect = timing.connect_ms.unwrap_or(0) + timing.tls_ms.unwrap_or(0);
+    let first_byte = timing.first_byte_ms.unwrap_or(timing.total_ms);
+    Timings {
+        blocked: -1.0,
+        dns: phase(timing.dns_ms),
+        connect: match (timing.connect_ms, timing.tls_ms) {
+            (None, None) => -1.0,
+            _ => connect as f64,
+        },
+        ssl: phase(timing.tls_ms),
+        send: 0.0,
+        wait: first_byte.saturating_sub(dns + connect) as f64,
+        receive: timing.total_ms.saturating_sub(first_byte) as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GurlClient;
+    use crate::client::GurlRequest;
+    use crate::client::test_server::{Reply, TestServer};
+
+    /// Redirect `/old` to `/new`, which answers with a short text body.
+    async fn serve() -> TestServer {
+        TestServer::start(|request| match request.target.as_str() {
+            "/old" => Reply::redirect(302, "/new").header("set-cookie", "id=1"),
+            _ => Reply::text("hello"),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_records_redirects_with_redaction() {
+        let server = serve().await;
+        let path = std::env::temp_dir().join(format!("gurl-har-{}.har", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let client = GurlClient::new()
+            .unwrap()
+            .with_har(HarRecorder::open(&path).unwrap());
+        let mut req = GurlRequest::get(server.url("/old"));
+        req.headers
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        client.execute(req).await.unwrap();
+
+        // A second recorder continues the archive
+        let client = GurlClient::new().unwrap().with_har(
+            HarRecorder::open(&path)
+                .unwrap()
+                .redact_headers(Vec::<String>::new()),
+        );
+        let mut req = GurlRequest::get(server.url("/new"));
+        req.headers
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        client.execute(req).await.unwrap();
+
+        let har: Har = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(har.log.version, "1.2");
+        let entries = &har.log.entries;
+        assert_eq!(entries.len(), 3);
+
+        let hop = &entries[0];
+        assert_eq!(hop.request.url, server.url("/old").as_str());
+        assert_eq!(hop.response.status, 302);
+        assert_eq!(hop.response.redirect_url, "/new");
+        let header = |headers: &[NameValue], name: &str| {
+            headers
+                .iter()
+                .find(|header| header.name == name)
+                .map(|header| header.value.clone())
+        };
+        assert_eq!(
+            header(&hop.request.headers, "authorization").as_deref(),
+            Some(REDACTED)
+        );
+        assert_eq!(
+            header(&hop.response.headers, "set-cookie").as_deref(),
+            Some(REDACTED)
+        );
+
+        let last = &entries[1];
+        assert_eq!(last.request.url, server.url("/new").as_str());
+        assert_eq!(last.response.status, 200);
+        assert_eq!(last.response.content.text.as_deref(), Some("hello"));
+        assert_eq!(last.response.content.size, 5);
+        assert!(last.timings.wait >= 0.0 && last.timings.receive >= 0.0);
+
+        assert_eq!(
+            header(&entries[2].request.headers, "authorization").as_deref(),
+            Some("Bearer secret")
+        );
+    }
+}
@@ -1,14 +1,121 @@
+pub mod cache;
 pub mod client;
+pub mod config;
+pub mod curl;
+pub mod har;
 pub mod output;
+pub mod session;
 
-pub use client::GurlClient;
+pub use client::{GurlClient, GurlClientBuilder};
+pub use config::Config;
 pub use output::envelope::GurlResponse;
 
+/// Everything that can go wrong in gurl. Each variant has a stable
+/// machine-readable `code()` for the error envelope.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    /// A request failure that fits none of the other variants
     #[error("request failed: {0}")]
     Request(String),
 
     #[error("invalid URL: {0}")]
     InvalidUrl(String),
+
+    #[error("could not resolve host {host}: {message}")]
+    Dns { host: String, message: String },
+
+    #[error("could not connect: {0}")]
+    Connect(String),
+
+    /// Handshake failures as well as unusable certificates, keys or pins
+    #[error("TLS error: {0}")]
+    Tls(String),
+
+    #[error("timed out: {0}")]
+    Timeout(String),
+
+    #[error("response body exceeds the {limit}-byte limit: {url}")]
+    BodyTooLarge { url: String, limit: u64 },
+
+    #[error("too many redirects: more than {max} following {url}")]
+    TooManyRedirects { url: String, max: usize },
+
+    #[error("blocked by robots.txt: {url} ({rule})")]
+    RobotsDisallowed { url: String, rule: String },
+
+    #[error("invalid config: {0}")]
+    Config(String),
+
+    #[error("invalid curl command: {0}")]
+    InvalidCurl(String),
+
+    #[error("offline and no cached response for {0}")]
+    NotCached(String),
+
+    #[error("no recorded response for {method} {url} in cassette {cassette}")]
+    NotRecorded {
+        method: String,
+        url: String,
+        cassette: String,
+    },
+
+    #[error("authentication failed: {0}")]
+    Auth(String),
+}
+
+impl Error {
+    /// Stable identifier for the kind of failure, e.g. `dns` or `timeout`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Request(_) => "request",
+            Error::InvalidUrl(_) => "invalid_url",
+            Error::Dns { .. } => "dns",
+            Error::Connect(_) => "connect",
+            Error::Tls(_) => "tls",
+            Error::Timeout(_) => "timeout",
+            Error::BodyTooLarge { .. } => "body_too_large",
+            Error::TooManyRedirects { .. } => "too_many_redirects",
+            Error::RobotsDisallowed { .. } => "policy_denied",
+            Error::Config(_) => "config",
+            Error::InvalidCurl(_) => "invalid_curl",
+            Error::NotCached(_) => "not_cached",
+            Error::NotRecorded { .. } => "not_recorded",
+            Error::Auth(_) => "auth",
+        }
+    }
+}
+
+/// Durations as seconds, fractional if need be, in serialized requests.
+pub(crate) mod secs {
+    use serde::{Deserialize, Deserializer, Serializer, de};
+    use std::time::Duration;
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(de::Error::custom)
+    }
+}
+
+/// Write a file only the current user can read, for files holding credentials.
+pub fn write_private(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+/// Lowercase hex encoding, used for fingerprints and cache keys.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
@@ -1,6 +1,7 @@
 use crate::client::response::ResponseMeta;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GurlResponse {
@@ -10,6 +11,38 @@ pub struct GurlResponse {
     pub content: Content,
 }
 
+/// Printed in place of a `GurlResponse` when a request fails.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorEnvelope {
+    pub gurl: String,
+    pub error: ErrorInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorInfo {
+    /// Stable machine-readable kind, see `Error::code`
+    pub code: String,
+    pub message: String,
+}
+
+impl ErrorEnvelope {
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            gurl: env!("CARGO_PKG_VERSION").to_string(),
+            error: ErrorInfo {
+                code: code.into(),
+                message: message.into(),
+            },
+        }
+    }
+}
+
+impl From<&crate::Error> for ErrorEnvelope {
+    fn from(error: &crate::Error) -> Self {
+        Self::new(error.code(), error.to_string())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestMeta {
     pub method: String,
@@ -31,11 +64,26 @@ pub struct Content {
     pub links: Option<Vec<Link>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub images: Option<Vec<Image>>,
+    /// Present when the body was too large to keep in memory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<Truncated>,
     /// Raw HTTP response body (excluded from JSON envelope)
     #[serde(skip)]
     pub raw_body: Vec<u8>,
 }
 
+/// A body larger than the client's memory limit. `raw_body` and the
+/// converted content hold only its first `kept` bytes; the whole body was
+/// streamed to `body_file`, an owner-only file in the temp directory. The
+/// caller owns it: gurl never deletes it once the response is returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Truncated {
+    /// Size of the whole body in bytes
+    pub size: u64,
+    pub kept: usize,
+    pub body_file: PathBuf,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Link {
     pub text: String,
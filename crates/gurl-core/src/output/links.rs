@@ -0,0 +1,156 @@
+// Relative link resolution for converted markdown.
+//
+// Converters emit links as they appear in the page, so `[docs](/docs)` only
+// makes sense next to the URL it came from. Links are resolved against the
+// final URL after redirects; fragments-only links and code blocks are left
+// alone.
+
+use url::Url;
+
+/// Rewrite relative inline links, images and reference definitions in
+/// `markdown` as absolute URLs.
+pub(crate) fn resolve_relative(markdown: &str, base: &Url) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence || trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            out.push_str(line);
+        } else if let Some(definition) = reference_definition(line, base) {
+            out.push_str(&definition);
+        } else {
+            out.push_str(&inline_links(line, base));
+        }
+    }
+    out
+}
+
+/// `[id]: target "title"`
+fn reference_definition(line: &str, base: &Url) -> Option<String> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    if !rest.starts_with('[') || rest.starts_with("[^") {
+        return None;
+    }
+    let label_end = rest.find("]:")? + 2;
+    let after = &rest[label_end..];
+    let spaces = after.len() - after.trim_start().len();
+    let target_start = indent + label_end + spaces;
+    let target_len = line[target_start..]
+        .find(char::is_whitespace)
+        .unwrap_or(line.len() - target_start);
+    let target = &line[target_start..target_start + target_len];
+    let resolved = resolve(target, base)?;
+    Some(format!(
+        "{}{resolved}{}",
+        &line[..target_start],
+        &line[target_start + target_len..]
+    ))
+}
+
+/// `[text](target "title")` and `![alt](target)`, skipping code spans.
+fn inline_links(line: &str, base: &Url) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(i) = rest.find(['`', ']']) {
+        if rest[i..].starts_with('`') {
+            // Copy a code span through its closing backtick untouched
+            let end = rest[i + 1..].find('`').map_or(rest.len(), |j| i + j + 2);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        out.push_str(&rest[..=i]);
+        rest = &rest[i + 1..];
+        let Some(after) = rest.strip_prefix('(') else {
+            continue;
+        };
+        out.push('(');
+        let (target, tail) = match after.strip_prefix('<') {
+            Some(bracketed) => match bracketed.find('>') {
+                Some(end) => {
+                    out.push('<');
+                    (&bracketed[..end], &bracketed[end..])
+                }
+                None => ("", after),
+            },
+            None => {
+                // Balanced parentheses may appear inside a bare target
+                let mut depth = 0usize;
+                let end = after
+                    .char_indices()
+                    .find(|&(_, c)| match c {
+                        '(' => {
+                            depth += 1;
+                            false
+                        }
+                        ')' if depth == 0 => true,
+                        ')' => {
+                            depth -= 1;
+                            false
+                        }
+                        c => c.is_whitespace(),
+                    })
+                    .map_or(after.len(), |(end, _)| end);
+                (&after[..end], &after[end..])
+            }
+        };
+        match resolve(target, base) {
+            Some(resolved) => out.push_str(&resolved),
+            None => out.push_str(target),
+        }
+        rest = tail;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The absolute form of a relative `target`, or `None` to leave it as is.
+fn resolve(target: &str, base: &Url) -> Option<String> {
+    if target.is_empty() || target.starts_with('#') {
+        return None;
+    }
+    match Url::parse(target) {
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            base.join(target).ok().map(|url| url.to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(markdown: &str) -> String {
+        let base = Url::parse("https://example.com/docs/guide/intro").unwrap();
+        resolve_relative(markdown, &base)
+    }
+
+    #[test]
+    fn test_inline_links_and_images() {
+        assert_eq!(
+            resolved("See [setup](../setup \"Setup\") and ![logo](/img/logo(1).png).\n"),
+            "See [setup](https://example.com/docs/setup \"Setup\") and \
+             ![logo](https://example.com/img/logo(1).png).\n"
+        );
+        assert_eq!(
+            resolved("[a](https://other.test/x) [b](#top) [c](mailto:a@b.c) [d](<next page>)"),
+            "[a](https://other.test/x) [b](#top) [c](mailto:a@b.c) \
+             [d](<https://example.com/docs/guide/next%20page>)"
+        );
+    }
+
+    #[test]
+    fn test_reference_definitions_and_code_left_alone() {
+        assert_eq!(
+            resolved("[api]: /api/v1 \"API\"\n"),
+            "[api]: https://example.com/api/v1 \"API\"\n"
+        );
+        let code = "```\n[x](/raw)\n```\nuse `[x](/raw)` here\n";
+        assert_eq!(resolved(code), code);
+    }
+}
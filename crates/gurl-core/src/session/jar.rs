@@ -0,0 +1,202 @@
+// Cookie jar shared between a `GurlClient` and the session it persists to.
+//
+// reqwest's own `Jar` cannot be inspected or serialized, so gurl keeps its
+// own store and hands it to reqwest through the `CookieStore` trait.
+
+use chrono::{DateTime, Utc};
+use cookie::time::OffsetDateTime;
+use cookie_store::{CookieDomain, CookieExpiration, CookieStore, RawCookie};
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use url::Url;
+
+/// A cookie as persisted in a session file and shown by `gurl session show`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    /// Sent to `domain` only, not to its subdomains
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub host_only: bool,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secure: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub http_only: bool,
+    /// Absent for session cookies, which gurl keeps until they are cleared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+pub struct CookieJar(RwLock<CookieStore>);
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unexpired cookies in the jar.
+    pub fn list(&self) -> Vec<StoredCookie> {
+        self.read()
+            .iter_unexpired()
+            .filter_map(|cookie| {
+                let (domain, host_only) = match &cookie.domain {
+                    CookieDomain::HostOnly(domain) => (domain.clone(), true),
+                    CookieDomain::Suffix(domain) => (domain.clone(), false),
+                    _ => return None,
+                };
+                let expires = match cookie.expires {
+                    CookieExpiration::AtUtc(at) => {
+                        DateTime::from_timestamp(at.unix_timestamp(), at.nanosecond())
+                    }
+                    CookieExpiration::SessionEnd => None,
+                };
+                Some(StoredCookie {
+                    name: cookie.name().to_string(),
+                    value: cookie.value().to_string(),
+                    domain,
+                    host_only,
+                    path: String::from(&cookie.path),
+                    secure: cookie.secure().unwrap_or(false),
+                    http_only: cookie.http_only().unwrap_or(false),
+                    expires,
+                })
+            })
+            .collect()
+    }
+
+    /// Add a cookie, replacing any with the same name, domain and path.
+    /// Returns false if the cookie is expired or invalid.
+    pub fn insert(&self, cookie: &StoredCookie) -> bool {
+        if cookie.expires.is_some_and(|at| at <= Utc::now()) {
+            return false;
+        }
+        let domain = cookie.domain.trim_start_matches('.');
+        let path = if cookie.path.starts_with('/') {
+            cookie.path.as_str()
+        } else {
+            "/"
+        };
+        let Ok(url) = Url::parse(&format!("https://{domain}{path}")) else {
+            return false;
+        };
+        let mut raw = RawCookie::build((cookie.name.clone(), cookie.value.clone()))
+            .path(path.to_string())
+            .secure(cookie.secure)
+            .http_only(cookie.http_only);
+        if !cookie.host_only {
+            raw = raw.domain(domain.to_string());
+        }
+        if let Some(expires) = cookie.expires
+            && let Ok(at) = OffsetDateTime::from_unix_timestamp(expires.timestamp())
+        {
+            raw = raw.expires(at);
+        }
+        self.write().insert_raw(&raw.build(), &url).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().iter_unexpired().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.write().clear();
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, CookieStore> {
+        self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, CookieStore> {
+        self.0.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers.filter_map(|value| {
+            let value = value.to_str().ok()?;
+            RawCookie::parse(value).ok().map(RawCookie::into_owned)
+        });
+        self.write().store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let header = self
+            .read()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if header.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&header).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore as _;
+
+    fn request_cookies(jar: &CookieJar, url: &str) -> Option<String> {
+        jar.cookies(&Url::parse(url).unwrap())
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_set_cookie_round_trip() {
+        let jar = CookieJar::new();
+        let url = Url::parse("https://app.example.com/login").unwrap();
+        let headers = [
+            HeaderValue::from_static("sid=abc; Path=/; Secure; HttpOnly"),
+            HeaderValue::from_static("theme=dark; Domain=example.com; Max-Age=3600"),
+            HeaderValue::from_static("old=1; Max-Age=0"),
+        ];
+        jar.set_cookies(&mut headers.iter(), &url);
+
+        let mut stored = jar.list();
+        stored.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(stored.len(), 2);
+        assert!(stored[0].host_only && stored[0].secure && stored[0].expires.is_none());
+        assert_eq!(stored[1].domain, "example.com");
+        assert!(!stored[1].host_only && stored[1].expires.is_some());
+
+        // Reload into a fresh jar, as a session does
+        let reloaded = CookieJar::new();
+        for cookie in &stored {
+            assert!(reloaded.insert(cookie));
+        }
+        let sent = request_cookies(&reloaded, "https://app.example.com/").unwrap();
+        assert!(sent.contains("sid=abc") && sent.contains("theme=dark"));
+        assert_eq!(
+            request_cookies(&reloaded, "http://www.example.com/"),
+            Some("theme=dark".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expired_cookie_rejected() {
+        let jar = CookieJar::new();
+        let expired = StoredCookie {
+            name: "gone".to_string(),
+            value: "1".to_string(),
+            domain: "example.com".to_string(),
+            host_only: false,
+            path: "/".to_string(),
+            secure: false,
+            http_only: false,
+            expires: Some(Utc::now() - chrono::Duration::hours(1)),
+        };
+        assert!(!jar.insert(&expired));
+        assert!(jar.is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+// Named persistent sessions: a cookie jar plus default request headers.
+//
+// Each session is one JSON file, `<name>.json`, under the gurl data
+// directory (e.g. `~/.local/share/gurl/sessions`). Expired cookies are
+// dropped when a session is loaded and when it is saved.
+
+mod jar;
+pub mod netscape;
+
+pub use jar::{CookieJar, StoredCookie};
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A session in use. Clones share the same cookie jar, so a session
+/// attached to a client sees the cookies its responses set.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub name: String,
+    /// Sent with every request unless the request sets the header itself
+    pub headers: BTreeMap<String, String>,
+    pub cookies: Arc<CookieJar>,
+    /// HAR archive every request made with the session is recorded into
+    pub har: Option<PathBuf>,
+    pub created: DateTime<Utc>,
+}
+
+/// The on-disk form of a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionData {
+    pub name: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub cookies: Vec<StoredCookie>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub har: Option<PathBuf>,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+/// One line of `gurl session list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub name: String,
+    pub cookies: usize,
+    pub headers: usize,
+    pub updated: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            headers: BTreeMap::new(),
+            cookies: Arc::new(CookieJar::new()),
+            har: None,
+            created: Utc::now(),
+        }
+    }
+
+    /// Remember a request header for later requests. Headers that only make
+    /// sense for a single request (body metadata, conditionals), cookies,
+    /// which live in the jar, and credentials are ignored: session headers
+    /// go to every host, so a token given for one API would leak to all.
+    /// Returns whether the header was kept.
+    pub fn remember_header(&mut self, name: &str, value: &str) -> bool {
+        let lower = name.to_ascii_lowercase();
+        if lower.starts_with("content-")
+            || lower.starts_with("if-")
+            || lower == "cookie"
+            || is_credential_header(&lower)
+        {
+            return false;
+        }
+        self.headers.insert(lower, value.to_string());
+        true
+    }
+
+    pub fn header_map(&self) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                map.insert(name, value);
+            }
+        }
+        map
+    }
+
+    pub fn data(&self) -> SessionData {
+        SessionData {
+            name: self.name.clone(),
+            headers: self.headers.clone(),
+            cookies: self.cookies.list(),
+            har: self.har.clone(),
+            created: self.created,
+            updated: Utc::now(),
+        }
+    }
+
+    pub fn from_data(data: SessionData) -> Self {
+        let cookies = CookieJar::new();
+        for cookie in &data.cookies {
+            cookies.insert(cookie);
+        }
+        Self {
+            name: data.name,
+            headers: data.headers,
+            cookies: Arc::new(cookies),
+            har: data.har,
+            created: data.created,
+        }
+    }
+}
+
+/// Directory of saved sessions.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    /// Open (creating if needed) a session store rooted at `dir`.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Open the store in the user's data directory (e.g. `~/.local/share/gurl/sessions`).
+    pub fn open_default() -> io::Result<Self> {
+        let dir = Self::default_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+        Self::open(dir)
+    }
+
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|d| d.join("gurl").join("sessions"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saved sessions, sorted by name.
+    pub fn list(&self) -> io::Result<Vec<SessionSummary>> {
+        let mut sessions = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            // Skip files that are not sessions rather than failing the listing
+            let Ok(data) = read_data(&path) else { continue };
+            let now = Utc::now();
+            sessions.push(SessionSummary {
+                cookies: data
+                    .cookies
+                    .iter()
+                    .filter(|c| c.expires.is_none_or(|at| at > now))
+                    .count(),
+                name: data.name,
+                headers: data.headers.len(),
+                updated: data.updated,
+            });
+        }
+        sessions.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(sessions)
+    }
+
+    /// Load a saved session, or `None` if there is no session by that name.
+    pub fn load(&self, name: &str) -> io::Result<Option<Session>> {
+        match read_data(&self.path(name)?) {
+            Ok(data) => Ok(Some(Session::from_data(data))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Load a saved session, starting a new one if it does not exist.
+    pub fn load_or_new(&self, name: &str) -> io::Result<Session> {
+        Ok(self.load(name)?.unwrap_or_else(|| Session::new(name)))
+    }
+
+    pub fn save(&self, session: &Session) -> io::Result<()> {
+        let path = self.path(&session.name)?;
+        let json = serde_json::to_vec_pretty(&session.data())?;
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        // Cookies are credentials too
+        crate::write_private(&tmp, &json)?;
+        std::fs::rename(&tmp, &path)
+    }
+
+    /// Delete a saved session. Returns false if it did not exist.
+    pub fn remove(&self, name: &str) -> io::Result<bool> {
+        match std::fs::remove_file(self.path(name)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn path(&self, name: &str) -> io::Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid session name '{name}' (use letters, digits, '-', '_' and '.')"),
+            ));
+        }
+        Ok(self.dir.join(format!("{name}.json")))
+    }
+}
+
+/// Whether a header carries a secret: `Authorization`, `Proxy-Authorization`
+/// or one of the usual API key and token headers (`X-API-Key`, `X-Auth-Token`, ...).
+pub fn is_credential_header(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    lower == "authorization"
+        || lower == "proxy-authorization"
+        || ["api-key", "apikey", "token", "secret", "password"]
+            .iter()
+            .any(|word| lower.contains(word))
+}
+
+fn read_data(path: &Path) -> io::Result<SessionData> {
+    let json = std::fs::read(path)?;
+    serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> SessionStore {
+        let dir = std::env::temp_dir().join(format!("gurl-sessions-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        SessionStore::open(dir).unwrap()
+    }
+
+    #[test]
+    fn test_save_load_and_remove() {
+        let store = temp_store("roundtrip");
+        let mut session = store.load_or_new("github").unwrap();
+        session.remember_header("Accept-Language", "fr");
+        session.remember_header("Content-Type", "application/json");
+        session.cookies.insert(&StoredCookie {
+            name: "sid".to_string(),
+            value: "abc".to_string(),
+            domain: "github.com".to_string(),
+            host_only: true,
+            path: "/".to_string(),
+            secure: true,
+            http_only: true,
+            expires: None,
+        });
+        store.save(&session).unwrap();
+
+        let loaded = store.load("github").unwrap().unwrap();
+        assert_eq!(loaded.headers.len(), 1);
+        assert_eq!(loaded.header_map()["accept-language"], "fr");
+        assert_eq!(loaded.cookies.list()[0].value, "abc");
+
+        let list = store.list().unwrap();
+        assert_eq!((list.len(), list[0].cookies), (1, 1));
+
+        assert!(store.remove("github").unwrap());
+        assert!(store.load("github").unwrap().is_none());
+        assert!(store.load("../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_credential_headers_are_not_remembered() {
+        let mut session = Session::new("api");
+        assert!(!session.remember_header("Authorization", "Bearer t"));
+        assert!(!session.remember_header("Proxy-Authorization", "Basic eDp5"));
+        assert!(!session.remember_header("X-API-Key", "k"));
+        assert!(!session.remember_header("X-Auth-Token", "t"));
+        assert!(session.remember_header("X-Request-Source", "cli"));
+        assert_eq!(
+            session.headers.keys().collect::<Vec<_>>(),
+            ["x-request-source"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_session_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let store = temp_store("private");
+        store.save(&Session::new("mine")).unwrap();
+        let mode = std::fs::metadata(store.path("mine").unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        store.remove("mine").unwrap();
+    }
+}
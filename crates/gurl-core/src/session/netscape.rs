@@ -0,0 +1,147 @@
+// Netscape cookies.txt format, as exported by browser extensions and
+// written by curl (`-c`) and wget.
+//
+// One cookie per line, seven tab-separated fields:
+//
+//     domain  include_subdomains  path  secure  expires  name  value
+//
+// `expires` is a Unix timestamp, 0 for session cookies. Lines starting with
+// `#` are comments, except the `#HttpOnly_` prefix curl puts on the domain
+// of HttpOnly cookies.
+
+use super::{CookieJar, StoredCookie};
+use chrono::DateTime;
+
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// Parse cookies.txt content. Malformed lines are skipped, as curl does.
+pub fn parse(text: &str) -> Vec<StoredCookie> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<StoredCookie> {
+    let line = line.trim_end_matches('\r');
+    let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+        Some(rest) => (rest, true),
+        None if line.starts_with('#') => return None,
+        None => (line, false),
+    };
+    let fields: Vec<&str> = line.splitn(7, '\t').collect();
+    let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+        return None;
+    };
+    if domain.is_empty() || name.is_empty() {
+        return None;
+    }
+    let expires = match expires.parse::<i64>().ok()? {
+        0 => None,
+        at => Some(DateTime::from_timestamp(at, 0)?),
+    };
+    Some(StoredCookie {
+        name: name.to_string(),
+        value: value.to_string(),
+        domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+        host_only: !subdomains.eq_ignore_ascii_case("TRUE"),
+        path: path.to_string(),
+        secure: secure.eq_ignore_ascii_case("TRUE"),
+        http_only,
+        expires,
+    })
+}
+
+/// Render cookies in cookies.txt format.
+pub fn write(cookies: &[StoredCookie]) -> String {
+    let mut out = String::from("# Netscape HTTP Cookie File\n# Written by gurl\n\n");
+    for cookie in cookies {
+        let bool_field = |b: bool| if b { "TRUE" } else { "FALSE" };
+        let prefix = if cookie.http_only {
+            HTTP_ONLY_PREFIX
+        } else {
+            ""
+        };
+        let dot = if cookie.host_only { "" } else { "." };
+        out.push_str(&format!(
+            "{prefix}{dot}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            cookie.domain,
+            bool_field(!cookie.host_only),
+            cookie.path,
+            bool_field(cookie.secure),
+            cookie.expires.map_or(0, |at| at.timestamp()),
+            cookie.name,
+            cookie.value,
+        ));
+    }
+    out
+}
+
+impl CookieJar {
+    /// Add the cookies from cookies.txt content, returning how many were
+    /// accepted (expired ones are not).
+    pub fn import_netscape(&self, text: &str) -> usize {
+        parse(text)
+            .iter()
+            .filter(|cookie| self.insert(cookie))
+            .count()
+    }
+
+    /// The jar's unexpired cookies in cookies.txt format.
+    pub fn to_netscape(&self) -> String {
+        write(&self.list())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore;
+    use url::Url;
+
+    const COOKIES_TXT: &str = "\
+# Netscape HTTP Cookie File
+.example.com\tTRUE\t/\tFALSE\t0\ttheme\tdark
+#HttpOnly_sso.example.com\tFALSE\t/\tTRUE\t4102444800\tsid\tabc123
+sso.example.com\tFALSE\t/admin\tTRUE\t4102444800\tadmin\t1
+old.example.com\tFALSE\t/\tFALSE\t946684800\texpired\t1
+not a cookie line
+";
+
+    fn sent(jar: &CookieJar, url: &str) -> String {
+        jar.cookies(&Url::parse(url).unwrap())
+            .map(|v| v.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_parse() {
+        let cookies = parse(COOKIES_TXT);
+        assert_eq!(cookies.len(), 4);
+        assert!(!cookies[0].host_only && cookies[0].expires.is_none());
+        assert!(cookies[1].http_only && cookies[1].secure && cookies[1].host_only);
+        assert_eq!(cookies[1].expires.unwrap().timestamp(), 4102444800);
+    }
+
+    #[test]
+    fn test_import_matches_domain_path_and_secure() {
+        let jar = CookieJar::new();
+        assert_eq!(jar.import_netscape(COOKIES_TXT), 3);
+
+        assert_eq!(sent(&jar, "https://www.example.com/"), "theme=dark");
+        let sso = sent(&jar, "https://sso.example.com/");
+        assert!(sso.contains("sid=abc123") && !sso.contains("admin"));
+        assert!(sent(&jar, "https://sso.example.com/admin/users").contains("admin=1"));
+        // Secure cookies stay off plain HTTP
+        assert_eq!(sent(&jar, "http://sso.example.com/"), "theme=dark");
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let jar = CookieJar::new();
+        jar.import_netscape(COOKIES_TXT);
+        let mut written = parse(&jar.to_netscape());
+        let mut original = parse(COOKIES_TXT);
+        original.retain(|c| c.name != "expired");
+        written.sort_by(|a, b| a.name.cmp(&b.name));
+        original.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(written, original);
+    }
+}
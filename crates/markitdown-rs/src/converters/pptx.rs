@@ -112,10 +112,8 @@ fn parse_slide_xml(xml: &str) -> String {
                     "p" if in_table => {
                         // paragraph inside table cell
                     }
-                    "p" => {
-                        if !current_paragraph.is_empty() {
-                            paragraphs.push(std::mem::take(&mut current_paragraph));
-                        }
+                    "p" if !current_paragraph.is_empty() => {
+                        paragraphs.push(std::mem::take(&mut current_paragraph));
                     }
                     "tbl" => in_table = true,
                     "tr" => current_row.clear(),
@@ -123,14 +121,12 @@ fn parse_slide_xml(xml: &str) -> String {
                     _ => {}
                 }
             }
-            Ok(Event::Text(e)) => {
-                if in_text {
-                    let text = e.unescape().unwrap_or_default().to_string();
-                    if in_table {
-                        current_cell.push_str(&text);
-                    } else {
-                        current_paragraph.push_str(&text);
-                    }
+            Ok(Event::Text(e)) if in_text => {
+                let text = e.unescape().unwrap_or_default().to_string();
+                if in_table {
+                    current_cell.push_str(&text);
+                } else {
+                    current_paragraph.push_str(&text);
                 }
             }
             Ok(Event::End(ref e)) => {
@@ -141,10 +137,8 @@ fn parse_slide_xml(xml: &str) -> String {
                     "tc" => {
                         current_row.push(std::mem::take(&mut current_cell));
                     }
-                    "tr" => {
-                        if !current_row.is_empty() {
-                            table_rows.push(std::mem::take(&mut current_row));
-                        }
+                    "tr" if !current_row.is_empty() => {
+                        table_rows.push(std::mem::take(&mut current_row));
                     }
                     "tbl" => {
                         in_table = false;